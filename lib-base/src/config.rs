@@ -15,7 +15,12 @@
 
 use anyhow::{Context, Result};
 use crate::ButtonAction;
-use mutenix_hid::LedColor;
+use mutenix_hid::{
+    HardwareType, LedAnimation, LedColor, LedPattern, PressClassifier, PressMode, WatchdogConfig,
+    DEFAULT_DEGRADED_AFTER_MISSES, DEFAULT_ERROR_AFTER_MISSES, DEFAULT_LONGPRESS_THRESHOLD,
+    DEFAULT_MAX_RECONNECT_DELAY_MS, DEFAULT_RECONNECT_DELAY_MS, PING_LOOP_TIME_SECONDS,
+};
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -30,23 +35,55 @@ pub struct Config {
     #[serde(default)]
     pub longpress_action: Vec<ButtonAction>,
     pub led_status: Vec<LedStatus>,
+    /// Brightness scale applied to every LED; 1.0 keeps colors unchanged
+    #[serde(default = "default_brightness")]
+    pub led_brightness: f32,
     #[serde(default)]
     pub logging: LoggingConfig,
     #[serde(default)]
     pub virtual_keypad: VirtualKeypadConfig,
+    #[serde(default)]
+    pub button_press: ButtonPressConfig,
+    #[serde(default)]
+    pub watchdog: ConnectionWatchdogConfig,
 }
 
-/// Device identification (vendor ID, product ID)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Rule selecting a device. Every criterion given has to match; a zero or
+/// missing vendor or product ID matches any
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeviceIdentification {
+    #[serde(default)]
     pub vendor_id: u16,
+    #[serde(default)]
     pub product_id: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    /// Pattern for the product string, `*` and `?` are wildcards, case is ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_page: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface_number: Option<i32>,
+    /// Hardware type the device has to report after connecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hardware_type: Option<HardwareType>,
+    /// Rules with a higher priority are preferred
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+}
+
+fn is_zero(value: &i32) -> bool {
+    *value == 0
 }
 
 /// LED status configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LedStatus {
     pub button_id: u8,
+    /// Restrict the LED to the device with this serial number; applies to all devices if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub teams_state: Option<TeamsStateConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -55,6 +92,9 @@ pub struct LedStatus {
     pub color_command: Option<LedStatusColorCommand>,
     #[serde(default)]
     pub webhook: bool,
+    /// Brightness scale for this LED, multiplied with the global `led_brightness`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f32>,
 }
 
 /// LED status result command configuration
@@ -81,6 +121,10 @@ pub struct LedStatusColorCommand {
     pub timeout: f64,
 }
 
+fn default_brightness() -> f32 {
+    1.0
+}
+
 fn default_interval() -> f64 {
     5.0
 }
@@ -97,6 +141,87 @@ pub struct TeamsStateConfig {
     pub color_on: Option<LedColorConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color_off: Option<LedColorConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_on: Option<LedPatternConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_off: Option<LedPatternConfig>,
+}
+
+impl TeamsStateConfig {
+    /// Animation for the LED depending on whether the Teams state is active;
+    /// `default_on` is the color of an active state without `color_on`
+    pub fn animation(&self, active: bool, default_on: LedColor) -> LedAnimation {
+        let (color, default_color, pattern) = if active {
+            (&self.color_on, default_on, &self.pattern_on)
+        } else {
+            (&self.color_off, LedColor::Black, &self.pattern_off)
+        };
+        LedAnimation::new(
+            color.as_ref().map_or(default_color, |c| c.to_led_color()),
+            pattern.as_ref().map_or(LedPattern::Solid, |p| p.to_led_pattern()),
+        )
+    }
+}
+
+/// LED animation pattern configuration; periods and durations are in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum LedPatternConfig {
+    Solid,
+    Blink {
+        #[serde(default = "default_blink_period")]
+        period: f64,
+    },
+    Pulse {
+        #[serde(default = "default_pulse_period")]
+        period: f64,
+    },
+    Breathe {
+        #[serde(default = "default_breathe_period")]
+        period: f64,
+    },
+    Alternate {
+        color: LedColorConfig,
+        #[serde(default = "default_blink_period")]
+        period: f64,
+    },
+    Flash {
+        #[serde(default = "default_flash_duration")]
+        duration: f64,
+    },
+}
+
+impl LedPatternConfig {
+    pub fn to_led_pattern(&self) -> LedPattern {
+        let seconds = |value: f64| std::time::Duration::from_secs_f64(value.max(0.0));
+        match self {
+            LedPatternConfig::Solid => LedPattern::Solid,
+            LedPatternConfig::Blink { period } => LedPattern::Blink { period: seconds(*period) },
+            LedPatternConfig::Pulse { period } => LedPattern::Pulse { period: seconds(*period) },
+            LedPatternConfig::Breathe { period } => LedPattern::Breathe { period: seconds(*period) },
+            LedPatternConfig::Alternate { color, period } => LedPattern::Alternate {
+                other: color.to_led_color(),
+                period: seconds(*period),
+            },
+            LedPatternConfig::Flash { duration } => LedPattern::Flash { duration: seconds(*duration) },
+        }
+    }
+}
+
+fn default_blink_period() -> f64 {
+    1.0
+}
+
+fn default_pulse_period() -> f64 {
+    2.0
+}
+
+fn default_breathe_period() -> f64 {
+    4.0
+}
+
+fn default_flash_duration() -> f64 {
+    0.5
 }
 
 /// Teams state types
@@ -114,8 +239,12 @@ pub enum TeamsStateType {
 }
 
 /// LED color configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+///
+/// Accepts a color name (`green`), a hex string (`"#ff8800"` or `"#ff880010"`
+/// including white) or explicit channels (`{r: 255, g: 136, b: 0, w: 0}`).
+/// Custom colors are written back as hex strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "LedColorRepr", into = "LedColorRepr")]
 pub enum LedColorConfig {
     Black,
     Red,
@@ -127,6 +256,21 @@ pub enum LedColorConfig {
     White,
     Orange,
     Purple,
+    Rgbw { r: u8, g: u8, b: u8, w: u8 },
+}
+
+/// Serialized forms of `LedColorConfig`
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LedColorRepr {
+    Text(String),
+    Channels {
+        r: u8,
+        g: u8,
+        b: u8,
+        #[serde(default)]
+        w: u8,
+    },
 }
 
 impl LedColorConfig {
@@ -142,10 +286,91 @@ impl LedColorConfig {
             LedColorConfig::White => LedColor::White,
             LedColorConfig::Orange => LedColor::Orange,
             LedColorConfig::Purple => LedColor::Purple,
+            LedColorConfig::Rgbw { r, g, b, w } => LedColor::rgbw(*r, *g, *b, *w),
+        }
+    }
+
+    /// Parse a `#rrggbb` or `#rrggbbww` hex string
+    fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        let valid_length = digits.len() == 6 || digits.len() == 8;
+        if !valid_length || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| {
+            digits
+                .get(i * 2..i * 2 + 2)
+                .map_or(Some(0), |pair| u8::from_str_radix(pair, 16).ok())
+        };
+        Some(LedColorConfig::Rgbw {
+            r: channel(0)?,
+            g: channel(1)?,
+            b: channel(2)?,
+            w: channel(3)?,
+        })
+    }
+}
+
+impl std::str::FromStr for LedColorConfig {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "black" => Ok(LedColorConfig::Black),
+            "red" => Ok(LedColorConfig::Red),
+            "green" => Ok(LedColorConfig::Green),
+            "blue" => Ok(LedColorConfig::Blue),
+            "yellow" => Ok(LedColorConfig::Yellow),
+            "cyan" => Ok(LedColorConfig::Cyan),
+            "magenta" => Ok(LedColorConfig::Magenta),
+            "white" => Ok(LedColorConfig::White),
+            "orange" => Ok(LedColorConfig::Orange),
+            "purple" => Ok(LedColorConfig::Purple),
+            _ => Self::from_hex(value).ok_or_else(|| {
+                format!("invalid LED color '{}': expected a color name or #rrggbb[ww]", value)
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for LedColorConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedColorConfig::Black => write!(f, "black"),
+            LedColorConfig::Red => write!(f, "red"),
+            LedColorConfig::Green => write!(f, "green"),
+            LedColorConfig::Blue => write!(f, "blue"),
+            LedColorConfig::Yellow => write!(f, "yellow"),
+            LedColorConfig::Cyan => write!(f, "cyan"),
+            LedColorConfig::Magenta => write!(f, "magenta"),
+            LedColorConfig::White => write!(f, "white"),
+            LedColorConfig::Orange => write!(f, "orange"),
+            LedColorConfig::Purple => write!(f, "purple"),
+            LedColorConfig::Rgbw { r, g, b, w: 0 } => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            LedColorConfig::Rgbw { r, g, b, w } => {
+                write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, w)
+            }
+        }
+    }
+}
+
+impl TryFrom<LedColorRepr> for LedColorConfig {
+    type Error = String;
+
+    fn try_from(repr: LedColorRepr) -> std::result::Result<Self, Self::Error> {
+        match repr {
+            LedColorRepr::Text(text) => text.parse(),
+            LedColorRepr::Channels { r, g, b, w } => Ok(LedColorConfig::Rgbw { r, g, b, w }),
         }
     }
 }
 
+impl From<LedColorConfig> for LedColorRepr {
+    fn from(color: LedColorConfig) -> Self {
+        LedColorRepr::Text(color.to_string())
+    }
+}
+
 /// Logging configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
@@ -224,6 +449,100 @@ fn default_bind_port() -> u16 {
     12909
 }
 
+/// Classification of button presses into short and long presses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ButtonPressConfig {
+    /// Whether the firmware, the host or both decide on long presses
+    #[serde(default)]
+    pub mode: PressMode,
+    /// Press duration from which the host counts a long press
+    #[serde(default = "default_longpress_threshold_ms")]
+    pub longpress_threshold_ms: u64,
+}
+
+impl ButtonPressConfig {
+    /// Classifier for the configured mode and threshold
+    pub fn classifier(&self) -> PressClassifier {
+        PressClassifier::new(self.mode, Duration::from_millis(self.longpress_threshold_ms))
+    }
+}
+
+impl Default for ButtonPressConfig {
+    fn default() -> Self {
+        Self {
+            mode: PressMode::default(),
+            longpress_threshold_ms: default_longpress_threshold_ms(),
+        }
+    }
+}
+
+fn default_longpress_threshold_ms() -> u64 {
+    DEFAULT_LONGPRESS_THRESHOLD.as_millis() as u64
+}
+
+/// Monitoring of the device connections with pings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionWatchdogConfig {
+    #[serde(default = "default_ping_interval_ms")]
+    pub ping_interval_ms: u64,
+    /// Consecutive missed pings after which a connection counts as degraded
+    #[serde(default = "default_degraded_after")]
+    pub degraded_after: u32,
+    /// Consecutive missed pings after which a connection is opened again
+    #[serde(default = "default_error_after")]
+    pub error_after: u32,
+    #[serde(default = "default_reconnect_delay_ms")]
+    pub reconnect_delay_ms: u64,
+    #[serde(default = "default_max_reconnect_delay_ms")]
+    pub max_reconnect_delay_ms: u64,
+}
+
+impl ConnectionWatchdogConfig {
+    /// Watchdog settings for `HidDevice` and `DeviceManager`
+    pub fn to_watchdog(&self) -> WatchdogConfig {
+        WatchdogConfig::new()
+            .with_ping_interval(Duration::from_millis(self.ping_interval_ms))
+            .with_degraded_after(self.degraded_after)
+            .with_error_after(self.error_after)
+            .with_reconnect_delay(
+                Duration::from_millis(self.reconnect_delay_ms),
+                Duration::from_millis(self.max_reconnect_delay_ms),
+            )
+    }
+}
+
+impl Default for ConnectionWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_ms: default_ping_interval_ms(),
+            degraded_after: default_degraded_after(),
+            error_after: default_error_after(),
+            reconnect_delay_ms: default_reconnect_delay_ms(),
+            max_reconnect_delay_ms: default_max_reconnect_delay_ms(),
+        }
+    }
+}
+
+fn default_ping_interval_ms() -> u64 {
+    PING_LOOP_TIME_SECONDS * 1000
+}
+
+fn default_degraded_after() -> u32 {
+    DEFAULT_DEGRADED_AFTER_MISSES
+}
+
+fn default_error_after() -> u32 {
+    DEFAULT_ERROR_AFTER_MISSES
+}
+
+fn default_reconnect_delay_ms() -> u64 {
+    DEFAULT_RECONNECT_DELAY_MS
+}
+
+fn default_max_reconnect_delay_ms() -> u64 {
+    DEFAULT_MAX_RECONNECT_DELAY_MS
+}
+
 impl Config {
     /// Load configuration from a YAML file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
@@ -239,6 +558,7 @@ impl Config {
     /// 1. Specified path (if provided)
     /// 2. Current directory
     /// 3. User's config directory (~/.config/mutenix/ on Linux/macOS, %APPDATA%\mutenix\ on Windows)
+    ///
     /// If no config file is found, creates a default one in the user's config directory
     pub fn load() -> Result<Self> {
         Self::load_with_name("mutenix.yaml")
@@ -293,20 +613,11 @@ impl Config {
 
     /// Get user's configuration directory
     fn get_user_config_dir() -> Result<std::path::PathBuf> {
-        let config_dir = if cfg!(target_os = "macos") {
-            dirs::config_dir()
-                .context("Failed to get config directory")?
-                .join("mutenix")
-        } else if cfg!(target_os = "windows") {
-            dirs::config_dir()
-                .context("Failed to get config directory")?
-                .join("mutenix")
-        } else {
-            // Linux and other Unix-like systems
-            dirs::config_dir()
-                .context("Failed to get config directory")?
-                .join("mutenix")
-        };
+        // dirs resolves the platform specific location (Library/Application Support,
+        // %APPDATA% or ~/.config)
+        let config_dir = dirs::config_dir()
+            .context("Failed to get config directory")?
+            .join("mutenix");
         
         Ok(config_dir)
     }
@@ -321,20 +632,24 @@ impl Config {
                 DeviceIdentification {
                     vendor_id: 7504,
                     product_id: 24969,
+                    ..Default::default()
                 },
                 DeviceIdentification {
                     vendor_id: 7504,
                     product_id: 24774,
+                    ..Default::default()
                 },
                 DeviceIdentification {
                     vendor_id: 4617,
                     product_id: 1,
+                    ..Default::default()
                 },
             ],
             actions: vec![
                 // Button 1: Toggle mute
                 ButtonAction {
                     button_id: 1,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -348,6 +663,7 @@ impl Config {
                 // Button 2: Toggle hand
                 ButtonAction {
                     button_id: 2,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -361,6 +677,7 @@ impl Config {
                 // Button 3: Activate Teams
                 ButtonAction {
                     button_id: 3,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -374,6 +691,7 @@ impl Config {
                 // Button 4: Like reaction
                 ButtonAction {
                     button_id: 4,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -389,6 +707,7 @@ impl Config {
                 // Button 5: Leave call
                 ButtonAction {
                     button_id: 5,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -404,6 +723,7 @@ impl Config {
                 // Button 3 long press: Toggle video
                 ButtonAction {
                     button_id: 3,
+                    serial_number: None,
                     actions: vec![Action {
                         webhook: None,
                         keyboard: None,
@@ -419,65 +739,96 @@ impl Config {
                 // Button 1 LED: Mute status (green when muted, red when unmuted)
                 LedStatus {
                     button_id: 1,
+                    serial_number: None,
                     teams_state: Some(TeamsStateConfig {
                         teams_state: TeamsStateType::IsMuted,
                         color_on: Some(LedColorConfig::Green),
                         color_off: Some(LedColorConfig::Red),
+                        pattern_on: None,
+                        pattern_off: None,
                     }),
                     result_command: None,
                     color_command: None,
                     webhook: false,
+                    brightness: None,
                 },
                 // Button 2 LED: Hand raised status (yellow when raised, off when not)
                 LedStatus {
                     button_id: 2,
+                    serial_number: None,
                     teams_state: Some(TeamsStateConfig {
                         teams_state: TeamsStateType::IsHandRaised,
                         color_on: Some(LedColorConfig::Yellow),
                         color_off: Some(LedColorConfig::Black),
+                        pattern_on: None,
+                        pattern_off: None,
                     }),
                     result_command: None,
                     color_command: None,
                     webhook: false,
+                    brightness: None,
                 },
                 // Button 3 LED: Video status (green when on, red when off)
                 LedStatus {
                     button_id: 3,
+                    serial_number: None,
                     teams_state: Some(TeamsStateConfig {
                         teams_state: TeamsStateType::IsVideoOn,
                         color_on: Some(LedColorConfig::Green),
                         color_off: Some(LedColorConfig::Red),
+                        pattern_on: None,
+                        pattern_off: None,
                     }),
                     result_command: None,
                     color_command: None,
                     webhook: false,
+                    brightness: None,
                 },
                 // Button 5 LED: In meeting status (green when in meeting, off when not)
                 LedStatus {
                     button_id: 5,
+                    serial_number: None,
                     teams_state: Some(TeamsStateConfig {
                         teams_state: TeamsStateType::IsInMeeting,
                         color_on: Some(LedColorConfig::Green),
                         color_off: Some(LedColorConfig::Black),
+                        pattern_on: None,
+                        pattern_off: None,
                     }),
                     result_command: None,
                     color_command: None,
                     webhook: false,
+                    brightness: None,
                 },
             ],
+            led_brightness: default_brightness(),
             logging: LoggingConfig::default(),
             virtual_keypad: VirtualKeypadConfig::default(),
+            button_press: ButtonPressConfig::default(),
+            watchdog: ConnectionWatchdogConfig::default(),
         }
     }
 
-    /// Find button action by button ID
-    pub fn find_button_action(&self, button_id: u8) -> Option<&ButtonAction> {
-        self.actions.iter().find(|a| a.button_id == button_id)
+    /// Find button action by button ID for the given device.
+    /// Entries scoped to the device's serial number take precedence over unscoped ones.
+    pub fn find_button_action(&self, button_id: u8, serial_number: Option<&str>) -> Option<&ButtonAction> {
+        Self::find_scoped(&self.actions, button_id, serial_number)
     }
 
-    /// Find longpress action by button ID
-    pub fn find_longpress_action(&self, button_id: u8) -> Option<&ButtonAction> {
-        self.longpress_action.iter().find(|a| a.button_id == button_id)
+    /// Find longpress action by button ID for the given device
+    pub fn find_longpress_action(&self, button_id: u8, serial_number: Option<&str>) -> Option<&ButtonAction> {
+        Self::find_scoped(&self.longpress_action, button_id, serial_number)
+    }
+
+    fn find_scoped<'a>(
+        actions: &'a [ButtonAction],
+        button_id: u8,
+        serial_number: Option<&str>,
+    ) -> Option<&'a ButtonAction> {
+        let candidates = || actions.iter().filter(|a| a.button_id == button_id);
+        candidates()
+            .find(|a| a.serial_number.is_some() && a.serial_number.as_deref() == serial_number)
+            .or_else(|| candidates().find(|a| a.serial_number.is_none()))
     }
 
     /// Find LED status by button ID
@@ -486,6 +837,28 @@ impl Config {
         self.led_status.iter().find(|l| l.button_id == button_id)
     }
 
+    /// LED status entries that apply to the given device.
+    /// An entry scoped to the device replaces unscoped entries for the same button.
+    pub fn led_status_for(&self, serial_number: Option<&str>) -> Vec<&LedStatus> {
+        let scoped: Vec<&LedStatus> = self
+            .led_status
+            .iter()
+            .filter(|l| l.serial_number.is_some() && l.serial_number.as_deref() == serial_number)
+            .collect();
+
+        self.led_status
+            .iter()
+            .filter(|l| l.serial_number.is_none())
+            .filter(|l| !scoped.iter().any(|s| s.button_id == l.button_id))
+            .chain(scoped.iter().copied())
+            .collect()
+    }
+
+    /// Effective brightness of an LED: the global scale times the LED's own scale
+    pub fn led_brightness_for(&self, led_status: &LedStatus) -> f32 {
+        self.led_brightness * led_status.brightness.unwrap_or(1.0)
+    }
+
     /// Get device info for HID connection
     pub fn get_device_info(&self) -> Vec<mutenix_hid::DeviceInfo> {
         self.device_identifications
@@ -493,7 +866,13 @@ impl Config {
             .map(|d| mutenix_hid::DeviceInfo {
                 vendor_id: d.vendor_id,
                 product_id: d.product_id,
-                serial_number: None,
+                serial_number: d.serial_number.clone(),
+                path: None,
+                product_pattern: d.product.clone(),
+                usage_page: d.usage_page,
+                interface_number: d.interface_number,
+                hardware_type: d.hardware_type,
+                priority: d.priority,
             })
             .collect()
     }
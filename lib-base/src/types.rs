@@ -8,6 +8,9 @@ use teams_api::{ClientMessageParameterType, MeetingAction};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ButtonAction {
     pub button_id: u8,
+    /// Restrict the action to the device with this serial number; applies to all devices if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
     pub actions: Vec<Action>,
 }
 
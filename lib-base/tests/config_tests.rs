@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use lib_base::*;
+use mutenix_hid::{HardwareType, LedColor, LedPattern, PressMode};
+use std::time::Duration;
+
+/// Config from the given YAML; required lists it leaves out are empty
+fn parse(extra: &str) -> Config {
+    let mut yaml = extra.to_string();
+    for required in ["device_identifications", "actions", "led_status"] {
+        if !extra.lines().any(|line| line.starts_with(&format!("{}:", required))) {
+            yaml.push_str(&format!("{}: []\n", required));
+        }
+    }
+    serde_yaml::from_str(&yaml).unwrap()
+}
+
+fn color(yaml: &str) -> Result<LedColorConfig, serde_yaml::Error> {
+    serde_yaml::from_str(yaml)
+}
+
+#[test]
+fn test_color_names_and_hex_strings() {
+    assert_eq!(color("green").unwrap(), LedColorConfig::Green);
+    assert_eq!(
+        color("'#ff8800'").unwrap(),
+        LedColorConfig::Rgbw { r: 0xff, g: 0x88, b: 0x00, w: 0 }
+    );
+    assert_eq!(
+        color("'#ff880010'").unwrap(),
+        LedColorConfig::Rgbw { r: 0xff, g: 0x88, b: 0x00, w: 0x10 }
+    );
+    assert_eq!(color("'#FF8800'").unwrap().to_led_color(), LedColor::rgbw(0xff, 0x88, 0, 0));
+}
+
+#[test]
+fn test_color_channels() {
+    assert_eq!(
+        color("{r: 1, g: 2, b: 3, w: 4}").unwrap(),
+        LedColorConfig::Rgbw { r: 1, g: 2, b: 3, w: 4 }
+    );
+    // White is optional
+    assert_eq!(
+        color("{r: 1, g: 2, b: 3}").unwrap(),
+        LedColorConfig::Rgbw { r: 1, g: 2, b: 3, w: 0 }
+    );
+}
+
+#[test]
+fn test_invalid_colors_are_rejected() {
+    for invalid in ["pink", "'#ff88'", "'#ff88001'", "'#gg8800'", "'ff8800'", "{r: 256, g: 0, b: 0}"] {
+        assert!(color(invalid).is_err(), "{} should be rejected", invalid);
+    }
+}
+
+#[test]
+fn test_colors_round_trip() {
+    let colors = [
+        LedColorConfig::Purple,
+        LedColorConfig::Rgbw { r: 0xff, g: 0x88, b: 0x00, w: 0 },
+        LedColorConfig::Rgbw { r: 1, g: 2, b: 3, w: 4 },
+    ];
+    for original in colors {
+        let yaml = serde_yaml::to_string(&original).unwrap();
+        assert_eq!(color(&yaml).unwrap(), original);
+    }
+    // Custom colors are written as hex strings
+    let yaml = serde_yaml::to_string(&LedColorConfig::Rgbw { r: 1, g: 2, b: 3, w: 4 }).unwrap();
+    assert_eq!(yaml.trim(), "'#01020304'");
+    assert_eq!(LedColorConfig::Rgbw { r: 0xff, g: 0x88, b: 0, w: 0 }.to_string(), "#ff8800");
+}
+
+#[test]
+fn test_patterns() {
+    let pattern = |yaml: &str| serde_yaml::from_str::<LedPatternConfig>(yaml).unwrap();
+
+    assert_eq!(pattern("type: solid").to_led_pattern(), LedPattern::Solid);
+    assert_eq!(
+        pattern("type: blink").to_led_pattern(),
+        LedPattern::Blink { period: Duration::from_secs(1) }
+    );
+    assert_eq!(
+        pattern("{type: pulse, period: 0.5}").to_led_pattern(),
+        LedPattern::Pulse { period: Duration::from_millis(500) }
+    );
+    assert_eq!(
+        pattern("{type: alternate, color: '#0000ff'}").to_led_pattern(),
+        LedPattern::Alternate { other: LedColor::rgbw(0, 0, 0xff, 0), period: Duration::from_secs(1) }
+    );
+    assert_eq!(
+        pattern("{type: flash, duration: -1}").to_led_pattern(),
+        LedPattern::Flash { duration: Duration::ZERO }
+    );
+    assert!(serde_yaml::from_str::<LedPatternConfig>("type: sparkle").is_err());
+
+    let original = LedPatternConfig::Breathe { period: 3.0 };
+    let yaml = serde_yaml::to_string(&original).unwrap();
+    assert_eq!(serde_yaml::from_str::<LedPatternConfig>(&yaml).unwrap(), original);
+}
+
+#[test]
+fn test_teams_state_animation() {
+    let config = parse(
+        "led_status:\n\
+         - button_id: 1\n  \
+           teams_state:\n    \
+             teams_state: is-muted\n    \
+             color_on: red\n    \
+             pattern_on: {type: blink, period: 2}\n",
+    );
+    let teams_state = config.led_status[0].teams_state.as_ref().unwrap();
+
+    let on = teams_state.animation(true, LedColor::White);
+    assert_eq!(on.color, LedColor::Red);
+    assert_eq!(on.pattern, LedPattern::Blink { period: Duration::from_secs(2) });
+    // Without color and pattern the LED is off
+    let off = teams_state.animation(false, LedColor::White);
+    assert_eq!(off.color, LedColor::Black);
+    assert_eq!(off.pattern, LedPattern::Solid);
+
+    // Without color_on an active state uses the frontend's default color
+    let mut no_color = teams_state.clone();
+    no_color.color_on = None;
+    assert_eq!(no_color.animation(true, LedColor::Green).color, LedColor::Green);
+}
+
+#[test]
+fn test_led_status_for_prefers_serial_scoped_entries() {
+    let config = parse(
+        "led_status:\n\
+         - {button_id: 1, webhook: false}\n\
+         - {button_id: 2, webhook: false}\n\
+         - {button_id: 1, serial_number: LEFT, webhook: true}\n\
+         - {button_id: 3, serial_number: RIGHT, webhook: true}\n",
+    );
+    let buttons = |serial: Option<&str>| {
+        let mut entries: Vec<(u8, Option<String>)> = config
+            .led_status_for(serial)
+            .into_iter()
+            .map(|l| (l.button_id, l.serial_number.clone()))
+            .collect();
+        entries.sort();
+        entries
+    };
+
+    assert_eq!(buttons(Some("LEFT")), vec![(1, Some("LEFT".to_string())), (2, None)]);
+    assert_eq!(
+        buttons(Some("RIGHT")),
+        vec![(1, None), (2, None), (3, Some("RIGHT".to_string()))]
+    );
+    assert_eq!(buttons(None), vec![(1, None), (2, None)]);
+}
+
+#[test]
+fn test_led_brightness_for_scales_global_brightness() {
+    let config = parse(
+        "led_brightness: 0.5\n\
+         led_status:\n\
+         - {button_id: 1, brightness: 0.5}\n\
+         - {button_id: 2}\n",
+    );
+    assert_eq!(config.led_brightness_for(&config.led_status[0]), 0.25);
+    assert_eq!(config.led_brightness_for(&config.led_status[1]), 0.5);
+}
+
+#[test]
+fn test_actions_prefer_serial_scoped_entries() {
+    let config = parse(
+        "actions:\n\
+         - {button_id: 1, actions: [{command: global}]}\n\
+         - {button_id: 1, serial_number: LEFT, actions: [{command: left}]}\n\
+         - {button_id: 2, serial_number: LEFT, actions: [{command: left-only}]}\n\
+         longpress_action:\n\
+         - {button_id: 3, serial_number: RIGHT, actions: [{command: right-long}]}\n",
+    );
+    let command = |action: Option<&ButtonAction>| action.and_then(|a| a.actions[0].command.clone());
+
+    assert_eq!(command(config.find_button_action(1, Some("LEFT"))).as_deref(), Some("left"));
+    assert_eq!(command(config.find_button_action(1, Some("RIGHT"))).as_deref(), Some("global"));
+    assert_eq!(command(config.find_button_action(1, None)).as_deref(), Some("global"));
+    assert!(config.find_button_action(2, Some("RIGHT")).is_none());
+    assert!(config.find_button_action(2, None).is_none());
+    assert_eq!(
+        command(config.find_longpress_action(3, Some("RIGHT"))).as_deref(),
+        Some("right-long")
+    );
+    assert!(config.find_longpress_action(3, Some("LEFT")).is_none());
+}
+
+#[test]
+fn test_button_press_config() {
+    let defaults = parse("").button_press.classifier();
+    assert_eq!(defaults.mode(), PressMode::Hybrid);
+    assert_eq!(defaults.threshold(), mutenix_hid::DEFAULT_LONGPRESS_THRESHOLD);
+
+    let config = parse("button_press: {mode: host, longpress_threshold_ms: 800}\n");
+    let classifier = config.button_press.classifier();
+    assert_eq!(classifier.mode(), PressMode::Host);
+    assert_eq!(classifier.threshold(), Duration::from_millis(800));
+}
+
+#[test]
+fn test_connection_watchdog_config() {
+    let config = parse(
+        "watchdog:\n  \
+           ping_interval_ms: 500\n  \
+           degraded_after: 2\n  \
+           error_after: 4\n  \
+           reconnect_delay_ms: 100\n  \
+           max_reconnect_delay_ms: 1000\n",
+    );
+    let watchdog = config.watchdog.to_watchdog();
+    assert_eq!(watchdog.ping_interval(), Duration::from_millis(500));
+    assert_eq!(watchdog.degraded_after(), 2);
+    assert_eq!(watchdog.error_after(), 4);
+    assert_eq!(watchdog.reconnect_delay(), Duration::from_millis(100));
+    assert_eq!(watchdog.max_reconnect_delay(), Duration::from_secs(1));
+
+    // Missing values fall back to the library defaults
+    let config = parse("watchdog: {error_after: 6}\n");
+    assert_eq!(config.watchdog.degraded_after, mutenix_hid::DEFAULT_DEGRADED_AFTER_MISSES);
+    assert_eq!(config.watchdog.error_after, 6);
+}
+
+#[test]
+fn test_device_identification() {
+    let config = parse(
+        "device_identifications:\n\
+         - product: 'mutenix*'\n  \
+           usage_page: 65440\n  \
+           interface_number: 1\n  \
+           hardware_type: ten-button-usb\n  \
+           priority: 5\n\
+         - {vendor_id: 7504, product_id: 24969, serial_number: ABC}\n",
+    );
+    let info = config.get_device_info();
+    assert_eq!(info.len(), 2);
+
+    assert_eq!(info[0].vendor_id, 0);
+    assert_eq!(info[0].product_pattern.as_deref(), Some("mutenix*"));
+    assert_eq!(info[0].usage_page, Some(0xffa0));
+    assert_eq!(info[0].interface_number, Some(1));
+    assert_eq!(info[0].hardware_type, Some(HardwareType::TenButtonUsb));
+    assert_eq!(info[0].priority, 5);
+
+    assert_eq!((info[1].vendor_id, info[1].product_id), (7504, 24969));
+    assert_eq!(info[1].serial_number.as_deref(), Some("ABC"));
+    assert_eq!(info[1].priority, 0);
+    assert!(info.iter().all(|i| i.path.is_none()));
+
+    // Unset criteria are left out when writing the config back
+    let yaml = serde_yaml::to_string(&config.device_identifications[1]).unwrap();
+    assert!(!yaml.contains("priority"));
+    assert!(!yaml.contains("usage_page"));
+    let written: DeviceIdentification = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(written.serial_number.as_deref(), Some("ABC"));
+}
+
+#[test]
+fn test_config_round_trip() {
+    let config = parse(
+        "led_brightness: 0.8\n\
+         led_status:\n\
+         - button_id: 1\n  \
+           serial_number: LEFT\n  \
+           brightness: 0.5\n  \
+           teams_state:\n    \
+             teams_state: is-video-on\n    \
+             color_on: {r: 1, g: 2, b: 3, w: 4}\n    \
+             pattern_on: {type: pulse}\n\
+         button_press: {mode: firmware}\n",
+    );
+    let yaml = serde_yaml::to_string(&config).unwrap();
+    let read_back: Config = serde_yaml::from_str(&yaml).unwrap();
+
+    assert_eq!(read_back.led_brightness, 0.8);
+    assert_eq!(read_back.button_press.mode, PressMode::Firmware);
+    let led = &read_back.led_status[0];
+    assert_eq!(led.serial_number.as_deref(), Some("LEFT"));
+    assert_eq!(led.brightness, Some(0.5));
+    let teams_state = led.teams_state.as_ref().unwrap();
+    assert_eq!(teams_state.color_on, Some(LedColorConfig::Rgbw { r: 1, g: 2, b: 3, w: 4 }));
+    assert_eq!(teams_state.pattern_on, Some(LedPatternConfig::Pulse { period: 2.0 }));
+}
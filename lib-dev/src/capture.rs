@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Capture of HID traffic.
+//!
+//! `CaptureTransport` wraps another transport and records every report in
+//! both directions into a file, one JSON object per line:
+//!
+//! ```json
+//! {"timestamp_us":0,"serial":"ABC","direction":"connect","vendor_id":7504,"product_id":24969,"product":"Mutenix Macropad","manufacturer":"Mutenix"}
+//! {"timestamp_us":1250,"serial":"ABC","direction":"tx","report_id":1,"data":"f000000000000000"}
+//! {"timestamp_us":4810,"serial":"ABC","direction":"rx","report_id":1,"data":"9901020305000000"}
+//! {"timestamp_us":9120,"serial":"ABC","direction":"disconnect"}
+//! ```
+//!
+//! Timestamps count from the creation of the capture. `ReplayTransport`
+//! feeds such a file back into the host.
+
+use crate::hid_device::HidError;
+use crate::transport::{DeviceDescriptor, HidConnection, HidTransport};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Errors while writing or reading a capture
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid capture record in line {line}: {message}")]
+    InvalidRecord { line: usize, message: String },
+}
+
+/// What happened on the wire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "direction", rename_all = "kebab-case")]
+pub enum CaptureEntry {
+    /// The host opened the device
+    Connect {
+        vendor_id: u16,
+        product_id: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        manufacturer: Option<String>,
+    },
+    /// Report written by the host (host -> device)
+    Tx {
+        report_id: u8,
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+    },
+    /// Report read by the host (device -> host)
+    Rx {
+        report_id: u8,
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+    },
+    /// Reading or writing failed, the device is gone
+    Disconnect,
+}
+
+impl CaptureEntry {
+    /// Entry for a report as passed to or returned by a connection;
+    /// the first byte is the report ID
+    fn report(rx: bool, report: &[u8]) -> Self {
+        let (report_id, data) = match report.split_first() {
+            Some((report_id, data)) => (*report_id, data.to_vec()),
+            None => (0, Vec::new()),
+        };
+        if rx {
+            CaptureEntry::Rx { report_id, data }
+        } else {
+            CaptureEntry::Tx { report_id, data }
+        }
+    }
+
+    /// The report including its report ID, for Tx and Rx entries
+    pub fn report_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            CaptureEntry::Tx { report_id, data } | CaptureEntry::Rx { report_id, data } => {
+                let mut report = vec![*report_id];
+                report.extend_from_slice(data);
+                Some(report)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One line of a capture
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Microseconds since the capture was started
+    pub timestamp_us: u64,
+    /// Serial number of the device, if it reports one
+    pub serial: Option<String>,
+    #[serde(flatten)]
+    pub entry: CaptureEntry,
+}
+
+/// Read all records of a capture file
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>, CaptureError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| CaptureError::InvalidRecord {
+            line: i + 1,
+            message: e.to_string(),
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Destination of captured records; shared by all connections of a transport
+pub struct CaptureWriter {
+    started: Instant,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl CaptureWriter {
+    /// Write the capture to a new file, replacing an existing one
+    pub fn create(path: &Path) -> Result<Self, CaptureError> {
+        Ok(Self::new(Box::new(LineWriter::new(File::create(path)?))))
+    }
+
+    /// Write the capture to any output
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        Self {
+            started: Instant::now(),
+            output: Mutex::new(output),
+        }
+    }
+
+    /// Append a record stamped with the current time
+    pub fn record(&self, serial: Option<&str>, entry: CaptureEntry) {
+        let record = CaptureRecord {
+            timestamp_us: self.started.elapsed().as_micros() as u64,
+            serial: serial.map(str::to_string),
+            entry,
+        };
+
+        // Capturing must never break the connection it observes
+        let result = serde_json::to_string(&record)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(self.output.lock().unwrap(), "{}", line));
+        if let Err(e) = result {
+            warn!("Failed to write capture record: {}", e);
+        }
+    }
+}
+
+/// Transport that records the traffic of another transport
+pub struct CaptureTransport {
+    inner: Arc<dyn HidTransport>,
+    writer: Arc<CaptureWriter>,
+}
+
+impl CaptureTransport {
+    pub fn new(inner: Arc<dyn HidTransport>, writer: Arc<CaptureWriter>) -> Self {
+        Self { inner, writer }
+    }
+}
+
+impl HidTransport for CaptureTransport {
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError> {
+        self.inner.enumerate()
+    }
+
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError> {
+        let inner = self.inner.open(descriptor)?;
+        let serial = descriptor.serial_number.clone();
+        self.writer.record(
+            serial.as_deref(),
+            CaptureEntry::Connect {
+                vendor_id: descriptor.vendor_id,
+                product_id: descriptor.product_id,
+                product: descriptor.product.clone(),
+                manufacturer: descriptor.manufacturer.clone(),
+            },
+        );
+
+        Ok(Box::new(CaptureConnection {
+            inner,
+            serial,
+            writer: self.writer.clone(),
+        }))
+    }
+}
+
+/// Connection that records everything passing through it
+struct CaptureConnection {
+    inner: Box<dyn HidConnection>,
+    serial: Option<String>,
+    writer: Arc<CaptureWriter>,
+}
+
+impl CaptureConnection {
+    fn record(&self, entry: CaptureEntry) {
+        self.writer.record(self.serial.as_deref(), entry);
+    }
+}
+
+impl HidConnection for CaptureConnection {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let result = self.inner.write(data);
+        match &result {
+            Ok(_) => self.record(CaptureEntry::report(false, data)),
+            Err(_) => self.record(CaptureEntry::Disconnect),
+        }
+        result
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        let result = self.inner.read_timeout(buffer, timeout_ms);
+        match &result {
+            Ok(0) => {}
+            Ok(size) => self.record(CaptureEntry::report(true, &buffer[..*size])),
+            Err(_) => self.record(CaptureEntry::Disconnect),
+        }
+        result
+    }
+}
+
+/// Reports are stored as lowercase hex strings to keep captures readable
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 {
+            return Err(D::Error::custom("odd number of hex digits"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| D::Error::custom(format!("invalid hex digits at {}", i)))
+            })
+            .collect()
+    }
+}
@@ -1,7 +1,41 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
-use crate::constants::MAX_CHUNK_SIZE;
+use crate::constants::{MAX_CHUNK_SIZE, MAX_FILE_SIZE};
+
+/// Values the chunk protocol cannot represent
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ChunkError {
+    #[error("Filename {filename} is too long, at most {max} bytes fit into a chunk")]
+    FilenameTooLong { filename: String, max: usize },
+
+    #[error("File of {size} bytes is too large, at most {max} bytes are supported")]
+    FileTooLarge { size: usize, max: usize },
+
+    #[error("Too many files, at most {max} can be transferred at once")]
+    TooManyFiles { max: usize },
+}
+
+/// File id for the file at `index` of a transfer
+pub fn file_id(index: usize) -> Result<u16, ChunkError> {
+    u16::try_from(index).map_err(|_| ChunkError::TooManyFiles {
+        max: u16::MAX as usize + 1,
+    })
+}
+
+/// Append the length prefixed filename to a chunk content, leaving `reserved` bytes free
+fn push_filename(content: &mut Vec<u8>, filename: &str, reserved: usize) -> Result<(), ChunkError> {
+    let max = MAX_CHUNK_SIZE - 1 - reserved;
+    if filename.len() > max {
+        return Err(ChunkError::FilenameTooLong {
+            filename: filename.to_string(),
+            max,
+        });
+    }
+    content.push(filename.len() as u8);
+    content.extend_from_slice(filename.as_bytes());
+    Ok(())
+}
 
 /// Types of chunks in the file transfer protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,6 +54,21 @@ impl From<ChunkType> for u16 {
     }
 }
 
+impl TryFrom<u16> for ChunkType {
+    type Error = u16;
+
+    fn try_from(val: u16) -> Result<Self, Self::Error> {
+        match val {
+            1 => Ok(ChunkType::FileStart),
+            2 => Ok(ChunkType::FileChunk),
+            3 => Ok(ChunkType::FileEnd),
+            4 => Ok(ChunkType::Complete),
+            5 => Ok(ChunkType::FileDelete),
+            other => Err(other),
+        }
+    }
+}
+
 /// Base chunk structure for file transfer protocol
 #[derive(Debug, Clone)]
 pub struct Chunk {
@@ -103,17 +152,36 @@ pub struct FileStart {
 }
 
 impl FileStart {
-    pub fn new(id: u16, package: u16, total_packages: u16, filename: &str, filesize: u16) -> Self {
+    /// The file size is sent with a size indicator giving its width in bytes:
+    /// 2 for files up to 64 KiB - 1, which every firmware understands, 4 above.
+    pub fn new(
+        id: u16,
+        package: u16,
+        total_packages: u16,
+        filename: &str,
+        filesize: usize,
+    ) -> Result<Self, ChunkError> {
+        if filesize > MAX_FILE_SIZE {
+            return Err(ChunkError::FileTooLarge {
+                size: filesize,
+                max: MAX_FILE_SIZE,
+            });
+        }
+
+        let size_bytes = match u16::try_from(filesize) {
+            Ok(size) => size.to_le_bytes().to_vec(),
+            Err(_) => (filesize as u32).to_le_bytes().to_vec(),
+        };
+
         let mut chunk = Chunk::new(ChunkType::FileStart, id, package, total_packages);
-        
+
         let mut content = Vec::new();
-        content.push(filename.len() as u8);
-        content.extend_from_slice(filename.as_bytes());
-        content.push(2); // Size indicator
-        content.extend_from_slice(&filesize.to_le_bytes());
-        
+        push_filename(&mut content, filename, 1 + size_bytes.len())?;
+        content.push(size_bytes.len() as u8); // Size indicator
+        content.extend_from_slice(&size_bytes);
+
         chunk.content = content;
-        Self { chunk }
+        Ok(Self { chunk })
     }
 
     pub fn inner(&self) -> &Chunk {
@@ -153,15 +221,14 @@ pub struct FileDelete {
 }
 
 impl FileDelete {
-    pub fn new(id: u16, filename: &str) -> Self {
+    pub fn new(id: u16, filename: &str) -> Result<Self, ChunkError> {
         let mut chunk = Chunk::new(ChunkType::FileDelete, id, 0, 0);
-        
+
         let mut content = Vec::new();
-        content.push(filename.len() as u8);
-        content.extend_from_slice(filename.as_bytes());
-        
+        push_filename(&mut content, filename, 0)?;
+
         chunk.content = content;
-        Self { chunk }
+        Ok(Self { chunk })
     }
 
     pub fn inner(&self) -> &Chunk {
@@ -7,6 +7,9 @@ pub const HEADER_SIZE: usize = 8;
 /// Maximum size of data in a single chunk (60 bytes total - header)
 pub const MAX_CHUNK_SIZE: usize = 60 - HEADER_SIZE;
 
+/// Largest file the chunk protocol can transfer: package indices are 16 bit
+pub const MAX_FILE_SIZE: usize = u16::MAX as usize * MAX_CHUNK_SIZE;
+
 /// Sleep time between data transfers in seconds
 pub const DATA_TRANSFER_SLEEP_TIME: f64 = 1.0;
 
@@ -16,6 +19,18 @@ pub const STATE_CHANGE_SLEEP_TIME: f64 = 0.5;
 /// Sleep time while waiting for requests in seconds
 pub const WAIT_FOR_REQUESTS_SLEEP_TIME: f64 = STATE_CHANGE_SLEEP_TIME;
 
+/// Number of unacknowledged chunks that may be in flight during an update
+pub const DEFAULT_UPDATE_WINDOW_SIZE: usize = 8;
+
+/// Time after which an unacknowledged chunk is sent again, in milliseconds
+pub const DEFAULT_RETRANSMIT_TIMEOUT_MS: u64 = 500;
+
+/// Number of retransmits of a single chunk before the update fails
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Time a device may take to restart after its settings changed, in milliseconds
+pub const DEFAULT_CONFIGURE_TIMEOUT_MS: u64 = 10_000;
+
 /// HID Report ID for communication commands
 pub const HID_REPORT_ID_COMMUNICATION: u8 = 1;
 
@@ -28,4 +43,22 @@ pub const HID_COMMAND_PREPARE_UPDATE: u8 = 0xE0;
 /// HID command to reset device
 pub const HID_COMMAND_RESET: u8 = 0xE1;
 
-pub const PING_LOOP_TIME_SECONDS: u64 = 4;
\ No newline at end of file
+pub const PING_LOOP_TIME_SECONDS: u64 = 4;
+
+/// Consecutive missed pings after which a connection is degraded
+pub const DEFAULT_DEGRADED_AFTER_MISSES: u32 = 1;
+
+/// Consecutive missed pings after which a connection is dropped and opened again
+pub const DEFAULT_ERROR_AFTER_MISSES: u32 = 3;
+
+/// Delay before reopening a device that stopped responding, in milliseconds
+pub const DEFAULT_RECONNECT_DELAY_MS: u64 = 1_000;
+
+/// Limit the reconnect delay doubles up to, in milliseconds
+pub const DEFAULT_MAX_RECONNECT_DELAY_MS: u64 = 30_000;
+
+/// Longest time the I/O thread waits for a report before performing queued writes, in milliseconds
+pub const IO_POLL_INTERVAL_MS: i32 = 5;
+
+/// Commands without coalesce key that may wait in the write queue of a device
+pub const DEFAULT_WRITE_QUEUE_CAPACITY: usize = 32;
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Event streams of device handlers.
+//!
+//! Every `HidDevice` publishes what happens on its connection to a broadcast
+//! channel. Subscribers receive the events with `DeviceEvents::next` or as an
+//! async stream; a subscriber that falls behind skips the oldest events
+//! instead of holding up the device.
+
+use crate::device_messages::LogMessage;
+use crate::hid_commands::{Status, StatusRequest, VersionInfo};
+use crate::hid_device::{DeviceMessage, HidError};
+use crate::transport::DeviceDescriptor;
+use log::warn;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::Stream;
+
+/// Number of events buffered per subscriber before the oldest are skipped
+pub const EVENT_CAPACITY: usize = 256;
+
+/// Something that happened on the connection of a device
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    /// A device was opened
+    Connected(DeviceDescriptor),
+    /// The connection was lost or dropped
+    Disconnected,
+    /// Button status
+    Status(Status),
+    /// The device restarted and asks for its state; the handler already replays it
+    StatusRequest(StatusRequest),
+    VersionInfo(VersionInfo),
+    /// Log line the firmware sent
+    Log(LogMessage),
+    /// Reading or writing failed, or the device stopped answering
+    Error(HidError),
+}
+
+impl DeviceEvent {
+    /// The message the device sent, for events that carry one
+    pub fn message(&self) -> Option<DeviceMessage> {
+        match self {
+            DeviceEvent::Status(status) => Some(DeviceMessage::Status(status.clone())),
+            DeviceEvent::StatusRequest(request) => Some(DeviceMessage::StatusRequest(request.clone())),
+            DeviceEvent::VersionInfo(version_info) => Some(DeviceMessage::VersionInfo(version_info.clone())),
+            DeviceEvent::Log(log_message) => Some(DeviceMessage::Log(log_message.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl From<DeviceMessage> for DeviceEvent {
+    fn from(message: DeviceMessage) -> Self {
+        match message {
+            DeviceMessage::Status(status) => DeviceEvent::Status(status),
+            DeviceMessage::StatusRequest(request) => DeviceEvent::StatusRequest(request),
+            DeviceMessage::VersionInfo(version_info) => DeviceEvent::VersionInfo(version_info),
+            DeviceMessage::Log(log_message) => DeviceEvent::Log(log_message),
+        }
+    }
+}
+
+/// Async stream of events. Ends once the publisher is dropped.
+pub struct DeviceEvents<T = DeviceEvent> {
+    inner: BroadcastStream<T>,
+    skipped: u64,
+}
+
+impl<T: Clone + Send + 'static> DeviceEvents<T> {
+    pub(crate) fn new(receiver: broadcast::Receiver<T>) -> Self {
+        Self {
+            inner: BroadcastStream::new(receiver),
+            skipped: 0,
+        }
+    }
+
+    /// Wait for the next event; `None` once the publisher is dropped
+    pub async fn next(&mut self) -> Option<T> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Number of events skipped so far because this subscriber fell behind
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+impl<T: Clone + Send + 'static> Stream for DeviceEvents<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => return Poll::Ready(Some(event)),
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(count)))) => {
+                    warn!("Event subscriber fell behind, skipped {} events", count);
+                    self.skipped += count;
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
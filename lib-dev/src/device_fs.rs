@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Access to the device filesystem.
+//!
+//! Files are written and deleted with the chunk protocol of firmware updates,
+//! but a session ends with `Completed` only: the device is not reset and keeps
+//! running, so a keymap or an asset can be replaced without a full update.
+
+use crate::chunks::file_id;
+use crate::device_update::{run_session, TransferFile, UpdateError, UpdateOptions};
+use crate::hid_device::HidDevice;
+use crate::transport::HidConnection;
+use log::info;
+use std::path::Path;
+
+/// Operation on a single file of the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsOperation {
+    Write { path: String, content: Vec<u8> },
+    Delete { path: String },
+}
+
+impl FsOperation {
+    /// Path of the file on the device
+    pub fn path(&self) -> &str {
+        match self {
+            FsOperation::Write { path, .. } | FsOperation::Delete { path } => path,
+        }
+    }
+}
+
+/// File operations sent to the device in one session, in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct FsBatch {
+    operations: Vec<FsOperation>,
+}
+
+impl FsBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write `content` to `path` on the device
+    pub fn write(mut self, path: impl Into<String>, content: Vec<u8>) -> Self {
+        self.operations.push(FsOperation::Write { path: path.into(), content });
+        self
+    }
+
+    /// Write a local file to `path` on the device
+    pub fn write_file(self, path: impl Into<String>, local: &Path) -> Result<Self, UpdateError> {
+        Ok(self.write(path, std::fs::read(local)?))
+    }
+
+    /// Delete `path` on the device
+    pub fn delete(mut self, path: impl Into<String>) -> Self {
+        self.operations.push(FsOperation::Delete { path: path.into() });
+        self
+    }
+
+    pub fn operations(&self) -> &[FsOperation] {
+        &self.operations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Transfer files in batch order
+    pub fn transfer_files(&self) -> Result<Vec<TransferFile>, UpdateError> {
+        self.operations
+            .iter()
+            .enumerate()
+            .map(|(i, operation)| match operation {
+                FsOperation::Write { path, content } => {
+                    TransferFile::from_content(file_id(i)?, path, content.clone())
+                }
+                FsOperation::Delete { path } => TransferFile::delete(file_id(i)?, path),
+            })
+            .collect()
+    }
+}
+
+/// Run the operations of `batch` in one session without resetting the device.
+/// A cancelled session resets the device and returns `UpdateError::Cancelled`.
+/// Blocks the calling thread like `perform_hid_upgrade_with`.
+pub fn perform_fs_batch_with(
+    device: &mut dyn HidConnection,
+    batch: &FsBatch,
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
+    let transfer_files = batch.transfer_files()?;
+    if transfer_files.is_empty() {
+        return Ok(());
+    }
+
+    info!("Starting filesystem session with {} operations", transfer_files.len());
+    run_session(device, transfer_files, options)?;
+    info!("Filesystem session complete");
+    Ok(())
+}
+
+/// Filesystem of a device handled by a `HidDevice`, see `HidDevice::fs`.
+///
+/// Regular communication is paused while a session runs, like during an
+/// update, and resumes on the same connection once the session is complete.
+pub struct DeviceFs<'a> {
+    device: &'a HidDevice,
+}
+
+impl<'a> DeviceFs<'a> {
+    pub(crate) fn new(device: &'a HidDevice) -> Self {
+        Self { device }
+    }
+
+    /// Write a single file
+    pub async fn push(&self, path: &str, content: Vec<u8>, options: &UpdateOptions) -> Result<(), UpdateError> {
+        self.apply(&FsBatch::new().write(path, content), options).await
+    }
+
+    /// Delete a single file
+    pub async fn delete(&self, path: &str, options: &UpdateOptions) -> Result<(), UpdateError> {
+        self.apply(&FsBatch::new().delete(path), options).await
+    }
+
+    /// Run all operations of `batch` in one session
+    pub async fn apply(&self, batch: &FsBatch, options: &UpdateOptions) -> Result<(), UpdateError> {
+        self.device.run_fs_batch(batch, options).await
+    }
+}
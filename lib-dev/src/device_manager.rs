@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Management of several simultaneously connected Mutenix devices.
+//!
+//! The manager periodically enumerates the transport and starts one `HidDevice`
+//! per matching serial number. Events from all devices are forwarded to the
+//! subscribers tagged with the serial number of their origin.
+
+use crate::device_events::{DeviceEvent, DeviceEvents, EVENT_CAPACITY};
+use crate::hid_commands::{HidOutputCommand, SetLed};
+use crate::hid_device::{
+    is_selected, select_rule, ConnectionState, DeviceInfo, DeviceMessage, HardwareState, HidDevice,
+    HidError,
+};
+use crate::transport::{DeviceDescriptor, HidApiTransport, HidTransport};
+use crate::watchdog::WatchdogConfig;
+use crate::write_queue::WriteQueueConfig;
+use log::{debug, error, info};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+/// Interval between two scans for newly attached devices
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time a disconnected device may be missing from enumeration before its handler is dropped
+const REMOVAL_DELAY: Duration = Duration::from_secs(30);
+
+/// Device message tagged with the serial number of the device that sent it
+#[derive(Debug, Clone)]
+pub struct TaggedDeviceMessage {
+    pub serial_number: String,
+    pub message: DeviceMessage,
+}
+
+/// Device event tagged with the serial number of the device it happened on
+#[derive(Debug, Clone)]
+pub struct TaggedDeviceEvent {
+    pub serial_number: String,
+    pub event: DeviceEvent,
+}
+
+/// Handler for all connected Mutenix devices
+pub struct DeviceManager {
+    device_info: Vec<DeviceInfo>,
+    transport: Arc<dyn HidTransport>,
+    watchdog: WatchdogConfig,
+    write_queue: WriteQueueConfig,
+    removal_delay: Duration,
+    devices: Arc<RwLock<BTreeMap<String, Arc<HidDevice>>>>,
+    events: broadcast::Sender<TaggedDeviceEvent>,
+    /// Cancelled by `stop`; replaced once the running `process()` has ended
+    shutdown: Mutex<CancellationToken>,
+    /// Held while `process()` runs
+    process_lock: Mutex<()>,
+}
+
+impl DeviceManager {
+    /// Create a new device manager
+    pub fn new(device_info: Vec<DeviceInfo>) -> Self {
+        Self::with_transport(device_info, Arc::new(HidApiTransport::new()))
+    }
+
+    /// Create a new device manager on top of a custom transport
+    pub fn with_transport(device_info: Vec<DeviceInfo>, transport: Arc<dyn HidTransport>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            device_info,
+            transport,
+            watchdog: WatchdogConfig::default(),
+            write_queue: WriteQueueConfig::default(),
+            removal_delay: REMOVAL_DELAY,
+            devices: Arc::new(RwLock::new(BTreeMap::new())),
+            events,
+            shutdown: Mutex::new(CancellationToken::new()),
+            process_lock: Mutex::new(()),
+        }
+    }
+
+    /// Monitor every device with the given watchdog settings
+    pub fn with_watchdog(mut self, watchdog: WatchdogConfig) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    /// Give every device a write queue with the given settings
+    pub fn with_write_queue(mut self, write_queue: WriteQueueConfig) -> Self {
+        self.write_queue = write_queue;
+        self
+    }
+
+    /// Drop the handler of a device that is disconnected and missing from
+    /// enumeration for the given time; 30 seconds by default
+    pub fn with_removal_delay(mut self, removal_delay: Duration) -> Self {
+        self.removal_delay = removal_delay;
+        self
+    }
+
+    /// Subscribe to the events of all devices, including devices found later
+    pub fn subscribe(&self) -> DeviceEvents<TaggedDeviceEvent> {
+        DeviceEvents::new(self.events.subscribe())
+    }
+
+    /// Register a callback for messages from any device.
+    /// The callback runs on a task of its own, fed by `subscribe`.
+    pub async fn register_callback<F>(&self, callback: F)
+    where
+        F: Fn(TaggedDeviceMessage) + Send + Sync + 'static,
+    {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            while let Some(tagged) = events.next().await {
+                if let Some(message) = tagged.event.message() {
+                    callback(TaggedDeviceMessage {
+                        serial_number: tagged.serial_number,
+                        message,
+                    });
+                }
+            }
+        });
+    }
+
+    /// Get the device with the given serial number
+    pub async fn device(&self, serial_number: &str) -> Option<Arc<HidDevice>> {
+        self.devices.read().await.get(serial_number).cloned()
+    }
+
+    /// Serial numbers of all currently connected devices
+    pub async fn connected_serials(&self) -> Vec<String> {
+        let devices = self.devices.read().await.clone();
+        let mut serials = Vec::new();
+        for (serial, device) in devices {
+            if device.state().await.connection_status.is_connected() {
+                serials.push(serial);
+            }
+        }
+        serials
+    }
+
+    /// Hardware state of every known device, keyed by serial number
+    pub async fn states(&self) -> BTreeMap<String, HardwareState> {
+        let devices = self.devices.read().await.clone();
+        let mut states = BTreeMap::new();
+        for (serial, device) in devices {
+            states.insert(serial, device.state().await);
+        }
+        states
+    }
+
+    /// Send a command to the device with the given serial number
+    pub async fn send_command_to<C: HidOutputCommand + Send + 'static>(
+        &self,
+        serial_number: &str,
+        command: C,
+    ) -> Result<usize, HidError> {
+        let device = self.device(serial_number).await.ok_or(HidError::NotConnected)?;
+        device.send_command(command).await
+    }
+
+    /// Set an LED of the device with the given serial number, see `HidDevice::set_led`
+    pub async fn set_led_on(&self, serial_number: &str, command: SetLed) -> Result<usize, HidError> {
+        let device = self.device(serial_number).await.ok_or(HidError::NotConnected)?;
+        device.set_led(command).await
+    }
+
+    /// Send a command to every connected device
+    pub async fn broadcast_command<C: HidOutputCommand + Clone + Send + 'static>(
+        &self,
+        command: C,
+    ) -> Result<(), HidError> {
+        let mut result = Ok(());
+        for serial in self.connected_serials().await {
+            if let Err(e) = self.send_command_to(&serial, command.clone()).await {
+                error!("Failed to send command to {}: {}", serial, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Key used to tell devices apart; falls back to the path for devices without serial
+    fn device_key(descriptor: &DeviceDescriptor) -> String {
+        descriptor
+            .serial_number
+            .clone()
+            .unwrap_or_else(|| descriptor.path.clone())
+    }
+
+    /// Start a handler for every matching device that has none yet and drop
+    /// the handlers of devices that are gone
+    async fn discover(&self, missing_since: &mut HashMap<String, Instant>) -> Result<(), HidError> {
+        let descriptors = self.transport.enumerate()?;
+
+        // Devices matching a rule with higher priority are started first
+        let mut selected: Vec<(&DeviceDescriptor, DeviceInfo)> = descriptors
+            .iter()
+            .filter(|d| is_selected(&self.device_info, d))
+            .map(|d| (d, select_rule(&self.device_info, d).cloned().unwrap_or_default()))
+            .collect();
+        selected.sort_by_key(|(_, rule)| std::cmp::Reverse(rule.priority));
+        let present: HashSet<String> = selected.iter().map(|(d, _)| Self::device_key(d)).collect();
+
+        for (descriptor, rule) in selected {
+            let key = Self::device_key(descriptor);
+            if self.devices.read().await.contains_key(&key) {
+                continue;
+            }
+
+            info!("Found new device {}", key);
+            // Keep the criteria of the rule, e.g. the interface and the hardware type.
+            // A device without serial number is pinned to its path instead.
+            let pinned = DeviceInfo {
+                vendor_id: descriptor.vendor_id,
+                product_id: descriptor.product_id,
+                serial_number: descriptor.serial_number.clone(),
+                path: descriptor.serial_number.is_none().then(|| descriptor.path.clone()),
+                ..rule
+            };
+            let device = Arc::new(
+                HidDevice::with_transport(vec![pinned], self.transport.clone())
+                    .with_watchdog(self.watchdog.clone())
+                    .with_write_queue(self.write_queue.clone()),
+            );
+
+            // Subscribe before processing starts so the connect event is forwarded
+            let mut events = device.subscribe();
+            let sender = self.events.clone();
+            let serial_number = key.clone();
+            tokio::spawn(async move {
+                while let Some(event) = events.next().await {
+                    let _ = sender.send(TaggedDeviceEvent {
+                        serial_number: serial_number.clone(),
+                        event,
+                    });
+                }
+            });
+
+            self.devices.write().await.insert(key.clone(), device.clone());
+
+            let devices = self.devices.clone();
+            tokio::spawn(async move {
+                if let Err(e) = device.process().await {
+                    error!("Device {} stopped: {}", key, e);
+                }
+                // Forget the stopped handler, unless it was replaced already
+                let mut devices = devices.write().await;
+                if devices.get(&key).is_some_and(|known| Arc::ptr_eq(known, &device)) {
+                    devices.remove(&key);
+                }
+            });
+        }
+
+        self.remove_missing(&present, missing_since).await;
+        Ok(())
+    }
+
+    /// Stop the handlers of devices that have been disconnected and missing
+    /// from enumeration for longer than the removal delay
+    async fn remove_missing(&self, present: &HashSet<String>, missing_since: &mut HashMap<String, Instant>) {
+        let known = self.devices.read().await.clone();
+        missing_since.retain(|key, _| known.contains_key(key));
+
+        for (key, device) in known {
+            if present.contains(&key)
+                || device.state().await.connection_status != ConnectionState::Disconnected
+            {
+                missing_since.remove(&key);
+                continue;
+            }
+
+            let since = *missing_since.entry(key.clone()).or_insert_with(Instant::now);
+            if since.elapsed() >= self.removal_delay {
+                info!("Device {} is gone, dropping its handler", key);
+                missing_since.remove(&key);
+                self.devices.write().await.remove(&key);
+                tokio::spawn(async move { device.stop().await });
+            }
+        }
+    }
+
+    /// Main processing loop; discovers devices until `stop` is called and can
+    /// be started again afterwards. Only one `process()` may run at a time.
+    pub async fn process(&self) -> Result<(), HidError> {
+        let _running = self.process_lock.try_lock().map_err(|_| HidError::AlreadyRunning)?;
+        let shutdown = self.shutdown.lock().await.clone();
+
+        let mut missing_since = HashMap::new();
+        while !shutdown.is_cancelled() {
+            if let Err(e) = self.discover(&mut missing_since).await {
+                debug!("Device discovery failed: {}", e);
+            }
+            tokio::select! {
+                _ = shutdown.cancelled() => {},
+                _ = sleep(DISCOVERY_INTERVAL) => {},
+            }
+        }
+
+        // Still holding the process lock, so a waiting `stop` has seen this run end
+        *self.shutdown.lock().await = CancellationToken::new();
+        Ok(())
+    }
+
+    /// Stop discovery and all device handlers. The handlers are dropped, so
+    /// `states()` is empty afterwards. Returns once `process()` has returned.
+    pub async fn stop(&self) {
+        self.shutdown.lock().await.cancel();
+        let _stopped = self.process_lock.lock().await;
+
+        let devices = std::mem::take(&mut *self.devices.write().await);
+        for device in devices.values() {
+            device.stop().await;
+        }
+    }
+}
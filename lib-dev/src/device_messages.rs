@@ -1,11 +1,28 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
+use crate::constants::HID_REPORT_ID_TRANSFER;
+use crate::hid_commands::{parse_input_message, HidInput, HidInputMessage, HidMessageError};
 use log::info;
 use std::fmt;
 
+/// Ensure `data` holds at least `expected` bytes and starts with one of `identifiers`
+fn check_identifier(data: &[u8], expected: usize, identifiers: &[&[u8; 2]]) -> Result<(), HidMessageError> {
+    if data.len() < expected {
+        return Err(HidMessageError::InvalidLength {
+            expected,
+            actual: data.len(),
+        });
+    }
+    if identifiers.iter().any(|id| data[0..2] == id[..]) {
+        Ok(())
+    } else {
+        Err(HidMessageError::InvalidData)
+    }
+}
+
 /// Error message received from device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UpdateError {
     pub identifier: String,
     pub info: String,
@@ -56,6 +73,14 @@ impl UpdateError {
     }
 }
 
+impl HidInputMessage for UpdateError {
+    /// Parse an "ER" message; identifier and length byte are required
+    fn from_buffer(buffer: &[u8]) -> Result<Self, HidMessageError> {
+        check_identifier(buffer, 3, &[b"ER"])?;
+        Ok(Self::from_bytes(buffer))
+    }
+}
+
 impl fmt::Display for UpdateError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_valid() {
@@ -67,7 +92,7 @@ impl fmt::Display for UpdateError {
 }
 
 /// Acknowledgment of a received chunk
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkAck {
     pub identifier: String,
     pub id: u16,
@@ -128,6 +153,14 @@ impl ChunkAck {
     }
 }
 
+impl HidInputMessage for ChunkAck {
+    /// Parse an "AK" message; identifier, id, package and type are required
+    fn from_buffer(buffer: &[u8]) -> Result<Self, HidMessageError> {
+        check_identifier(buffer, 7, &[b"AK"])?;
+        Ok(Self::from_bytes(buffer))
+    }
+}
+
 impl fmt::Display for ChunkAck {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_valid() {
@@ -143,7 +176,7 @@ impl fmt::Display for ChunkAck {
 }
 
 /// Log message from device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LogMessage {
     pub identifier: String,
     pub level: LogLevel,
@@ -156,6 +189,15 @@ pub enum LogLevel {
     Error,
 }
 
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Error => log::Level::Error,
+        }
+    }
+}
+
 impl LogMessage {
     pub fn from_bytes(data: &[u8]) -> Self {
         if data.len() < 2 {
@@ -207,6 +249,14 @@ impl LogMessage {
     }
 }
 
+impl HidInputMessage for LogMessage {
+    /// Parse an "LD" or "LE" message; the text may be empty
+    fn from_buffer(buffer: &[u8]) -> Result<Self, HidMessageError> {
+        check_identifier(buffer, 2, &[b"LD", b"LE"])?;
+        Ok(Self::from_bytes(buffer))
+    }
+}
+
 impl fmt::Display for LogMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_valid() {
@@ -233,18 +283,24 @@ pub enum HidUpdateMessage {
     Log(LogMessage),
 }
 
-/// Parse a HID update message from raw bytes
+/// Parse a HID update message from raw bytes without report ID.
+/// Kept for existing callers: a frame too short for its type is still
+/// returned with the missing fields zeroed, where `parse_input_message`
+/// rejects it with `HidMessageError::InvalidLength`.
 pub fn parse_hid_update_message(data: &[u8]) -> Option<HidUpdateMessage> {
-    if data.len() < 2 {
-        return None;
-    }
-
-    let identifier = String::from_utf8_lossy(&data[0..2]);
-
-    match identifier.as_ref() {
-        "AK" => Some(HidUpdateMessage::ChunkAck(ChunkAck::from_bytes(data))),
-        "ER" => Some(HidUpdateMessage::Error(UpdateError::from_bytes(data))),
-        "LD" | "LE" => Some(HidUpdateMessage::Log(LogMessage::from_bytes(data))),
+    let mut buffer = Vec::with_capacity(data.len() + 1);
+    buffer.push(HID_REPORT_ID_TRANSFER);
+    buffer.extend_from_slice(data);
+
+    match parse_input_message(&buffer) {
+        Ok(HidInput::ChunkAck(ack)) => Some(HidUpdateMessage::ChunkAck(ack)),
+        Ok(HidInput::UpdateError(error)) => Some(HidUpdateMessage::Error(error)),
+        Ok(HidInput::Log(log)) => Some(HidUpdateMessage::Log(log)),
+        Err(HidMessageError::InvalidLength { .. }) if data.len() >= 2 => match &data[0..2] {
+            b"AK" => Some(HidUpdateMessage::ChunkAck(ChunkAck::from_bytes(data))),
+            b"ER" => Some(HidUpdateMessage::Error(UpdateError::from_bytes(data))),
+            _ => None,
+        },
         _ => None,
     }
 }
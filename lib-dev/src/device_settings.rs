@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Settings stored on the device.
+//!
+//! The firmware keeps the serial console and the USB filesystem switched off
+//! unless told otherwise. Changing a setting restarts the device, which then
+//! enumerates again with the new USB interfaces.
+
+use crate::hid_commands::UpdateConfig;
+use std::fmt;
+
+/// Settings to change on the device; settings left at `None` keep their value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceSettings {
+    /// Expose the serial console (REPL) over USB
+    pub serial_console: Option<bool>,
+    /// Expose the device filesystem as USB mass storage
+    pub filesystem: Option<bool>,
+}
+
+impl DeviceSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_serial_console(mut self, enabled: bool) -> Self {
+        self.serial_console = Some(enabled);
+        self
+    }
+
+    pub fn with_filesystem(mut self, enabled: bool) -> Self {
+        self.filesystem = Some(enabled);
+        self
+    }
+
+    /// True if no setting would be changed
+    pub fn is_empty(&self) -> bool {
+        self.serial_console.is_none() && self.filesystem.is_none()
+    }
+
+    /// Command that applies these settings
+    pub fn to_command(&self) -> UpdateConfig {
+        let mut command = UpdateConfig::new();
+        if let Some(enabled) = self.serial_console {
+            command = command.activate_serial_console(enabled);
+        }
+        if let Some(enabled) = self.filesystem {
+            command = command.activate_filesystem(enabled);
+        }
+        command
+    }
+}
+
+impl fmt::Display for DeviceSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |value: Option<bool>| match value {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => "unchanged",
+        };
+        write!(
+            f,
+            "serial console {}, filesystem {}",
+            describe(self.serial_console),
+            describe(self.filesystem)
+        )
+    }
+}
@@ -1,17 +1,25 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
-use crate::chunks::{Chunk, Completed, FileChunk, FileDelete, FileEnd, FileStart};
+use crate::chunks::{file_id, Chunk, ChunkError, ChunkType, Completed, FileChunk, FileDelete, FileEnd, FileStart};
 use crate::constants::{
+    DEFAULT_MAX_RETRIES, DEFAULT_RETRANSMIT_TIMEOUT_MS, DEFAULT_UPDATE_WINDOW_SIZE,
     HID_COMMAND_PREPARE_UPDATE, HID_COMMAND_RESET, HID_REPORT_ID_COMMUNICATION,
     HID_REPORT_ID_TRANSFER, MAX_CHUNK_SIZE, STATE_CHANGE_SLEEP_TIME,
 };
-use crate::device_messages::{parse_hid_update_message, ChunkAck, HidUpdateMessage};
-use hidapi::HidDevice;
-use log::{debug, error, info, warn};
+use crate::device_messages::ChunkAck;
+use crate::dissector::{dissect, FrameDirection};
+use crate::firmware_bundle::{BundleError, FirmwareBundle};
+use crate::hid_commands::{parse_input_message, HidInput, VersionInfo};
+use crate::hid_device::{HidError, DEVICE_LOG_TARGET};
+use crate::transport::HidConnection;
+use log::{debug, error, info, log, warn};
 use std::path::Path;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 /// Errors during device update
 #[derive(Debug, thiserror::Error)]
@@ -28,8 +36,138 @@ pub enum UpdateError {
     #[error("Write failed: {0}")]
     WriteFailed(String),
 
+    #[error("Read failed: {0}")]
+    ReadFailed(String),
+
     #[error("File error: {0}")]
     FileError(String),
+
+    #[error("Update cancelled")]
+    Cancelled,
+
+    #[error("Cannot transfer file: {0}")]
+    Chunk(#[from] ChunkError),
+
+    #[error("Invalid firmware bundle: {0}")]
+    Bundle(#[from] BundleError),
+
+    #[error("Another update is already running")]
+    AlreadyRunning,
+
+    #[error("Device did not come back after the update: {0}")]
+    Restart(HidError),
+
+    #[error("{type_:?} {package} of {file} not acknowledged after {attempts} attempts")]
+    ChunkTimeout {
+        file: String,
+        type_: ChunkType,
+        package: u16,
+        attempts: u32,
+    },
+}
+
+/// Progress of a running update, emitted after every acknowledged chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateProgress {
+    /// Name of the file currently transferred
+    pub file: String,
+    /// Index of the current file, starting at 0
+    pub file_index: usize,
+    pub file_count: usize,
+    /// Acknowledged chunks of the current file
+    pub chunk: usize,
+    pub total_chunks: usize,
+    /// Acknowledged payload bytes over all files
+    pub bytes: usize,
+    pub total_bytes: usize,
+    /// Estimated remaining time, once at least one byte has been acknowledged
+    pub eta: Option<Duration>,
+}
+
+impl UpdateProgress {
+    /// Fraction of payload bytes transferred, in `0.0..=1.0`
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 1.0;
+        }
+        self.bytes as f64 / self.total_bytes as f64
+    }
+}
+
+/// Callback type for update progress
+pub type ProgressCallback = Arc<dyn Fn(UpdateProgress) + Send + Sync>;
+
+/// Options of an update run
+#[derive(Clone)]
+pub struct UpdateOptions {
+    progress: Option<ProgressCallback>,
+    cancel: CancellationToken,
+    window_size: usize,
+    retransmit_timeout: Duration,
+    max_retries: u32,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            progress: None,
+            cancel: CancellationToken::new(),
+            window_size: DEFAULT_UPDATE_WINDOW_SIZE,
+            retransmit_timeout: Duration::from_millis(DEFAULT_RETRANSMIT_TIMEOUT_MS),
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+}
+
+impl UpdateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of unacknowledged chunks that may be in flight, at least 1
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size.max(1);
+        self
+    }
+
+    /// Time after which an unacknowledged chunk is sent again
+    pub fn with_retransmit_timeout(mut self, timeout: Duration) -> Self {
+        self.retransmit_timeout = timeout;
+        self
+    }
+
+    /// Number of retransmits of a single chunk, and of read errors in a row,
+    /// before the update fails
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Receive a progress event after every acknowledged chunk
+    pub fn with_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(UpdateProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Abort the update once the token is cancelled
+    pub fn with_cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Token that cancels this update
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    fn report(&self, progress: UpdateProgress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
 }
 
 /// Represents a file to be transferred to the device
@@ -51,25 +189,19 @@ impl TransferFile {
             .to_string();
 
         // Check if this is a delete marker
-        if filename.ends_with(".delete") {
-            let actual_filename = filename.trim_end_matches(".delete");
-            let chunk = FileDelete::new(id, actual_filename);
-            
-            return Ok(Self {
-                id,
-                filename: actual_filename.to_string(),
-                content: Vec::new(),
-                size: 0,
-                chunks: vec![chunk.inner().clone()],
-            });
+        if let Some(actual_filename) = filename.strip_suffix(".delete") {
+            return Self::delete(id, actual_filename);
         }
 
-        // Read file content
-        let content = std::fs::read(path)?;
+        Self::from_content(id, &filename, std::fs::read(path)?)
+    }
+
+    /// Create a transfer file writing `content` to `filename` on the device
+    pub fn from_content(id: u16, filename: &str, content: Vec<u8>) -> Result<Self, UpdateError> {
         let size = content.len();
 
         // Pad content to MAX_CHUNK_SIZE boundary (workaround for update issue)
-        let mut padded_content = content.clone();
+        let mut padded_content = content;
         let padding = MAX_CHUNK_SIZE - (size % MAX_CHUNK_SIZE);
         if padding < MAX_CHUNK_SIZE {
             padded_content.extend(vec![0x20; padding]);
@@ -77,25 +209,37 @@ impl TransferFile {
 
         let mut file = Self {
             id,
-            filename,
+            filename: filename.to_string(),
             content: padded_content,
             size,
             chunks: Vec::new(),
         };
 
-        file.make_chunks();
-        
+        file.make_chunks()?;
+
         debug!("File {} has {} chunks", file.filename, file.chunks.len());
 
         Ok(file)
     }
 
-    fn make_chunks(&mut self) {
+    /// Create a transfer file deleting `filename` on the device
+    pub fn delete(id: u16, filename: &str) -> Result<Self, UpdateError> {
+        Ok(Self {
+            id,
+            filename: filename.to_string(),
+            content: Vec::new(),
+            size: 0,
+            chunks: vec![FileDelete::new(id, filename)?.inner().clone()],
+        })
+    }
+
+    fn make_chunks(&mut self) -> Result<(), ChunkError> {
         let total_packages = self.calculate_total_packages();
-        
-        // Add file start chunk
+
+        // Add file start chunk; this rejects files whose package indices exceed 16 bit,
+        // so the casts below cannot wrap
         self.chunks.push(
-            FileStart::new(self.id, 0, total_packages as u16, &self.filename, self.size as u16)
+            FileStart::new(self.id, 0, total_packages as u16, &self.filename, self.size)?
                 .inner()
                 .clone(),
         );
@@ -119,10 +263,11 @@ impl TransferFile {
 
         // Add file end chunk
         self.chunks.push(FileEnd::new(self.id).inner().clone());
+        Ok(())
     }
 
     fn calculate_total_packages(&self) -> usize {
-        (self.size + MAX_CHUNK_SIZE - 1) / MAX_CHUNK_SIZE
+        self.size.div_ceil(MAX_CHUNK_SIZE)
     }
 
     /// Get the next chunk that hasn't been acknowledged
@@ -135,19 +280,23 @@ impl TransferFile {
         self.chunks.iter_mut().find(|c| !c.is_acked())
     }
 
-    /// Acknowledge a chunk
-    pub fn acknowledge_chunk(&mut self, ack: &ChunkAck) {
+    /// Acknowledge a chunk. Returns whether a chunk was newly acknowledged.
+    pub fn acknowledge_chunk(&mut self, ack: &ChunkAck) -> bool {
         if ack.id != self.id {
-            return;
+            return false;
         }
 
         for chunk in &mut self.chunks {
             if chunk.type_ as u8 == ack.type_ && chunk.package == ack.package {
+                if chunk.is_acked() {
+                    return false;
+                }
                 chunk.set_acked(true);
                 debug!("Acked chunk {}", ack);
-                break;
+                return true;
             }
         }
+        false
     }
 
     /// Check if all chunks have been acknowledged
@@ -155,14 +304,41 @@ impl TransferFile {
         self.chunks.iter().all(|c| c.is_acked())
     }
 
+    /// All chunks of this file in transfer order
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
     /// Get total number of chunks
     pub fn total_chunks(&self) -> usize {
         self.chunks.len()
     }
+
+    /// Number of acknowledged chunks
+    pub fn acked_chunks(&self) -> usize {
+        self.chunks.iter().filter(|c| c.is_acked()).count()
+    }
+
+    /// Payload bytes of all data chunks
+    pub fn total_bytes(&self) -> usize {
+        self.data_chunks().map(|c| c.content.len()).sum()
+    }
+
+    /// Payload bytes of all acknowledged data chunks
+    pub fn acked_bytes(&self) -> usize {
+        self.data_chunks()
+            .filter(|c| c.is_acked())
+            .map(|c| c.content.len())
+            .sum()
+    }
+
+    fn data_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.iter().filter(|c| c.type_ == ChunkType::FileChunk)
+    }
 }
 
 /// Send a HID command to the device
-pub fn send_hid_command(device: &HidDevice, command: u8) -> Result<(), UpdateError> {
+pub fn send_hid_command(device: &dyn HidConnection, command: u8) -> Result<(), UpdateError> {
     let mut buffer = vec![HID_REPORT_ID_COMMUNICATION, command];
     buffer.extend(vec![0; 7]);
     
@@ -173,33 +349,128 @@ pub fn send_hid_command(device: &HidDevice, command: u8) -> Result<(), UpdateErr
     Ok(())
 }
 
-/// Perform HID upgrade with multiple files
+/// Perform HID upgrade with multiple files on an open hidapi device.
+///
+/// Kept for existing callers: the transfer blocks the calling thread like
+/// `perform_hid_upgrade_with`. A device handled by a `HidDevice` is updated
+/// with `HidDevice::update`, which runs the transfer off the runtime.
 pub async fn perform_hid_upgrade(
-    device: &HidDevice,
+    device: &hidapi::HidDevice,
     files: Vec<&Path>,
 ) -> Result<(), UpdateError> {
-    info!("Starting device update");
-    
-    // Send prepare update command
-    send_hid_command(device, HID_COMMAND_PREPARE_UPDATE)?;
-    sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME)).await;
+    let transfer_files: Vec<TransferFile> = files
+        .iter()
+        .enumerate()
+        .map(|(i, path)| TransferFile::new(file_id(i)?, path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    upgrade_files(device, transfer_files, &UpdateOptions::default())
+}
 
+/// Perform HID upgrade with multiple files, reporting progress and honoring
+/// the cancellation token of `options`. A cancelled update resets the device
+/// and returns `UpdateError::Cancelled`.
+///
+/// Blocks the calling thread until the update is done; from async code run
+/// it with `tokio::task::spawn_blocking`.
+pub fn perform_hid_upgrade_with(
+    device: &mut dyn HidConnection,
+    files: Vec<&Path>,
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
     // Create transfer files
-    let mut transfer_files: Vec<TransferFile> = files
+    let transfer_files: Vec<TransferFile> = files
         .iter()
         .enumerate()
-        .map(|(i, path)| TransferFile::new(i as u16, path))
+        .map(|(i, path)| TransferFile::new(file_id(i)?, path))
         .collect::<Result<Vec<_>, _>>()?;
 
-    info!("Prepared {} files for update", transfer_files.len());
+    upgrade_files(device, transfer_files, options)
+}
+
+/// Install a firmware bundle. The bundle is checked against the version
+/// information of the device before anything is sent. Blocks the calling
+/// thread like `perform_hid_upgrade_with`.
+pub fn perform_bundle_upgrade_with(
+    device: &mut dyn HidConnection,
+    bundle: &FirmwareBundle,
+    version_info: &VersionInfo,
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
+    bundle.check_device(version_info)?;
+    upgrade_files(device, bundle.transfer_files()?, options)
+}
+
+/// Put the device into update mode, transfer the files and reset the device
+pub(crate) fn upgrade_files(
+    device: &dyn HidConnection,
+    transfer_files: Vec<TransferFile>,
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
+    info!("Starting device update");
+    run_session(device, transfer_files, options)?;
+
+    sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME));
+
+    // Reset device
+    info!("Resetting device");
+    send_hid_command(device, HID_COMMAND_RESET)?;
+
+    info!("Device update complete");
+
+    Ok(())
+}
+
+/// Put the device into update mode, transfer the files and end the transfer
+/// with `Completed`. The device is not reset unless the session is cancelled.
+pub(crate) fn run_session(
+    device: &dyn HidConnection,
+    mut transfer_files: Vec<TransferFile>,
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
+    // Send prepare update command
+    send_hid_command(device, HID_COMMAND_PREPARE_UPDATE)?;
+    sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME));
+
+    info!("Prepared {} files for transfer", transfer_files.len());
+
+    let mut result = transfer(device, &mut transfer_files, options);
+    if result.is_ok() && options.cancel.is_cancelled() {
+        result = Err(UpdateError::Cancelled);
+    }
+    if matches!(result, Err(UpdateError::Cancelled)) {
+        // Leave update mode so the device comes back with its old files
+        info!("Transfer cancelled, resetting device");
+        send_hid_command(device, HID_COMMAND_RESET)?;
+    }
+    result?;
+
+    // Send completion packet
+    sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME));
+
+    let completed = Completed::new();
+    let mut packet = vec![HID_REPORT_ID_TRANSFER];
+    packet.extend_from_slice(&completed.inner().packet());
+
+    device
+        .write(&packet)
+        .map_err(|e| UpdateError::WriteFailed(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Send all files, each until every chunk is acknowledged
+fn transfer(
+    device: &dyn HidConnection,
+    transfer_files: &mut [TransferFile],
+    options: &UpdateOptions,
+) -> Result<(), UpdateError> {
+    let started = Instant::now();
+    let total_bytes: usize = transfer_files.iter().map(|f| f.total_bytes()).sum();
+    let mut bytes_done = 0;
 
-    let cancelled = false;
     let total_files = transfer_files.len();
     for (i, file) in transfer_files.iter_mut().enumerate() {
-        if cancelled {
-            break;
-        }
-
         info!(
             "Sending file {} ({}/{})",
             file.filename,
@@ -207,57 +478,24 @@ pub async fn perform_hid_upgrade(
             total_files
         );
 
-        let mut chunks_sent = 0;
         let total_chunks = file.total_chunks();
+        let mut window = SendWindow::new(total_chunks, options);
+        let mut read_errors = 0;
 
         while !file.is_complete() {
-            // Check for device responses
-            let mut buffer = [0u8; 100];
-            match device.read_timeout(&mut buffer, 1000) {
-                Ok(size) if size > 0 => {
-                    if let Some(msg) = parse_hid_update_message(&buffer[1..]) {
-                        match msg {
-                            HidUpdateMessage::ChunkAck(ack) => {
-                                file.acknowledge_chunk(&ack);
-                                chunks_sent += 1;
-                                debug!("Progress: {}/{}", chunks_sent, total_chunks);
-                            }
-                            HidUpdateMessage::Error(err) => {
-                                error!("Device error: {}", err);
-                                return Err(UpdateError::DeviceError(err.info));
-                            }
-                            HidUpdateMessage::Log(log) => {
-                                match log.level {
-                                    crate::device_messages::LogLevel::Debug => {
-                                        debug!("Device: {}", log.message)
-                                    }
-                                    crate::device_messages::LogLevel::Error => {
-                                        error!("Device: {}", log.message)
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Ok(_) => {
-                    // No data, continue
-                }
-                Err(e) => {
-                    warn!("Read timeout: {}", e);
-                }
+            if options.cancel.is_cancelled() {
+                return Err(UpdateError::Cancelled);
             }
 
-            // Send next chunk
-            if let Some(chunk) = file.get_next_chunk() {
+            // Send new chunks and retransmit expired ones
+            for index in window.due(file, Instant::now())? {
+                let chunk = &file.chunks()[index];
                 let mut packet = vec![HID_REPORT_ID_TRANSFER];
                 packet.extend_from_slice(&chunk.packet());
 
                 debug!(
-                    "Sending chunk ({}...) of file {}",
-                    &packet[..10.min(packet.len())]
-                        .iter()
-                        .map(|b| format!("{:02x}", b))
-                        .collect::<String>(),
+                    "Sending {} of file {}",
+                    dissect(FrameDirection::HostToDevice, &packet),
                     file.filename
                 );
 
@@ -265,31 +503,189 @@ pub async fn perform_hid_upgrade(
                     .write(&packet)
                     .map_err(|e| UpdateError::WriteFailed(e.to_string()))?;
             }
+
+            // Check for device responses until the next retransmit is due
+            let mut buffer = [0u8; 100];
+            let read = device.read_timeout(&mut buffer, window.read_timeout_ms(file, Instant::now()));
+            if read.is_ok() {
+                read_errors = 0;
+            }
+            match read {
+                Ok(size) if size > 0 => {
+                    debug!("HID RX: {}", dissect(FrameDirection::DeviceToHost, &buffer[..size]));
+                    match parse_input_message(&buffer[..size]) {
+                        Ok(HidInput::ChunkAck(ack)) => {
+                            if file.acknowledge_chunk(&ack) {
+                                let bytes = bytes_done + file.acked_bytes();
+                                debug!("Progress: {}/{}", file.acked_chunks(), total_chunks);
+                                options.report(UpdateProgress {
+                                    file: file.filename.clone(),
+                                    file_index: i,
+                                    file_count: total_files,
+                                    chunk: file.acked_chunks(),
+                                    total_chunks,
+                                    bytes,
+                                    total_bytes,
+                                    eta: estimate_remaining(started.elapsed(), bytes, total_bytes),
+                                });
+                            }
+                        }
+                        Ok(HidInput::UpdateError(err)) => {
+                            error!("Device error: {}", err);
+                            return Err(UpdateError::DeviceError(err.info));
+                        }
+                        Ok(HidInput::Log(message)) => {
+                            log!(target: DEVICE_LOG_TARGET, message.level.into(), "Device: {}", message.message);
+                        }
+                        Ok(other) => {
+                            debug!("Ignoring message during update: {}", other);
+                        }
+                        Err(e) => {
+                            debug!("Failed to parse message: {}", e);
+                        }
+                    }
+                }
+                Ok(_) => {
+                    // No data, continue
+                }
+                Err(e) => {
+                    // A transient error costs a retry, a lost device fails every read
+                    read_errors += 1;
+                    if read_errors > options.max_retries {
+                        return Err(UpdateError::ReadFailed(e.to_string()));
+                    }
+                    warn!("Read failed ({}/{}): {}", read_errors, options.max_retries, e);
+                    sleep(MAX_READ_TIMEOUT);
+                }
+            }
         }
 
+        bytes_done += file.total_bytes();
         info!("File {} transfer complete", file.filename);
     }
 
-    if !cancelled {
-        // Send completion packet
-        sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME)).await;
-        
-        let completed = Completed::new();
-        let mut packet = vec![HID_REPORT_ID_TRANSFER];
-        packet.extend_from_slice(&completed.inner().packet());
-        
-        device
-            .write(&packet)
-            .map_err(|e| UpdateError::WriteFailed(e.to_string()))?;
+    Ok(())
+}
+
+/// Run a blocking session on a thread of the blocking pool, so the reads and
+/// writes of the transfer do not stall the runtime. Progress events are sent
+/// back and reported on the calling task. The connection is handed back with
+/// the result.
+pub(crate) async fn run_blocking<F>(
+    mut connection: Box<dyn HidConnection>,
+    options: &UpdateOptions,
+    session: F,
+) -> (Box<dyn HidConnection>, Result<(), UpdateError>)
+where
+    F: FnOnce(&mut dyn HidConnection, &UpdateOptions) -> Result<(), UpdateError> + Send + 'static,
+{
+    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+    let mut blocking_options = options.clone();
+    blocking_options.progress = options.progress.as_ref().map(|_| {
+        Arc::new(move |progress| {
+            let _ = progress_tx.send(progress);
+        }) as ProgressCallback
+    });
+
+    let task = tokio::task::spawn_blocking(move || {
+        let result = session(connection.as_mut(), &blocking_options);
+        (connection, result)
+    });
+    // Ends once the session has dropped its options
+    while let Some(progress) = progress_rx.recv().await {
+        options.report(progress);
+    }
+    task.await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Longest time to block on a read, so cancellation is noticed quickly
+const MAX_READ_TIMEOUT: Duration = Duration::from_millis(100);
 
-        sleep(Duration::from_secs_f64(STATE_CHANGE_SLEEP_TIME)).await;
+/// Transmission state of a chunk that has been sent at least once
+struct SentChunk {
+    at: Instant,
+    attempts: u32,
+}
 
-        // Reset device
-        info!("Resetting device");
-        send_hid_command(device, HID_COMMAND_RESET)?;
+/// Sliding window over the unacknowledged chunks of one file.
+///
+/// Up to `size` unacknowledged chunks are in flight at once. A chunk is sent
+/// again when it is not acknowledged within the retransmit timeout, and the
+/// transfer fails once a chunk has been retransmitted `max_retries` times.
+struct SendWindow {
+    size: usize,
+    retransmit_timeout: Duration,
+    max_retries: u32,
+    sent: Vec<Option<SentChunk>>,
+}
 
-        info!("Device update complete");
+impl SendWindow {
+    fn new(total_chunks: usize, options: &UpdateOptions) -> Self {
+        Self {
+            size: options.window_size,
+            retransmit_timeout: options.retransmit_timeout,
+            max_retries: options.max_retries,
+            sent: (0..total_chunks).map(|_| None).collect(),
+        }
     }
 
-    Ok(())
+    /// Indices of the chunks to send now
+    fn due(&mut self, file: &TransferFile, now: Instant) -> Result<Vec<usize>, UpdateError> {
+        let mut due = Vec::new();
+        let in_window = file
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| !chunk.is_acked())
+            .take(self.size);
+
+        for (index, chunk) in in_window {
+            match &mut self.sent[index] {
+                None => {
+                    self.sent[index] = Some(SentChunk { at: now, attempts: 1 });
+                    due.push(index);
+                }
+                Some(sent) if now.duration_since(sent.at) >= self.retransmit_timeout => {
+                    if sent.attempts > self.max_retries {
+                        return Err(UpdateError::ChunkTimeout {
+                            file: file.filename.clone(),
+                            type_: chunk.type_,
+                            package: chunk.package,
+                            attempts: sent.attempts,
+                        });
+                    }
+                    debug!("Retransmitting {:?} {} of {}", chunk.type_, chunk.package, file.filename);
+                    sent.at = now;
+                    sent.attempts += 1;
+                    due.push(index);
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(due)
+    }
+
+    /// Time to wait for responses before the next retransmit is due
+    fn read_timeout_ms(&self, file: &TransferFile, now: Instant) -> i32 {
+        let next_due = file
+            .chunks()
+            .iter()
+            .zip(&self.sent)
+            .filter(|(chunk, _)| !chunk.is_acked())
+            .filter_map(|(_, sent)| sent.as_ref())
+            .map(|sent| (sent.at + self.retransmit_timeout).saturating_duration_since(now))
+            .min()
+            .unwrap_or(Duration::ZERO);
+
+        next_due.clamp(Duration::from_millis(1), MAX_READ_TIMEOUT).as_millis() as i32
+    }
+}
+
+/// Remaining time assuming the transfer continues at its average rate so far
+fn estimate_remaining(elapsed: Duration, bytes: usize, total_bytes: usize) -> Option<Duration> {
+    if bytes == 0 {
+        return None;
+    }
+    let remaining = total_bytes.saturating_sub(bytes) as f64 / bytes as f64;
+    Some(elapsed.mul_f64(remaining))
 }
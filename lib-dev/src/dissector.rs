@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Human-readable descriptions of raw HID frames.
+//!
+//! `dissect` decodes every report of the protocol: commands the host sends on
+//! report 1, file transfer chunks on report 2 and everything the device sends
+//! (status, status request, version info, chunk acknowledgments, errors and
+//! log lines). Frames start with their report ID, as they are written to and
+//! read from a connection. Device messages are decoded with `parse_input_message`,
+//! so the dissector shows what the handler sees. Malformed frames are described
+//! as far as possible.
+
+use crate::chunks::ChunkType;
+use crate::constants::{HEADER_SIZE, HID_REPORT_ID_COMMUNICATION, HID_REPORT_ID_TRANSFER};
+use crate::hid_commands::{parse_input_message, HidInCommand, HidInput, HidMessageError, HidOutCommand};
+use std::fmt;
+
+/// Direction a frame travels in; the same bytes mean different things each way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    HostToDevice,
+    DeviceToHost,
+}
+
+/// Decoded field of a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameField {
+    pub name: &'static str,
+    pub value: String,
+}
+
+/// Structured description of a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DissectedFrame {
+    pub direction: FrameDirection,
+    pub report_id: Option<u8>,
+    /// Message name, e.g. "SetLed" or "FileStart"; "Unknown" if not recognized
+    pub name: &'static str,
+    pub fields: Vec<FrameField>,
+    /// Why the frame could not be decoded completely
+    pub problem: Option<String>,
+}
+
+impl DissectedFrame {
+    fn new(direction: FrameDirection, report_id: Option<u8>, name: &'static str) -> Self {
+        Self {
+            direction,
+            report_id,
+            name,
+            fields: Vec::new(),
+            problem: None,
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.fields.push(FrameField { name, value: value.to_string() });
+        self
+    }
+
+    fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = Some(problem.into());
+        self
+    }
+
+    /// Value of the field called `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.as_str())
+    }
+}
+
+impl fmt::Display for DissectedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.fields.is_empty() {
+            let fields: Vec<String> = self
+                .fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, field.value))
+                .collect();
+            write!(f, " {{ {} }}", fields.join(", "))?;
+        }
+        if let Some(problem) = &self.problem {
+            write!(f, " [{}]", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex text that is not a frame
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid hex frame: {0}")]
+pub struct InvalidHex(pub String);
+
+/// Parse a frame written as hex, e.g. "01 01 03 0a", "0101030a",
+/// "[01, 01, 03, 0a]" (as logged) or "0x01 0x01"
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, InvalidHex> {
+    let cleaned = text.replace(['[', ']', ','], " ");
+    let mut frame = Vec::new();
+    for token in cleaned.split_whitespace() {
+        let token = token.trim_start_matches("0x").trim_start_matches("0X");
+        let digits = if token.len() == 1 { format!("0{}", token) } else { token.to_string() };
+        if digits.len() % 2 != 0 {
+            return Err(InvalidHex(format!("odd number of digits in {:?}", token)));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            let byte = digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| InvalidHex(format!("{:?} is not hex", token)))?;
+            frame.push(byte);
+        }
+    }
+    if frame.is_empty() {
+        return Err(InvalidHex("no bytes".to_string()));
+    }
+    Ok(frame)
+}
+
+/// Describe a frame; the first byte is the report ID
+pub fn dissect(direction: FrameDirection, frame: &[u8]) -> DissectedFrame {
+    let Some((&report_id, data)) = frame.split_first() else {
+        return DissectedFrame::new(direction, None, "Empty");
+    };
+
+    match direction {
+        FrameDirection::HostToDevice => match report_id {
+            HID_REPORT_ID_COMMUNICATION => dissect_command(report_id, data),
+            HID_REPORT_ID_TRANSFER => dissect_chunk(report_id, data),
+            _ => unknown(direction, report_id, data).problem(format!("unknown report {}", report_id)),
+        },
+        FrameDirection::DeviceToHost => dissect_input(report_id, data),
+    }
+}
+
+fn unknown(direction: FrameDirection, report_id: u8, data: &[u8]) -> DissectedFrame {
+    DissectedFrame::new(direction, Some(report_id), "Unknown").field("data", hex(data))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn truncated(expected: usize, actual: usize) -> String {
+    format!("truncated: expected {} bytes, got {}", expected, actual)
+}
+
+/// Commands on the communication report: `[command, payload x6, counter]`
+fn dissect_command(report_id: u8, data: &[u8]) -> DissectedFrame {
+    const COMMAND_SIZE: usize = 8;
+    let direction = FrameDirection::HostToDevice;
+    let frame = |name| DissectedFrame::new(direction, Some(report_id), name);
+
+    let Some(&command) = data.first() else {
+        return frame("Unknown").problem(truncated(COMMAND_SIZE, 0));
+    };
+    let mut padded = data.to_vec();
+    padded.resize(COMMAND_SIZE, 0);
+    let counter = padded[7];
+
+    let dissected = match command {
+        c if c == HidOutCommand::SetLed as u8 => frame("SetLed")
+            .field("led", padded[1])
+            .field("rgbw", format!("{:02x}{:02x}{:02x}{:02x}", padded[2], padded[3], padded[4], padded[5]))
+            .field("counter", counter),
+        c if c == HidOutCommand::Ping as u8 => frame("Ping").field("counter", counter),
+        c if c == HidOutCommand::PrepareUpdate as u8 => frame("PrepareUpdate"),
+        c if c == HidOutCommand::Reset as u8 => frame("Reset"),
+        c if c == HidOutCommand::UpdateConfig as u8 => frame("UpdateConfig")
+            .field("serial_console", config_value(padded[1]))
+            .field("filesystem", config_value(padded[2]))
+            .field("counter", counter),
+        other => return unknown(direction, report_id, data).problem(format!("unknown command {:#04x}", other)),
+    };
+
+    if data.len() < COMMAND_SIZE {
+        dissected.problem(truncated(COMMAND_SIZE, data.len()))
+    } else {
+        dissected
+    }
+}
+
+fn config_value(value: u8) -> String {
+    match value {
+        0 => "unchanged".to_string(),
+        1 => "off".to_string(),
+        2 => "on".to_string(),
+        other => format!("invalid ({})", other),
+    }
+}
+
+/// File transfer chunks: `[type:2][id:2][total:2][package:2][content]`
+fn dissect_chunk(report_id: u8, data: &[u8]) -> DissectedFrame {
+    let direction = FrameDirection::HostToDevice;
+    if data.len() < HEADER_SIZE {
+        return unknown(direction, report_id, data).problem(truncated(HEADER_SIZE, data.len()));
+    }
+    let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+    let (type_, id, total, package) = (word(0), word(2), word(4), word(6));
+    let content = &data[HEADER_SIZE..];
+
+    let Ok(type_) = ChunkType::try_from(type_) else {
+        return unknown(direction, report_id, data).problem(format!("unknown chunk type {}", type_));
+    };
+    let frame = DissectedFrame::new(direction, Some(report_id), chunk_name(type_));
+
+    match type_ {
+        ChunkType::FileStart => {
+            let frame = frame.field("file", id).field("packages", total);
+            match split_filename(content) {
+                Some((filename, rest)) => {
+                    let frame = frame.field("filename", filename);
+                    match rest.split_first() {
+                        Some((&width, size)) if (width == 2 || width == 4) && size.len() >= width as usize => {
+                            let mut bytes = [0u8; 4];
+                            bytes[..width as usize].copy_from_slice(&size[..width as usize]);
+                            frame.field("size", u32::from_le_bytes(bytes))
+                        }
+                        _ => frame.problem("invalid size"),
+                    }
+                }
+                None => frame.problem("invalid filename"),
+            }
+        }
+        ChunkType::FileChunk => frame
+            .field("file", id)
+            .field("package", package)
+            .field("packages", total)
+            .field("bytes", content.len()),
+        ChunkType::FileEnd => frame.field("file", id),
+        ChunkType::Complete => frame,
+        ChunkType::FileDelete => {
+            let frame = frame.field("file", id);
+            match split_filename(content) {
+                Some((filename, _)) => frame.field("filename", filename),
+                None => frame.problem("invalid filename"),
+            }
+        }
+    }
+}
+
+fn chunk_name(type_: ChunkType) -> &'static str {
+    match type_ {
+        ChunkType::FileStart => "FileStart",
+        ChunkType::FileChunk => "FileChunk",
+        ChunkType::FileEnd => "FileEnd",
+        ChunkType::Complete => "Complete",
+        ChunkType::FileDelete => "FileDelete",
+    }
+}
+
+/// Length prefixed filename and the bytes after it
+fn split_filename(content: &[u8]) -> Option<(String, &[u8])> {
+    let (&length, rest) = content.split_first()?;
+    let name = rest.get(..length as usize)?;
+    Some((String::from_utf8_lossy(name).into_owned(), &rest[length as usize..]))
+}
+
+/// Text up to the first NUL byte
+fn text(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// Everything the device sends, decoded with `parse_input_message`
+fn dissect_input(report_id: u8, data: &[u8]) -> DissectedFrame {
+    let direction = FrameDirection::DeviceToHost;
+    let frame = |name| DissectedFrame::new(direction, Some(report_id), name);
+    if data.is_empty() {
+        return frame("Unknown").problem(truncated(2, 0));
+    }
+
+    let mut report = vec![report_id];
+    report.extend_from_slice(data);
+    match parse_input_message(&report) {
+        Ok(HidInput::Status(status)) => frame("Status")
+            .field("button", status.button())
+            .field("triggered", status.triggered())
+            .field("longpressed", status.longpressed())
+            .field("pressed", status.pressed())
+            .field("released", status.released()),
+        Ok(HidInput::StatusRequest(_)) => frame("StatusRequest"),
+        Ok(HidInput::VersionInfo(version_info)) => frame("VersionInfo")
+            .field("version", version_info.version())
+            .field("hardware", version_info.hardware_type()),
+        Ok(HidInput::ChunkAck(ack)) => {
+            let chunk = match ChunkType::try_from(ack.type_ as u16) {
+                Ok(type_) => chunk_name(type_).to_string(),
+                Err(other) => format!("unknown ({})", other),
+            };
+            frame("ChunkAck")
+                .field("file", ack.id)
+                .field("package", ack.package)
+                .field("chunk", chunk)
+        }
+        Ok(HidInput::UpdateError(error)) => frame("UpdateError").field("message", text(error.info.as_bytes())),
+        Ok(HidInput::Log(log)) => frame("Log")
+            .field("level", format!("{:?}", log.level).to_lowercase())
+            .field("message", log.message),
+        Err(HidMessageError::UnknownCommand(command)) => {
+            unknown(direction, report_id, data).problem(format!("unknown message {:#04x}", command))
+        }
+        Err(e) => {
+            let (name, offset) = input_name(data);
+            match e {
+                // The parsers count from the start of the message, after the command byte
+                HidMessageError::InvalidLength { expected, actual } => {
+                    frame(name).problem(truncated(expected + offset, actual + offset))
+                }
+                e => frame(name).problem(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Name of a message the device sent and the offset its parser starts at
+fn input_name(data: &[u8]) -> (&'static str, usize) {
+    match data.get(0..2) {
+        Some(b"AK") => ("ChunkAck", 0),
+        Some(b"ER") => ("UpdateError", 0),
+        Some(b"LD") | Some(b"LE") => ("Log", 0),
+        _ => match data[0] {
+            c if c == HidInCommand::Status as u8 => ("Status", 1),
+            c if c == HidInCommand::StatusRequest as u8 => ("StatusRequest", 1),
+            c if c == HidInCommand::VersionInfo as u8 => ("VersionInfo", 1),
+            _ => ("Unknown", 1),
+        },
+    }
+}
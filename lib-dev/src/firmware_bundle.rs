@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Firmware bundles.
+//!
+//! A bundle is a tar archive, optionally gzip compressed, with a `manifest.json`
+//! at its root. The manifest lists the hardware types and firmware versions the
+//! bundle may be installed on and the files to write or delete, each written
+//! file with its SHA-256 digest:
+//!
+//! ```json
+//! {
+//!   "hardware_types": ["ten-button-usb"],
+//!   "min_version": "1.0.0",
+//!   "max_version": "1.9.99",
+//!   "files": [
+//!     { "action": "write", "path": "main.py", "sha256": "9f86d0..." },
+//!     { "action": "delete", "path": "old.py" }
+//!   ]
+//! }
+//! ```
+
+use crate::chunks::file_id;
+use crate::device_update::{TransferFile, UpdateError};
+use crate::hid_commands::{HardwareType, VersionInfo};
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// Name of the manifest inside a bundle
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Errors while loading or checking a firmware bundle
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Bundle has no {MANIFEST_NAME}")]
+    MissingManifest,
+
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("File {0} listed in the manifest is missing from the bundle")]
+    MissingFile(String),
+
+    #[error("File {0} is listed more than once in the manifest")]
+    DuplicateFile(String),
+
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Bundle does not support hardware type {0}")]
+    UnsupportedHardware(HardwareType),
+
+    #[error("Bundle requires firmware {min} to {max}, device runs {actual}")]
+    UnsupportedVersion {
+        actual: String,
+        min: String,
+        max: String,
+    },
+
+    #[error("Device did not report its firmware version")]
+    UnknownDeviceVersion,
+}
+
+/// File operation listed in a manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum BundleFile {
+    Write { path: String, sha256: String },
+    Delete { path: String },
+}
+
+impl BundleFile {
+    pub fn path(&self) -> &str {
+        match self {
+            BundleFile::Write { path, .. } | BundleFile::Delete { path } => path,
+        }
+    }
+}
+
+/// Manifest of a firmware bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Hardware types the bundle may be installed on; empty for any
+    #[serde(default)]
+    pub hardware_types: Vec<HardwareType>,
+    /// Oldest firmware the bundle may be installed over, inclusive
+    #[serde(default)]
+    pub min_version: Option<String>,
+    /// Newest firmware the bundle may be installed over, inclusive
+    #[serde(default)]
+    pub max_version: Option<String>,
+    pub files: Vec<BundleFile>,
+}
+
+impl BundleManifest {
+    /// Check that the bundle may be installed on a device reporting `version_info`
+    pub fn check_device(&self, version_info: &VersionInfo) -> Result<(), BundleError> {
+        let hardware_type = version_info.hardware_type();
+        if !self.hardware_types.is_empty() && !self.hardware_types.contains(&hardware_type) {
+            return Err(BundleError::UnsupportedHardware(hardware_type));
+        }
+
+        let actual = version_info.version_triple();
+        let min = self.min_version.as_deref().map(parse_version).transpose()?;
+        let max = self.max_version.as_deref().map(parse_version).transpose()?;
+        if min.is_some_and(|min| actual < min) || max.is_some_and(|max| actual > max) {
+            return Err(BundleError::UnsupportedVersion {
+                actual: version_info.version(),
+                min: self.min_version.clone().unwrap_or_else(|| "any".to_string()),
+                max: self.max_version.clone().unwrap_or_else(|| "any".to_string()),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Parse a "major.minor.patch" version
+fn parse_version(version: &str) -> Result<(u8, u8, u8), BundleError> {
+    let invalid = || BundleError::InvalidManifest(format!("invalid version {:?}", version));
+    let parts = version
+        .split('.')
+        .map(|part| part.parse::<u8>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [major, minor, patch] => Ok((major, minor, patch)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Hex encoded SHA-256 digest
+fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A loaded and verified firmware bundle
+#[derive(Debug, Clone)]
+pub struct FirmwareBundle {
+    manifest: BundleManifest,
+    contents: BTreeMap<String, Vec<u8>>,
+}
+
+impl FirmwareBundle {
+    /// Load a bundle from a `.tar` or `.tar.gz` file
+    pub fn open(path: &Path) -> Result<Self, BundleError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Load a bundle from the bytes of a tar archive, gzip compressed or not.
+    /// Every file to write must be present and match its digest.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, BundleError> {
+        let mut entries = if data.starts_with(&[0x1f, 0x8b]) {
+            read_tar(GzDecoder::new(data))?
+        } else {
+            read_tar(Cursor::new(data))?
+        };
+
+        let manifest = entries.remove(MANIFEST_NAME).ok_or(BundleError::MissingManifest)?;
+        let manifest: BundleManifest = serde_json::from_slice(&manifest)
+            .map_err(|e| BundleError::InvalidManifest(e.to_string()))?;
+
+        let mut listed = HashSet::new();
+        if let Some(file) = manifest.files.iter().find(|file| !listed.insert(file.path())) {
+            return Err(BundleError::DuplicateFile(file.path().to_string()));
+        }
+
+        let mut contents = BTreeMap::new();
+        for file in &manifest.files {
+            if let BundleFile::Write { path, sha256 } = file {
+                let content = entries
+                    .remove(path)
+                    .ok_or_else(|| BundleError::MissingFile(path.clone()))?;
+                let actual = sha256_hex(&content);
+                if !actual.eq_ignore_ascii_case(sha256) {
+                    return Err(BundleError::ChecksumMismatch {
+                        file: path.clone(),
+                        expected: sha256.clone(),
+                        actual,
+                    });
+                }
+                contents.insert(path.clone(), content);
+            }
+        }
+
+        for name in entries.keys() {
+            warn!("Ignoring {} in bundle, it is not listed in the manifest", name);
+        }
+
+        Ok(Self { manifest, contents })
+    }
+
+    pub fn manifest(&self) -> &BundleManifest {
+        &self.manifest
+    }
+
+    /// Content of a file to write
+    pub fn content(&self, path: &str) -> Option<&[u8]> {
+        self.contents.get(path).map(Vec::as_slice)
+    }
+
+    /// Check that the bundle may be installed on a device reporting `version_info`
+    pub fn check_device(&self, version_info: &VersionInfo) -> Result<(), BundleError> {
+        self.manifest.check_device(version_info)
+    }
+
+    /// Transfer files in manifest order
+    pub fn transfer_files(&self) -> Result<Vec<TransferFile>, UpdateError> {
+        self.manifest
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| match file {
+                BundleFile::Write { path, .. } => {
+                    TransferFile::from_content(file_id(i)?, path, self.contents[path].clone())
+                }
+                BundleFile::Delete { path } => TransferFile::delete(file_id(i)?, path),
+            })
+            .collect()
+    }
+}
+
+/// Read all regular files of a tar archive, keyed by their normalized path
+fn read_tar<R: Read>(reader: R) -> Result<BTreeMap<String, Vec<u8>>, BundleError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        debug!("Bundle entry {} ({} bytes)", path, content.len());
+        entries.insert(path, content);
+    }
+    Ok(entries)
+}
@@ -1,10 +1,13 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
+use crate::device_messages::{ChunkAck, LogMessage, UpdateError};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Hardware types for the Macropad
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 #[repr(u8)]
 pub enum HardwareType {
     Unknown = 0x00,
@@ -82,7 +85,7 @@ pub enum HidMessageError {
 }
 
 /// Status message from device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Status {
     buffer: [u8; 6],
 }
@@ -146,7 +149,7 @@ impl fmt::Display for Status {
 }
 
 /// Version information from device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VersionInfo {
     buffer: [u8; 6],
 }
@@ -156,6 +159,11 @@ impl VersionInfo {
         format!("{}.{}.{}", self.buffer[0], self.buffer[1], self.buffer[2])
     }
 
+    /// Major, minor and patch version, ordered for comparisons
+    pub fn version_triple(&self) -> (u8, u8, u8) {
+        (self.buffer[0], self.buffer[1], self.buffer[2])
+    }
+
     pub fn hardware_type(&self) -> HardwareType {
         HardwareType::from(self.buffer[3])
     }
@@ -189,7 +197,7 @@ impl fmt::Display for VersionInfo {
 }
 
 /// Status request from device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StatusRequest;
 
 impl HidInputMessage for StatusRequest {
@@ -204,8 +212,35 @@ impl fmt::Display for StatusRequest {
     }
 }
 
-/// Parse incoming HID message
-pub fn parse_input_message(buffer: &[u8]) -> Result<Box<dyn std::any::Any>, HidMessageError> {
+/// Any message received from the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HidInput {
+    Status(Status),
+    StatusRequest(StatusRequest),
+    VersionInfo(VersionInfo),
+    /// Acknowledgment of a transferred chunk ("AK")
+    ChunkAck(ChunkAck),
+    /// Error reported during an update ("ER")
+    UpdateError(UpdateError),
+    /// Debug or error log line ("LD"/"LE")
+    Log(LogMessage),
+}
+
+impl fmt::Display for HidInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HidInput::Status(status) => status.fmt(f),
+            HidInput::StatusRequest(request) => request.fmt(f),
+            HidInput::VersionInfo(version_info) => version_info.fmt(f),
+            HidInput::ChunkAck(ack) => write!(f, "Chunk Ack: {}", ack),
+            HidInput::UpdateError(error) => error.fmt(f),
+            HidInput::Log(log) => write!(f, "Log {}", log),
+        }
+    }
+}
+
+/// Parse incoming HID message; `buffer` starts with the report ID
+pub fn parse_input_message(buffer: &[u8]) -> Result<HidInput, HidMessageError> {
     if buffer.len() < 2 {
         return Err(HidMessageError::InvalidLength {
             expected: 2,
@@ -213,10 +248,18 @@ pub fn parse_input_message(buffer: &[u8]) -> Result<Box<dyn std::any::Any>, HidM
         });
     }
 
+    // Update channel messages are identified by two ASCII characters
+    match buffer.get(1..3) {
+        Some(b"AK") => return Ok(HidInput::ChunkAck(ChunkAck::from_buffer(&buffer[1..])?)),
+        Some(b"ER") => return Ok(HidInput::UpdateError(UpdateError::from_buffer(&buffer[1..])?)),
+        Some(b"LD") | Some(b"LE") => return Ok(HidInput::Log(LogMessage::from_buffer(&buffer[1..])?)),
+        _ => {}
+    }
+
     match buffer[1] {
-        0x99 => Ok(Box::new(VersionInfo::from_buffer(&buffer[2..])?)),
-        0x01 => Ok(Box::new(Status::from_buffer(&buffer[2..])?)),
-        0x02 => Ok(Box::new(StatusRequest::from_buffer(&buffer[2..])?)),
+        0x99 => Ok(HidInput::VersionInfo(VersionInfo::from_buffer(&buffer[2..])?)),
+        0x01 => Ok(HidInput::Status(Status::from_buffer(&buffer[2..])?)),
+        0x02 => Ok(HidInput::StatusRequest(StatusRequest::from_buffer(&buffer[2..])?)),
         cmd => Err(HidMessageError::UnknownCommand(cmd)),
     }
 }
@@ -234,9 +277,16 @@ pub enum LedColor {
     Magenta,
     Orange,
     Purple,
+    /// Arbitrary color with explicit red, green, blue and white channels
+    Rgbw { r: u8, g: u8, b: u8, w: u8 },
 }
 
 impl LedColor {
+    /// Create a color from explicit channel values
+    pub fn rgbw(r: u8, g: u8, b: u8, w: u8) -> Self {
+        LedColor::Rgbw { r, g, b, w }
+    }
+
     /// Returns RGBW values
     pub fn to_rgbw(&self) -> [u8; 4] {
         match self {
@@ -250,8 +300,26 @@ impl LedColor {
             LedColor::Magenta => [0x0A, 0x00, 0x0A, 0x00],
             LedColor::Orange => [0x0A, 0x08, 0x00, 0x00],
             LedColor::Purple => [0x09, 0x00, 0x09, 0x00],
+            LedColor::Rgbw { r, g, b, w } => [*r, *g, *b, *w],
         }
     }
+
+    /// Returns RGBW values scaled by `brightness`; 1.0 leaves the color unchanged
+    /// and channels saturate at 0xFF
+    pub fn to_rgbw_scaled(&self, brightness: f32) -> [u8; 4] {
+        let brightness = brightness.max(0.0);
+        self.to_rgbw()
+            .map(|channel| (channel as f32 * brightness).round().min(255.0) as u8)
+    }
+}
+
+/// Scheduling class of an output command in the write queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WritePriority {
+    /// Pings, firmware and settings commands; written first
+    Control,
+    /// Cosmetic updates such as LED colors
+    Led,
 }
 
 /// Base trait for HID output commands
@@ -260,6 +328,16 @@ pub trait HidOutputCommand: fmt::Debug + Send + Sync {
     fn report_id(&self) -> u8 {
         1
     }
+
+    fn priority(&self) -> WritePriority {
+        WritePriority::Control
+    }
+
+    /// Queued commands with the same key are replaced by the newest one,
+    /// which then takes the place of the first. None keeps every command.
+    fn coalesce_key(&self) -> Option<u8> {
+        None
+    }
 }
 
 /// Set LED command
@@ -267,12 +345,29 @@ pub trait HidOutputCommand: fmt::Debug + Send + Sync {
 pub struct SetLed {
     id: u8,
     color: LedColor,
+    brightness: f32,
     counter: u8,
 }
 
 impl SetLed {
     pub fn new(id: u8, color: LedColor) -> Self {
-        Self { id, color, counter: 0 }
+        Self { id, color, brightness: 1.0, counter: 0 }
+    }
+
+    /// Scale all color channels, see `LedColor::to_rgbw_scaled`
+    pub fn with_brightness(mut self, brightness: f32) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    /// ID of the LED to set
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// RGBW values as written to the device, brightness applied
+    pub fn rgbw(&self) -> [u8; 4] {
+        self.color.to_rgbw_scaled(self.brightness)
     }
 
     pub fn with_counter(mut self, counter: u8) -> Self {
@@ -283,7 +378,7 @@ impl SetLed {
 
 impl HidOutputCommand for SetLed {
     fn to_buffer(&self) -> Vec<u8> {
-        let color = self.color.to_rgbw();
+        let color = self.rgbw();
         vec![
             HidOutCommand::SetLed as u8,
             self.id,
@@ -295,6 +390,15 @@ impl HidOutputCommand for SetLed {
             self.counter,
         ]
     }
+
+    fn priority(&self) -> WritePriority {
+        WritePriority::Led
+    }
+
+    /// Only the newest color of an LED matters
+    fn coalesce_key(&self) -> Option<u8> {
+        Some(self.id)
+    }
 }
 
 impl fmt::Display for SetLed {
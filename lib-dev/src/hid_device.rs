@@ -1,21 +1,192 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
-use crate::PING_LOOP_TIME_SECONDS;
-use crate::hid_commands::{HidOutputCommand, SimpleCommand, Status, StatusRequest, parse_input_message};
-use hidapi::{HidApi, HidDevice as RawHidDevice};
-use log::{debug, error, info, warn};
+use crate::device_events::{DeviceEvent, DeviceEvents, EVENT_CAPACITY};
+use crate::device_fs::{perform_fs_batch_with, DeviceFs, FsBatch};
+use crate::device_messages::LogMessage;
+use crate::device_settings::DeviceSettings;
+use crate::device_update::{
+    perform_hid_upgrade_with, run_blocking, upgrade_files, UpdateError, UpdateOptions,
+};
+use crate::dissector::{dissect, FrameDirection};
+use crate::firmware_bundle::{BundleError, FirmwareBundle};
+use crate::hid_commands::{
+    HardwareType, HidInput, HidOutputCommand, LedColor, SetLed, SimpleCommand, Status,
+    StatusRequest, VersionInfo, parse_input_message,
+};
+use crate::io_thread::IoThread;
+use crate::led_framebuffer::LedFramebuffer;
+use crate::transport::{DeviceDescriptor, HidApiTransport, HidConnection, HidTransport};
+use crate::watchdog::{LinkStats, WatchdogConfig};
+use crate::write_queue::{WriteQueue, WriteQueueConfig};
+use log::{debug, error, info, log, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
 use tokio::time::sleep;
 
-/// Device identification information
-#[derive(Debug, Clone)]
+/// Rule selecting devices to connect to. Every criterion that is set has to
+/// match; a zero vendor or product ID matches any.
+#[derive(Debug, Clone, Default)]
 pub struct DeviceInfo {
     pub vendor_id: u16,
     pub product_id: u16,
     pub serial_number: Option<String>,
+    /// Platform path of the device, to tell devices without serial number apart
+    pub path: Option<String>,
+    /// Pattern for the product string; `*` matches any text, `?` one character,
+    /// letter case is ignored
+    pub product_pattern: Option<String>,
+    pub usage_page: Option<u16>,
+    pub interface_number: Option<i32>,
+    /// Hardware type the device has to report; checked once it sent its version
+    pub hardware_type: Option<HardwareType>,
+    /// Rules with a higher priority are tried first
+    pub priority: i32,
+}
+
+impl DeviceInfo {
+    /// Rule matching a vendor and product ID
+    pub fn new(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            ..Default::default()
+        }
+    }
+
+    /// Rule matching the device with the given serial number only
+    pub fn serial(serial_number: impl Into<String>) -> Self {
+        Self {
+            serial_number: Some(serial_number.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.serial_number = Some(serial_number.into());
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_product_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.product_pattern = Some(pattern.into());
+        self
+    }
+
+    pub fn with_usage_page(mut self, usage_page: u16) -> Self {
+        self.usage_page = Some(usage_page);
+        self
+    }
+
+    pub fn with_interface_number(mut self, interface_number: i32) -> Self {
+        self.interface_number = Some(interface_number);
+        self
+    }
+
+    pub fn with_hardware_type(mut self, hardware_type: HardwareType) -> Self {
+        self.hardware_type = Some(hardware_type);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Check whether an enumerated device matches this rule. The hardware type
+    /// is not known before connecting, see `accepts_hardware`. A rule without
+    /// IDs needs at least one other criterion.
+    pub fn matches(&self, descriptor: &DeviceDescriptor) -> bool {
+        let has_ids = self.vendor_id != 0 || self.product_id != 0;
+        let has_criteria = self.serial_number.is_some()
+            || self.path.is_some()
+            || self.product_pattern.is_some()
+            || self.usage_page.is_some()
+            || self.interface_number.is_some();
+        if !has_ids && !has_criteria {
+            return false;
+        }
+
+        (self.vendor_id == 0 || self.vendor_id == descriptor.vendor_id)
+            && (self.product_id == 0 || self.product_id == descriptor.product_id)
+            && self
+                .serial_number
+                .as_ref()
+                .is_none_or(|serial| descriptor.serial_number.as_deref() == Some(serial.as_str()))
+            && self.path.as_ref().is_none_or(|path| *path == descriptor.path)
+            && self.product_pattern.as_ref().is_none_or(|pattern| {
+                descriptor
+                    .product
+                    .as_ref()
+                    .is_some_and(|product| glob_matches(pattern, product))
+            })
+            && self.usage_page.is_none_or(|page| page == descriptor.usage_page)
+            && self
+                .interface_number
+                .is_none_or(|interface| interface == descriptor.interface_number)
+    }
+
+    /// Check the hardware type a device reported against this rule
+    pub fn accepts_hardware(&self, hardware_type: HardwareType) -> bool {
+        self.hardware_type.is_none_or(|expected| expected == hardware_type)
+    }
+}
+
+/// Case-insensitive wildcard match supporting `*` and `?`
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Greedy matching, going back to the last `*` on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Check whether an enumerated device is selected by the given rules.
+/// Without rules any device with "mutenix" in its product string is selected.
+pub fn is_selected(device_info: &[DeviceInfo], descriptor: &DeviceDescriptor) -> bool {
+    select_rule(device_info, descriptor).is_some()
+        || (device_info.is_empty()
+            && descriptor
+                .product
+                .as_ref()
+                .is_some_and(|product| product.to_lowercase().contains("mutenix")))
+}
+
+/// The rule with the highest priority matching an enumerated device; the
+/// first one of the list among rules with equal priority
+pub fn select_rule<'a>(device_info: &'a [DeviceInfo], descriptor: &DeviceDescriptor) -> Option<&'a DeviceInfo> {
+    device_info
+        .iter()
+        .filter(|info| info.matches(descriptor))
+        .fold(None, |best: Option<&DeviceInfo>, info| match best {
+            Some(best) if best.priority >= info.priority => Some(best),
+            _ => Some(info),
+        })
 }
 
 /// Connection state
@@ -23,9 +194,21 @@ pub struct DeviceInfo {
 pub enum ConnectionState {
     Disconnected,
     Connected,
+    /// Connected, but the device recently missed pings
+    Degraded,
+    /// A firmware update or filesystem session is running, regular communication is paused
+    Updating,
+    /// The device stopped answering pings; the connection is dropped and opened again
     Error,
 }
 
+impl ConnectionState {
+    /// True if commands can be sent, including over a degraded connection
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected | ConnectionState::Degraded)
+    }
+}
+
 /// Hardware state information
 #[derive(Debug, Clone)]
 pub struct HardwareState {
@@ -33,6 +216,11 @@ pub struct HardwareState {
     pub serial_number: Option<String>,
     pub manufacturer: Option<String>,
     pub product: Option<String>,
+    /// Firmware version as reported by the device, e.g. "1.2.3"
+    pub firmware_version: Option<String>,
+    pub hardware_type: Option<HardwareType>,
+    /// Latency and error statistics of the connection
+    pub link: LinkStats,
 }
 
 impl Default for HardwareState {
@@ -42,6 +230,9 @@ impl Default for HardwareState {
             serial_number: None,
             manufacturer: None,
             product: None,
+            firmware_version: None,
+            hardware_type: None,
+            link: LinkStats::default(),
         }
     }
 }
@@ -51,25 +242,69 @@ impl Default for HardwareState {
 pub enum DeviceMessage {
     Status(Status),
     StatusRequest(StatusRequest),
+    VersionInfo(VersionInfo),
+    /// Log line the firmware sent ("LD" debug or "LE" error frame)
+    Log(LogMessage),
+}
+
+/// Log target of messages the firmware sends, so they can be filtered separately
+pub const DEVICE_LOG_TARGET: &str = "mutenix_hid::device";
+
+/// Time `read_once` waits for a report before checking whether to stop
+const READ_WAIT: Duration = Duration::from_millis(100);
+
+/// Interval at which `wait_for_restart` checks for the new connection
+const RESTART_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Connection of a device at the moment it was told to restart
+struct RestartMark {
+    connections: u64,
+    serial_number: Option<String>,
+}
+
+/// Ping waiting for the device to show that it is alive
+struct PendingPing {
+    counter: u8,
+    sent: Instant,
+    /// A report arrived since the ping was sent
+    answered: bool,
+    /// The round-trip time was taken from a VersionInfo
+    timed: bool,
 }
 
-/// Callback type for incoming device messages
-pub type MessageCallback = Arc<dyn Fn(DeviceMessage) + Send + Sync>;
 
 /// HID Device handler with async communication
 pub struct HidDevice {
     state: Arc<RwLock<HardwareState>>,
     device_info: Vec<DeviceInfo>,
-    device: Arc<Mutex<Option<RawHidDevice>>>,
-    callbacks: Arc<RwLock<Vec<MessageCallback>>>,
-    send_buffer: mpsc::UnboundedSender<(Box<dyn HidOutputCommand + Send>, tokio::sync::oneshot::Sender<Result<usize, HidError>>)>,
-    send_receiver: Arc<Mutex<mpsc::UnboundedReceiver<(Box<dyn HidOutputCommand + Send>, tokio::sync::oneshot::Sender<Result<usize, HidError>>)>>>,
-    last_ping: Arc<Mutex<Instant>>,
-    running: Arc<RwLock<bool>>,
+    transport: Arc<dyn HidTransport>,
+    /// I/O thread owning the currently open connection
+    io: Arc<RwLock<Option<Arc<IoThread>>>>,
+    events: broadcast::Sender<DeviceEvent>,
+    leds: Arc<Mutex<LedFramebuffer>>,
+    queue: Arc<WriteQueue>,
+    watchdog: WatchdogConfig,
+    /// The ping waiting for its answer
+    pending_ping: Arc<Mutex<Option<PendingPing>>>,
+    /// Delay before reopening an unresponsive device
+    reconnect_delay: Arc<Mutex<Duration>>,
+    /// Cancels the running `process()`
+    shutdown: Arc<Mutex<CancellationToken>>,
+    /// Held by `process()` until it has shut down
+    process_lock: Arc<Mutex<()>>,
+    updating: Arc<RwLock<bool>>,
+    version_info: Arc<RwLock<Option<VersionInfo>>>,
+    /// Number of connections made so far, to notice a re-enumeration
+    connections: Arc<RwLock<u64>>,
+    /// The device currently connected
+    descriptor: Arc<RwLock<Option<DeviceDescriptor>>>,
+    /// Paths of devices that reported a hardware type their rule does not
+    /// accept; an entry is dropped once its device is no longer enumerated
+    rejected: Arc<Mutex<HashSet<String>>>,
 }
 
 /// Errors that can occur with HID operations
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum HidError {
     #[error("Device not connected")]
     NotConnected,
@@ -85,25 +320,92 @@ pub enum HidError {
     
     #[error("HID API error: {0}")]
     HidApiError(String),
+
+    #[error("Firmware update in progress")]
+    UpdateInProgress,
+
+    #[error("Device did not reconnect within {0:?}")]
+    ReconnectTimeout(Duration),
+
+    #[error("Device {0} connected instead of the restarted one")]
+    UnexpectedDevice(String),
+
+    #[error("Device reports hardware type {0}, which its selection rule does not accept")]
+    HardwareMismatch(HardwareType),
+
+    #[error("Device did not answer {0} pings")]
+    Unresponsive(u32),
+
+    #[error("Device handler stopped")]
+    Stopped,
+
+    #[error("Device handler is already running")]
+    AlreadyRunning,
+
+    #[error("Write queue is full")]
+    QueueFull,
+
+    #[error("Replaced by a newer command before it was written")]
+    Superseded,
+
+    #[error("No device setting to change")]
+    NoSettings,
+}
+
+/// Encode a command as report, starting with the report ID
+fn report_buffer(command: &dyn HidOutputCommand) -> Vec<u8> {
+    let mut buffer = vec![command.report_id()];
+    buffer.extend_from_slice(&command.to_buffer());
+
+    debug!("HID TX: {}", dissect(FrameDirection::HostToDevice, &buffer));
+
+    buffer
 }
 
 impl HidDevice {
     /// Create a new HID device handler
     pub fn new(device_info: Vec<DeviceInfo>) -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
-        
+        Self::with_transport(device_info, Arc::new(HidApiTransport::new()))
+    }
+
+    /// Create a new HID device handler on top of a custom transport
+    pub fn with_transport(device_info: Vec<DeviceInfo>, transport: Arc<dyn HidTransport>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
         Self {
             state: Arc::new(RwLock::new(HardwareState::default())),
             device_info,
-            device: Arc::new(Mutex::new(None)),
-            callbacks: Arc::new(RwLock::new(Vec::new())),
-            send_buffer: tx,
-            send_receiver: Arc::new(Mutex::new(rx)),
-            last_ping: Arc::new(Mutex::new(Instant::now())),
-            running: Arc::new(RwLock::new(true)),
+            transport,
+            io: Arc::new(RwLock::new(None)),
+            events,
+            leds: Arc::new(Mutex::new(LedFramebuffer::new())),
+            queue: Arc::new(WriteQueue::new(WriteQueueConfig::default())),
+            watchdog: WatchdogConfig::default(),
+            pending_ping: Arc::new(Mutex::new(None)),
+            reconnect_delay: Arc::new(Mutex::new(WatchdogConfig::default().reconnect_delay())),
+            shutdown: Arc::new(Mutex::new(CancellationToken::new())),
+            process_lock: Arc::new(Mutex::new(())),
+            updating: Arc::new(RwLock::new(false)),
+            version_info: Arc::new(RwLock::new(None)),
+            connections: Arc::new(RwLock::new(0)),
+            descriptor: Arc::new(RwLock::new(None)),
+            rejected: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
+    /// Monitor the connection with the given watchdog settings
+    pub fn with_watchdog(mut self, watchdog: WatchdogConfig) -> Self {
+        self.reconnect_delay = Arc::new(Mutex::new(watchdog.reconnect_delay()));
+        self.watchdog = watchdog;
+        self
+    }
+
+    /// Limit the commands waiting to be written, see `WriteQueueConfig`
+    pub fn with_write_queue(mut self, config: WriteQueueConfig) -> Self {
+        self.queue = Arc::new(WriteQueue::new(config));
+        self
+    }
+
     /// Create a new HID device handler that searches for any mutenix device
     pub fn new_auto() -> Self {
         Self::new(Vec::new())
@@ -114,44 +416,341 @@ impl HidDevice {
         self.state.read().await.clone()
     }
 
-    /// Register a callback for incoming device messages (Status and StatusRequest only)
+    /// Subscribe to the events of this device. Only events published after
+    /// subscribing are received; a subscriber that falls behind skips the
+    /// oldest events instead of blocking the device.
+    pub fn subscribe(&self) -> DeviceEvents {
+        DeviceEvents::new(self.events.subscribe())
+    }
+
+    /// Register a callback for incoming device messages (Status, StatusRequest, VersionInfo and Log).
+    /// The callback runs on a task of its own, fed by `subscribe`.
     pub async fn register_callback<F>(&self, callback: F)
     where
         F: Fn(DeviceMessage) + Send + Sync + 'static,
     {
-        let mut callbacks = self.callbacks.write().await;
-        callbacks.push(Arc::new(callback));
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                if let Some(message) = event.message() {
+                    callback(message);
+                }
+            }
+        });
+    }
+
+    /// Hand an event to all subscribers; without subscribers it is dropped
+    fn publish(&self, event: DeviceEvent) {
+        let _ = self.events.send(event);
     }
 
-    /// Send a HID command
+    /// Send a HID command. Commands sent before `process()` runs are queued,
+    /// within the limits of the write queue; after `stop` they fail with
+    /// `HidError::Stopped`.
     pub async fn send_command<C: HidOutputCommand + Send + 'static>(&self, command: C) -> Result<usize, HidError> {
         let (tx, rx) = tokio::sync::oneshot::channel();
-        self.send_buffer.send((Box::new(command), tx))
-            .map_err(|_| HidError::NotConnected)?;
-        rx.await.map_err(|_| HidError::NotConnected)?
+        self.queue.push(Box::new(command), tx).await?;
+        // The responder is dropped if the write loop is cancelled while sending
+        rx.await.map_err(|_| HidError::Stopped)?
+    }
+
+    /// Set an LED. The report is only written if the device does not show this
+    /// color already; returns `Ok(0)` if nothing had to be written or a newer
+    /// color of the LED replaced it in the write queue.
+    pub async fn set_led(&self, command: SetLed) -> Result<usize, HidError> {
+        let (id, rgbw) = (command.id(), command.rgbw());
+        if !self.leds.lock().await.set(id, rgbw) {
+            return Ok(0);
+        }
+
+        let size = match self.send_command(command).await {
+            Ok(size) => size,
+            // The newer color acknowledges itself once written
+            Err(HidError::Superseded) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        self.leds.lock().await.acknowledge(id, rgbw);
+        Ok(size)
+    }
+
+    /// Update the firmware of the connected device.
+    ///
+    /// Reading, writing and pinging are paused while the update runs; commands
+    /// sent meanwhile fail with `HidError::UpdateInProgress`. Afterwards the
+    /// connection is dropped and `process()` reconnects once the device has
+    /// restarted, whether the update succeeded, failed or was cancelled.
+    pub async fn update(&self, files: Vec<&Path>, options: &UpdateOptions) -> Result<(), UpdateError> {
+        let files: Vec<PathBuf> = files.iter().map(|path| path.to_path_buf()).collect();
+        let connection = self.begin_update().await?;
+        let (connection, result) = run_blocking(connection, options, move |device, options| {
+            perform_hid_upgrade_with(device, files.iter().map(PathBuf::as_path).collect(), options)
+        })
+        .await;
+        self.end_update(connection, &result).await;
+        result
+    }
+
+    /// Install a firmware bundle like `update`. The bundle is checked against
+    /// the version information of the device first; an incompatible bundle
+    /// is rejected without interrupting regular communication.
+    pub async fn update_bundle(&self, bundle: &FirmwareBundle, options: &UpdateOptions) -> Result<(), UpdateError> {
+        let version_info = self
+            .version_info
+            .read()
+            .await
+            .clone()
+            .ok_or(BundleError::UnknownDeviceVersion)?;
+        bundle.check_device(&version_info)?;
+        let transfer_files = bundle.transfer_files()?;
+
+        let connection = self.begin_update().await?;
+        let (connection, result) = run_blocking(connection, options, move |device, options| {
+            upgrade_files(device, transfer_files, options)
+        })
+        .await;
+        self.end_update(connection, &result).await;
+        result
+    }
+
+    /// Change the settings stored on the device.
+    ///
+    /// The device restarts to apply them. This waits until it has enumerated
+    /// again and reported its version, then returns the resulting state. The
+    /// firmware does not report the settings it applied, so the state only
+    /// confirms the restart. Empty settings fail with `HidError::NoSettings`,
+    /// as the device would not restart for them.
+    pub async fn configure(&self, settings: DeviceSettings, timeout: Duration) -> Result<HardwareState, HidError> {
+        if settings.is_empty() {
+            return Err(HidError::NoSettings);
+        }
+        let mark = self.restart_mark().await?;
+        self.send_command(settings.to_command()).await?;
+        info!("Applying device settings ({}), waiting for the device to restart", settings);
+        self.wait_for_restart(mark, timeout).await
+    }
+
+    /// Reset the device and wait until it is back.
+    ///
+    /// Returns once the device has disappeared, enumerated again with the
+    /// same serial number and reported its version, with the resulting state.
+    pub async fn reset_and_wait(&self, timeout: Duration) -> Result<HardwareState, HidError> {
+        let mark = self.restart_mark().await?;
+        self.send_command(SimpleCommand::reset()).await?;
+        info!("Resetting device, waiting for it to restart");
+        self.wait_for_restart(mark, timeout).await
+    }
+
+    /// Put the device into update mode and wait until it is back.
+    ///
+    /// Returns once the device has re-enumerated with the same serial number
+    /// and reported its version, like `reset_and_wait`, so a scripted flow
+    /// can start transferring files without a fixed sleep.
+    pub async fn prepare_update_and_wait(&self, timeout: Duration) -> Result<HardwareState, HidError> {
+        let mark = self.restart_mark().await?;
+        self.send_command(SimpleCommand::prepare_update()).await?;
+        info!("Entering update mode, waiting for the device to restart");
+        self.wait_for_restart(mark, timeout).await
+    }
+
+    /// Update the firmware like `update` and wait until the device has
+    /// restarted with it, see `reset_and_wait`. The version reported by the
+    /// restarted device is part of the returned state.
+    pub async fn update_and_wait(
+        &self,
+        files: Vec<&Path>,
+        options: &UpdateOptions,
+        timeout: Duration,
+    ) -> Result<HardwareState, UpdateError> {
+        let mark = self.restart_mark().await.map_err(UpdateError::Restart)?;
+        self.update(files, options).await?;
+        self.wait_for_restart(mark, timeout).await.map_err(UpdateError::Restart)
     }
 
-    /// Get raw device access (for firmware updates)
-    pub async fn raw_device(&self) -> Option<Arc<Mutex<Option<RawHidDevice>>>> {
-        Some(self.device.clone())
+    /// Install a firmware bundle like `update_bundle` and wait until the
+    /// device has restarted with it, see `update_and_wait`.
+    pub async fn update_bundle_and_wait(
+        &self,
+        bundle: &FirmwareBundle,
+        options: &UpdateOptions,
+        timeout: Duration,
+    ) -> Result<HardwareState, UpdateError> {
+        let mark = self.restart_mark().await.map_err(UpdateError::Restart)?;
+        self.update_bundle(bundle, options).await?;
+        self.wait_for_restart(mark, timeout).await.map_err(UpdateError::Restart)
+    }
+
+    /// Remember the current connection before making the device restart
+    async fn restart_mark(&self) -> Result<RestartMark, HidError> {
+        if *self.updating.read().await {
+            return Err(HidError::UpdateInProgress);
+        }
+        if !self.state.read().await.connection_status.is_connected() {
+            return Err(HidError::NotConnected);
+        }
+
+        let serial_number = self
+            .descriptor
+            .read()
+            .await
+            .as_ref()
+            .and_then(|descriptor| descriptor.serial_number.clone());
+        Ok(RestartMark {
+            connections: *self.connections.read().await,
+            serial_number,
+        })
+    }
+
+    /// Wait until a connection newer than the mark has reported its version.
+    /// A connection counter tells the new connection from the old one.
+    async fn wait_for_restart(&self, mark: RestartMark, timeout: Duration) -> Result<HardwareState, HidError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if *self.connections.read().await > mark.connections {
+                let serial_number = self
+                    .descriptor
+                    .read()
+                    .await
+                    .as_ref()
+                    .map(|descriptor| descriptor.serial_number.clone());
+                match serial_number {
+                    // Another device was picked up while this one restarted
+                    Some(serial_number) if mark.serial_number.is_some() && serial_number != mark.serial_number => {
+                        return Err(HidError::UnexpectedDevice(serial_number.unwrap_or_default()));
+                    }
+                    Some(_) if self.version_info.read().await.is_some() => return Ok(self.state().await),
+                    _ => {}
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(HidError::ReconnectTimeout(timeout));
+            }
+            sleep(RESTART_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Access to the filesystem of the connected device
+    pub fn fs(&self) -> DeviceFs<'_> {
+        DeviceFs::new(self)
+    }
+
+    /// Run a filesystem session. The device keeps running afterwards, so the
+    /// connection is handed back unless the session failed.
+    pub(crate) async fn run_fs_batch(&self, batch: &FsBatch, options: &UpdateOptions) -> Result<(), UpdateError> {
+        let batch = batch.clone();
+        let connection = self.begin_update().await?;
+        let (connection, result) = run_blocking(connection, options, move |device, options| {
+            perform_fs_batch_with(device, &batch, options)
+        })
+        .await;
+        match &result {
+            Ok(()) => self.resume(connection).await,
+            Err(e) => {
+                error!("Filesystem session failed: {}", e);
+                self.release(connection).await;
+            }
+        }
+        result
+    }
+
+    /// Pause regular communication and take the connection for an update
+    async fn begin_update(&self) -> Result<Box<dyn HidConnection>, UpdateError> {
+        {
+            let mut updating = self.updating.write().await;
+            if *updating {
+                return Err(UpdateError::AlreadyRunning);
+            }
+            *updating = true;
+        }
+
+        let Some(connection) = self.detach().await else {
+            *self.updating.write().await = false;
+            return Err(UpdateError::NotConnected);
+        };
+        self.state.write().await.connection_status = ConnectionState::Updating;
+        Ok(connection)
+    }
+
+    /// Release the connection after an update so the restarted device is picked up again
+    async fn end_update(&self, connection: Box<dyn HidConnection>, result: &Result<(), UpdateError>) {
+        match result {
+            Ok(()) => info!("Firmware update finished"),
+            Err(e) => error!("Firmware update failed: {}", e),
+        }
+
+        self.release(connection).await;
+    }
+
+    /// Drop the connection after a session so the device is looked up again
+    async fn release(&self, connection: Box<dyn HidConnection>) {
+        drop(connection);
+        self.state.write().await.connection_status = ConnectionState::Disconnected;
+        *self.updating.write().await = false;
+    }
+
+    /// Hand the connection back after a session that left the device running
+    async fn resume(&self, connection: Box<dyn HidConnection>) {
+        let descriptor = self.descriptor.read().await.clone().unwrap_or_default();
+        let status = match self.attach(connection, &descriptor).await {
+            Ok(()) => ConnectionState::Connected,
+            Err(e) => {
+                error!("Failed to resume the connection: {}", e);
+                ConnectionState::Disconnected
+            }
+        };
+        self.state.write().await.connection_status = status;
+        *self.updating.write().await = false;
+    }
+
+    /// Start an I/O thread owning the connection
+    async fn attach(&self, connection: Box<dyn HidConnection>, descriptor: &DeviceDescriptor) -> Result<(), HidError> {
+        let name = descriptor.serial_number.as_deref().unwrap_or(&descriptor.path);
+        let io = IoThread::spawn(connection, name)?;
+        *self.io.write().await = Some(Arc::new(io));
+        Ok(())
+    }
+
+    /// Stop the I/O thread and take the connection back from it
+    async fn detach(&self) -> Option<Box<dyn HidConnection>> {
+        let io = self.io.write().await.take()?;
+        io.close().await
     }
 
     /// Wait for device connection
     async fn wait_for_device(&self) -> Result<(), HidError> {
         info!("Looking for device...");
-        
-        let mut state = self.state.write().await;
-        state.connection_status = ConnectionState::Disconnected;
-        drop(state);
+
+        // An unresponsive device stays in the Error state until it is back
+        let unresponsive = {
+            let mut state = self.state.write().await;
+            if state.connection_status != ConnectionState::Error {
+                state.connection_status = ConnectionState::Disconnected;
+            }
+            state.connection_status == ConnectionState::Error
+        };
+        self.detach().await;
+        if self.descriptor.write().await.take().is_some() {
+            self.publish(DeviceEvent::Disconnected);
+        }
+
+        if unresponsive {
+            let delay = self.next_reconnect_delay().await;
+            info!("Reopening device in {:?}", delay);
+            sleep(delay).await;
+        }
 
         loop {
             match self.search_for_device().await {
-                Ok(device) => {
-                    let mut dev = self.device.lock().await;
-                    *dev = Some(device);
-                    drop(dev);
-                    
-                    self.set_hardware_info().await;
+                Ok((descriptor, device)) => {
+                    if let Err(e) = self.attach(device, &descriptor).await {
+                        error!("{}", e);
+                        sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+
+                    self.set_hardware_info(&descriptor).await;
+                    if let Err(e) = self.request_version_info().await {
+                        warn!("Failed to request version info: {}", e);
+                    }
                     return Ok(());
                 }
                 Err(_) => {
@@ -161,46 +760,42 @@ impl HidDevice {
         }
     }
 
+    /// Delay before reopening an unresponsive device. It doubles with every
+    /// failure and is reset once the device answers a ping.
+    async fn next_reconnect_delay(&self) -> Duration {
+        let mut delay = self.reconnect_delay.lock().await;
+        let current = *delay;
+        *delay = (current * 2).min(self.watchdog.max_reconnect_delay());
+        current
+    }
+
     /// Search for a compatible device
-    async fn search_for_device(&self) -> Result<RawHidDevice, HidError> {
-        let api = HidApi::new().map_err(|e| HidError::HidApiError(e.to_string()))?;
+    async fn search_for_device(&self) -> Result<(DeviceDescriptor, Box<dyn HidConnection>), HidError> {
+        let devices = self.transport.enumerate()?;
 
         // If no specific device info, search for mutenix devices
         if self.device_info.is_empty() {
-            for device_info in api.device_list() {
-                if let Some(product) = device_info.product_string() {
-                    if product.to_lowercase().contains("mutenix") {
-                        debug!("Found mutenix device: {:?}", device_info);
-                        return device_info
-                            .open_device(&api)
-                            .map_err(|e| HidError::HidApiError(e.to_string()));
-                    }
-                }
+            if let Some(descriptor) = devices.iter().find(|d| is_selected(&[], d)) {
+                debug!("Found mutenix device: {:?}", descriptor);
+                let device = self.transport.open(descriptor)?;
+                return Ok((descriptor.clone(), device));
             }
         } else {
-            // Try to open specific devices
-            for info in &self.device_info {
-                let result = if info.vendor_id == 0 && info.product_id == 0 {
-                    // Try to open by serial number only
-                    if let Some(serial) = &info.serial_number {
-                        api.device_list()
-                            .find(|d| d.serial_number() == Some(serial.as_str()))
-                            .and_then(|d| d.open_device(&api).ok())
-                    } else {
-                        None
+            // Try to open specific devices, in order of priority
+            let rejected = {
+                // A device that was unplugged is checked again when it is back
+                let mut rejected = self.rejected.lock().await;
+                rejected.retain(|path| devices.iter().any(|d| &d.path == path));
+                rejected.clone()
+            };
+            let mut rules: Vec<&DeviceInfo> = self.device_info.iter().collect();
+            rules.sort_by_key(|info| std::cmp::Reverse(info.priority));
+            for info in rules {
+                for descriptor in devices.iter().filter(|d| info.matches(d) && !rejected.contains(&d.path)) {
+                    if let Ok(device) = self.transport.open(descriptor) {
+                        info!("Device opened successfully");
+                        return Ok((descriptor.clone(), device));
                     }
-                } else {
-                    // Try to open by VID/PID and optional serial
-                    if let Some(serial) = &info.serial_number {
-                        api.open_serial(info.vendor_id, info.product_id, serial).ok()
-                    } else {
-                        api.open(info.vendor_id, info.product_id).ok()
-                    }
-                };
-
-                if let Some(device) = result {
-                    info!("Device opened successfully");
-                    return Ok(device);
                 }
             }
         }
@@ -209,52 +804,194 @@ impl HidDevice {
     }
 
     /// Set hardware information from connected device
-    async fn set_hardware_info(&self) {
-        let device = self.device.lock().await;
-        
-        if let Some(dev) = device.as_ref() {
-            let mut state = self.state.write().await;
-            state.serial_number = dev.get_serial_number_string().ok().flatten();
-            state.manufacturer = dev.get_manufacturer_string().ok().flatten();
-            state.product = dev.get_product_string().ok().flatten();
-            state.connection_status = ConnectionState::Connected;
-            
-            info!("Connected to device: {:?}", state);
+    async fn set_hardware_info(&self, descriptor: &DeviceDescriptor) {
+        // The device starts with blank LEDs, everything has to be written again
+        self.leds.lock().await.invalidate();
+        *self.version_info.write().await = None;
+        *self.descriptor.write().await = Some(descriptor.clone());
+        *self.pending_ping.lock().await = None;
+        let connections = {
+            let mut connections = self.connections.write().await;
+            *connections += 1;
+            *connections
+        };
+
+        let mut state = self.state.write().await;
+        if connections > 1 {
+            state.link.reconnects += 1;
+        }
+        state.link.consecutive_misses = 0;
+        state.serial_number = descriptor.serial_number.clone();
+        state.manufacturer = descriptor.manufacturer.clone();
+        state.product = descriptor.product.clone();
+        state.firmware_version = None;
+        state.hardware_type = None;
+        state.connection_status = ConnectionState::Connected;
+
+        info!("Connected to device: {:?}", state);
+        drop(state);
+        self.publish(DeviceEvent::Connected(descriptor.clone()));
+    }
+
+    /// Ask the device for its version information.
+    /// The firmware answers every ping with a VersionInfo message.
+    async fn request_version_info(&self) -> Result<usize, HidError> {
+        self.send_report(&SimpleCommand::ping(0)).await
+    }
+
+    /// Store the version information reported by the device
+    async fn set_version_info(&self, version_info: &VersionInfo) {
+        let mut state = self.state.write().await;
+        state.firmware_version = Some(version_info.version());
+        state.hardware_type = Some(version_info.hardware_type());
+        *self.version_info.write().await = Some(version_info.clone());
+
+        info!("Device reports {}", version_info);
+    }
+
+    /// Check the reported hardware type against the rule that selected the
+    /// device; a rejected device is not opened again
+    async fn check_hardware(&self, hardware_type: HardwareType) -> Result<(), HidError> {
+        let Some(descriptor) = self.descriptor.read().await.clone() else {
+            return Ok(());
+        };
+        if select_rule(&self.device_info, &descriptor).is_none_or(|rule| rule.accepts_hardware(hardware_type)) {
+            return Ok(());
+        }
+
+        self.rejected.lock().await.insert(descriptor.path);
+        Err(HidError::HardwareMismatch(hardware_type))
+    }
+
+    /// Take a received report as answer to the pending ping. Any report shows
+    /// that the link is alive. The firmware answers pings with VersionInfo,
+    /// which does not repeat the counter, so the round-trip time taken from it
+    /// is approximate.
+    async fn answer_ping(&self, version_info: bool) {
+        let mut pending = self.pending_ping.lock().await;
+        let Some(ping) = pending.as_mut() else {
+            return;
+        };
+        let mut state = self.state.write().await;
+
+        if !ping.answered {
+            ping.answered = true;
+            debug!("Device alive after ping {}", ping.counter);
+            *self.reconnect_delay.lock().await = self.watchdog.reconnect_delay();
+            state.link.record_answer();
+            if state.connection_status == ConnectionState::Degraded {
+                info!("Device answers again");
+                state.connection_status = ConnectionState::Connected;
+            }
+        }
+        if version_info && !ping.timed {
+            ping.timed = true;
+            let rtt = ping.sent.elapsed();
+            debug!("Ping {} answered by VersionInfo after {:?}", ping.counter, rtt);
+            state.link.record_rtt(rtt, true);
+        }
+    }
+
+    /// Count a ping without answer; degrade or drop the connection if too many were missed
+    async fn miss_ping(&self, counter: u8) {
+        let mut state = self.state.write().await;
+        state.link.record_miss();
+        let misses = state.link.consecutive_misses;
+
+        if misses >= self.watchdog.error_after() {
+            error!("Device did not answer {} pings, reopening it", misses);
+            state.connection_status = ConnectionState::Error;
+            state.link.watchdog_resets += 1;
+            drop(state);
+            self.publish(DeviceEvent::Error(HidError::Unresponsive(misses)));
+            // The read loop notices the missing connection and reconnects
+            self.detach().await;
+        } else if misses >= self.watchdog.degraded_after() {
+            warn!("Device did not answer ping {} ({} missed)", counter, misses);
+            if state.connection_status == ConnectionState::Connected {
+                state.connection_status = ConnectionState::Degraded;
+            }
         }
     }
 
     /// Send a report to the device
     async fn send_report(&self, command: &dyn HidOutputCommand) -> Result<usize, HidError> {
-        let device = self.device.lock().await;
-        
-        let dev = device.as_ref().ok_or(HidError::NotConnected)?;
-        
-        let mut buffer = vec![command.report_id()];
-        buffer.extend_from_slice(&command.to_buffer());
-        
-        debug!("HID TX: {:02x?}", buffer);
-        
-        dev.write(&buffer)
-            .map_err(|e| HidError::WriteFailed(e.to_string()))
+        let io = self.io.read().await.clone().ok_or(HidError::NotConnected)?;
+        io.write(report_buffer(command)).await
+    }
+
+    /// Answer a StatusRequest: the firmware restarted or woke up and lost its state.
+    /// The LEDs go through the write queue, so a newer `set_led` replaces a
+    /// replayed color that was not written yet instead of racing with it.
+    async fn replay_state(&self) {
+        let desired = {
+            let mut leds = self.leds.lock().await;
+            leds.invalidate();
+            leds.desired()
+        };
+
+        let mut replayed = Vec::new();
+        for (id, rgbw) in desired {
+            let [r, g, b, w] = rgbw;
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            match self.queue.push(Box::new(SetLed::new(id, LedColor::rgbw(r, g, b, w))), tx).await {
+                Ok(()) => replayed.push((id, rgbw, rx)),
+                Err(e) => warn!("Failed to replay LED {}: {}", id, e),
+            }
+        }
+
+        // A restarted device may run a different firmware now
+        let (tx, ping) = tokio::sync::oneshot::channel();
+        if let Err(e) = self.queue.push(Box::new(SimpleCommand::ping(0)), tx).await {
+            warn!("Failed to request version info: {}", e);
+        }
+
+        for (id, rgbw, rx) in replayed {
+            match rx.await {
+                Ok(Ok(_)) => {
+                    // Unless a newer color was written meanwhile
+                    let mut leds = self.leds.lock().await;
+                    if leds.desired().contains(&(id, rgbw)) {
+                        leds.acknowledge(id, rgbw);
+                    }
+                }
+                // The newer color acknowledges itself once written
+                Ok(Err(HidError::Superseded)) | Err(_) => {}
+                Ok(Err(e)) => warn!("Failed to replay LED {}: {}", id, e),
+            }
+        }
+        if let Ok(Err(e)) = ping.await {
+            warn!("Failed to request version info: {}", e);
+        }
     }
 
     /// Read loop
     async fn read_loop(&self) {
         loop {
-            if !*self.running.read().await {
-                break;
-            }
-
             match self.read_once().await {
                 Ok(_) => {
                     // Small yield to prevent busy loop
                     tokio::task::yield_now().await;
                 }
                 Err(HidError::NotConnected) => {
-                    sleep(Duration::from_millis(100)).await;
+                    if *self.updating.read().await {
+                        sleep(Duration::from_millis(100)).await;
+                    } else if let Err(e) = self.wait_for_device().await {
+                        // The connection was released, e.g. after an update
+                        error!("Failed to reconnect: {}", e);
+                    }
+                }
+                Err(e @ HidError::HardwareMismatch(_)) => {
+                    warn!("{}, disconnecting", e);
+                    self.publish(DeviceEvent::Error(e));
+                    if let Err(e) = self.wait_for_device().await {
+                        error!("Failed to reconnect: {}", e);
+                    }
                 }
                 Err(e) => {
                     error!("Read error: {}", e);
+                    self.state.write().await.link.read_errors += 1;
+                    self.publish(DeviceEvent::Error(e));
                     if let Err(e) = self.wait_for_device().await {
                         error!("Failed to reconnect: {}", e);
                     }
@@ -265,127 +1002,191 @@ impl HidDevice {
 
     /// Read once from device
     async fn read_once(&self) -> Result<(), HidError> {
-        let device = self.device.lock().await;
-        
-        let dev = device.as_ref().ok_or(HidError::NotConnected)?;
-        
-        let mut buffer = [0u8; 64];
-        match dev.read_timeout(&mut buffer, 100) {
-            Ok(0) => {
-                // No data, this is normal
-                Ok(())
+        let io = self.io.read().await.clone().ok_or(HidError::NotConnected)?;
+
+        // Return regularly so the read loop notices when it is stopped
+        let report = match tokio::time::timeout(READ_WAIT, io.next_report()).await {
+            Ok(report) => report?,
+            Err(_) => return Ok(()),
+        };
+        debug!("HID RX: {}", dissect(FrameDirection::DeviceToHost, &report));
+
+        // Parse and filter messages - only forward known messages to subscribers
+        let message = match parse_input_message(&report) {
+            Ok(HidInput::Status(status)) => Some(DeviceMessage::Status(status)),
+            Ok(HidInput::StatusRequest(request)) => Some(DeviceMessage::StatusRequest(request)),
+            Ok(HidInput::VersionInfo(version_info)) => Some(DeviceMessage::VersionInfo(version_info)),
+            Ok(HidInput::Log(log_message)) => Some(DeviceMessage::Log(log_message)),
+            Ok(other) => {
+                debug!("Message received but not forwarded to subscribers: {}", other);
+                None
             }
-            Ok(size) => {
-                debug!("HID RX: {:02x?}", &buffer[..size]);
-                
-                // Parse and filter messages - only forward Status and StatusRequest to subscribers
-                if let Ok(parsed) = parse_input_message(&buffer[..size]) {
-                    // Try to downcast to Status
-                    if let Some(status) = parsed.downcast_ref::<Status>() {
-                        let callbacks = self.callbacks.read().await;
-                        let msg = DeviceMessage::Status(status.clone());
-                        for callback in callbacks.iter() {
-                            callback(msg.clone());
-                        }
-                    }
-                    // Try to downcast to StatusRequest
-                    else if let Some(_status_req) = parsed.downcast_ref::<StatusRequest>() {
-                        let callbacks = self.callbacks.read().await;
-                        let msg = DeviceMessage::StatusRequest(StatusRequest);
-                        for callback in callbacks.iter() {
-                            callback(msg.clone());
-                        }
-                    }
-                    // Ping responses, VersionInfo, and other messages are not forwarded
-                    else {
-                        debug!("Message received but not forwarded to subscribers (ping/version/etc)");
-                    }
-                } else {
-                    debug!("Failed to parse message or unknown message type");
-                }
-                Ok(())
+            Err(e) => {
+                debug!("Failed to parse message: {}", e);
+                None
+            }
+        };
+
+        self.answer_ping(matches!(message, Some(DeviceMessage::VersionInfo(_)))).await;
+        match &message {
+            Some(DeviceMessage::VersionInfo(version_info)) => {
+                self.set_version_info(version_info).await;
+                self.check_hardware(version_info.hardware_type()).await?;
+            }
+            Some(DeviceMessage::Log(log_message)) => {
+                let serial = self.state.read().await.serial_number.clone();
+                log!(
+                    target: DEVICE_LOG_TARGET,
+                    log_message.level.into(),
+                    "[{}] {}",
+                    serial.as_deref().unwrap_or("unknown"),
+                    log_message.message
+                );
             }
-            Err(e) => Err(HidError::ReadFailed(e.to_string())),
+            Some(DeviceMessage::StatusRequest(_)) => self.replay_state().await,
+            _ => {}
         }
+
+        if let Some(message) = message {
+            self.publish(message.into());
+        }
+        Ok(())
     }
 
-    /// Write loop
+    /// Write loop: writes queued commands, control commands first
     async fn write_loop(&self) {
-        let mut receiver = self.send_receiver.lock().await;
-        
         loop {
-            if !*self.running.read().await {
-                break;
+            let queued = self.queue.pop().await;
+            if *self.updating.read().await {
+                let _ = queued.response.send(Err(HidError::UpdateInProgress));
+                continue;
             }
-            
-            match receiver.recv().await {
-                Some((command, response)) => {
-                    match self.send_report(command.as_ref()).await {
-                        Ok(size) => {
-                            let _ = response.send(Ok(size));
-                        }
-                        Err(e) => {
-                            error!("Failed to send command: {}", e);
-                            let _ = response.send(Err(e));
-                        }
-                    }
+            match self.send_report(queued.command.as_ref()).await {
+                Ok(size) => {
+                    let _ = queued.response.send(Ok(size));
                 }
-                None => {
-                    // Channel closed, exit loop
-                    break;
+                Err(e) => {
+                    error!("Failed to send command: {}", e);
+                    if !matches!(e, HidError::NotConnected) {
+                        self.state.write().await.link.write_errors += 1;
+                        self.publish(DeviceEvent::Error(e.clone()));
+                    }
+                    let _ = queued.response.send(Err(e));
                 }
             }
         }
     }
 
-    /// Ping loop to keep connection alive
+    /// Ping loop: keeps the connection alive and watches whether the device answers
     async fn ping_loop(&self) {
         // Use interval for more precise timing
-        let mut interval = tokio::time::interval(Duration::from_secs(PING_LOOP_TIME_SECONDS));
+        let mut interval = tokio::time::interval(self.watchdog.ping_interval());
         interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-        let mut ping_counter = 0;
-        
+        let mut ping_counter: u8 = 0;
+
         loop {
             interval.tick().await;
-            
-            if !*self.running.read().await {
-                break;
+
+            if *self.updating.read().await {
+                *self.pending_ping.lock().await = None;
+                continue;
+            }
+
+            // The previous ping had a whole interval to be answered
+            let pending = self.pending_ping.lock().await.take();
+            if let Some(ping) = pending.filter(|ping| !ping.answered) {
+                self.miss_ping(ping.counter).await;
+            }
+            if !self.state.read().await.connection_status.is_connected() {
+                continue;
             }
 
-            let last_ping = *self.last_ping.lock().await;
-            let elapsed = last_ping.elapsed();
-            
-            debug!("Sending ping after {:?} since last ping", elapsed);
-            
+            *self.pending_ping.lock().await = Some(PendingPing {
+                counter: ping_counter,
+                sent: Instant::now(),
+                answered: false,
+                timed: false,
+            });
             match self.send_command(SimpleCommand::ping(ping_counter)).await {
                 Ok(_) => {
-                    debug!("Ping sent successfully");
-                    *self.last_ping.lock().await = Instant::now();
+                    debug!("Ping {} sent", ping_counter);
+                    self.state.write().await.link.pings_sent += 1;
                 }
                 Err(e) => {
                     warn!("Failed to send ping: {}", e);
+                    *self.pending_ping.lock().await = None;
                 }
             }
             ping_counter = ping_counter.wrapping_add(1);
         }
     }
 
-    /// Main processing loop
+    /// Main processing loop. Runs until `stop` is called and can be started
+    /// again afterwards; only one `process()` may run at a time.
     pub async fn process(&self) -> Result<(), HidError> {
+        // Taken under the shutdown lock, so `stop` sees either no run or this one
+        let (_running, shutdown) = {
+            let mut shutdown = self.shutdown.lock().await;
+            let running = self.process_lock.try_lock().map_err(|_| HidError::AlreadyRunning)?;
+            *shutdown = CancellationToken::new();
+            (running, shutdown.clone())
+        };
+        self.queue.open();
+        // Dropping the loops cancels them at their current await point
+        let result = tokio::select! {
+            _ = shutdown.cancelled() => Ok(()),
+            result = self.run() => result,
+        };
+
+        self.shut_down().await;
+        result
+    }
+
+    async fn run(&self) -> Result<(), HidError> {
         self.wait_for_device().await?;
-        
+
         tokio::select! {
             _ = self.read_loop() => {},
             _ = self.write_loop() => {},
             _ = self.ping_loop() => {},
         }
-        
+
         Ok(())
     }
 
-    /// Stop the device processing
+    /// Close the connection
+    async fn shut_down(&self) {
+        self.detach().await;
+        *self.pending_ping.lock().await = None;
+        {
+            // A running update keeps its connection until it is done
+            let mut state = self.state.write().await;
+            if state.connection_status != ConnectionState::Updating {
+                state.connection_status = ConnectionState::Disconnected;
+            }
+        }
+        if self.descriptor.write().await.take().is_some() {
+            self.publish(DeviceEvent::Disconnected);
+        }
+        info!("Device handler stopped");
+    }
+
+    /// Stop processing: cancel all loops, fail queued commands with
+    /// `HidError::Stopped` and close the connection, so other programs can
+    /// open the device. Returns once `process()` has returned; without a
+    /// running `process()` only the queued commands are failed.
     pub async fn stop(&self) {
-        let mut running = self.running.write().await;
-        *running = false;
+        // Closed first, so no command is queued after the queue was emptied
+        for queued in self.queue.close() {
+            let _ = queued.response.send(Err(HidError::Stopped));
+        }
+        {
+            let shutdown = self.shutdown.lock().await;
+            if self.process_lock.try_lock().is_ok() {
+                return;
+            }
+            shutdown.cancel();
+        }
+        let _stopped = self.process_lock.lock().await;
     }
 }
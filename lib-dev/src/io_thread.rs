@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Blocking HID I/O on a thread of its own.
+//!
+//! hidapi handles may be moved between threads but not shared, and reading
+//! blocks. Every open connection is therefore owned by one I/O thread that
+//! polls for reports with a short timeout and performs the writes queued in
+//! between. Async code only talks to the thread over channels, so a write
+//! waits at most one poll interval and never for a lock held by a reader.
+
+use crate::constants::IO_POLL_INTERVAL_MS;
+use crate::hid_device::HidError;
+use crate::transport::HidConnection;
+use log::{debug, error};
+use std::sync::mpsc::{self as std_mpsc, TryRecvError};
+use std::thread;
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+/// Report read from the device, or the error that ended the connection
+type IncomingReport = Result<Vec<u8>, HidError>;
+
+enum IoRequest {
+    Write(Vec<u8>, oneshot::Sender<Result<usize, HidError>>),
+    /// Stop the thread and hand the connection back
+    Close(oneshot::Sender<Box<dyn HidConnection>>),
+}
+
+/// Handle to the I/O thread of an open connection. The thread ends, closing
+/// the connection, once the last handle is dropped.
+pub(crate) struct IoThread {
+    requests: std_mpsc::Sender<IoRequest>,
+    reports: Mutex<mpsc::UnboundedReceiver<IncomingReport>>,
+}
+
+impl IoThread {
+    /// Start a thread owning the connection
+    pub(crate) fn spawn(connection: Box<dyn HidConnection>, name: &str) -> Result<Self, HidError> {
+        let (requests, request_receiver) = std_mpsc::channel();
+        let (report_sender, reports) = mpsc::unbounded_channel();
+
+        thread::Builder::new()
+            .name(format!("hid-io {}", name))
+            .spawn(move || run(connection, request_receiver, report_sender))
+            .map_err(|e| HidError::HidApiError(format!("Failed to start I/O thread: {}", e)))?;
+
+        Ok(Self {
+            requests,
+            reports: Mutex::new(reports),
+        })
+    }
+
+    /// Write a report; the first byte is the report ID
+    pub(crate) async fn write(&self, data: Vec<u8>) -> Result<usize, HidError> {
+        let (tx, rx) = oneshot::channel();
+        self.requests
+            .send(IoRequest::Write(data, tx))
+            .map_err(|_| HidError::NotConnected)?;
+        rx.await.map_err(|_| HidError::NotConnected)?
+    }
+
+    /// Next report from the device. `NotConnected` once the thread was closed,
+    /// the error that ended the connection if reading failed.
+    pub(crate) async fn next_report(&self) -> Result<Vec<u8>, HidError> {
+        self.reports
+            .lock()
+            .await
+            .recv()
+            .await
+            .unwrap_or(Err(HidError::NotConnected))
+    }
+
+    /// Stop the thread and take the connection back, e.g. for a firmware update.
+    /// None if the thread has already ended.
+    pub(crate) async fn close(&self) -> Option<Box<dyn HidConnection>> {
+        let (tx, rx) = oneshot::channel();
+        self.requests.send(IoRequest::Close(tx)).ok()?;
+        rx.await.ok()
+    }
+}
+
+fn run(
+    connection: Box<dyn HidConnection>,
+    requests: std_mpsc::Receiver<IoRequest>,
+    reports: mpsc::UnboundedSender<IncomingReport>,
+) {
+    let mut buffer = [0u8; 64];
+    loop {
+        // Writes queued during the last poll go out before reading again
+        loop {
+            match requests.try_recv() {
+                Ok(IoRequest::Write(data, response)) => {
+                    let _ = response.send(connection.write(&data));
+                }
+                Ok(IoRequest::Close(response)) => {
+                    debug!("I/O thread handing the connection back");
+                    let _ = response.send(connection);
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    debug!("I/O thread closing the connection");
+                    return;
+                }
+            }
+        }
+
+        match connection.read_timeout(&mut buffer, IO_POLL_INTERVAL_MS) {
+            Ok(0) => {}
+            Ok(size) => {
+                if reports.send(Ok(buffer[..size].to_vec())).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                error!("I/O thread stopped: {}", e);
+                let _ = reports.send(Err(e));
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Host-side LED animations.
+//!
+//! The firmware only knows static colors, so patterns like blinking or breathing
+//! are rendered on the host into a series of `SetLed` frames. `LedAnimator` keeps
+//! one animation per LED and renders the color each LED should show at a point in time.
+
+use crate::hid_commands::LedColor;
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Interval between two rendered animation frames
+pub const LED_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Pattern an LED is animated with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedPattern {
+    /// Static color
+    Solid,
+    /// Color for the first half of every period, off for the second half
+    Blink { period: Duration },
+    /// Full brightness at the start of every period, fading out linearly
+    Pulse { period: Duration },
+    /// Smooth fade in and out once per period
+    Breathe { period: Duration },
+    /// Color for the first half of every period, `other` for the second half
+    Alternate { other: LedColor, period: Duration },
+    /// Color once for `duration`, off afterwards
+    Flash { duration: Duration },
+}
+
+/// Color and pattern of a single LED
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedAnimation {
+    pub color: LedColor,
+    pub pattern: LedPattern,
+}
+
+impl LedAnimation {
+    pub fn new(color: LedColor, pattern: LedPattern) -> Self {
+        Self { color, pattern }
+    }
+
+    /// Static color without animation
+    pub fn solid(color: LedColor) -> Self {
+        Self::new(color, LedPattern::Solid)
+    }
+
+    /// Color the LED shows `elapsed` after the animation started
+    pub fn color_at(&self, elapsed: Duration) -> LedColor {
+        match self.pattern {
+            LedPattern::Solid => self.color,
+            LedPattern::Blink { period } => {
+                if phase(elapsed, period) < 0.5 {
+                    self.color
+                } else {
+                    LedColor::Black
+                }
+            }
+            LedPattern::Pulse { period } => self.dimmed(1.0 - phase(elapsed, period)),
+            LedPattern::Breathe { period } => {
+                self.dimmed((1.0 - (2.0 * PI * phase(elapsed, period)).cos()) / 2.0)
+            }
+            LedPattern::Alternate { other, period } => {
+                if phase(elapsed, period) < 0.5 {
+                    self.color
+                } else {
+                    other
+                }
+            }
+            LedPattern::Flash { duration } => {
+                if elapsed < duration {
+                    self.color
+                } else {
+                    LedColor::Black
+                }
+            }
+        }
+    }
+
+    fn dimmed(&self, level: f32) -> LedColor {
+        let [r, g, b, w] = self.color.to_rgbw_scaled(level);
+        LedColor::rgbw(r, g, b, w)
+    }
+}
+
+/// Position within the current period, in `0.0..1.0`
+fn phase(elapsed: Duration, period: Duration) -> f32 {
+    if period.is_zero() {
+        return 0.0;
+    }
+    (elapsed.as_secs_f64() % period.as_secs_f64() / period.as_secs_f64()) as f32
+}
+
+#[derive(Debug)]
+struct AnimatedLed {
+    animation: LedAnimation,
+    started: Instant,
+}
+
+/// Animation state of all LEDs of one device
+#[derive(Debug, Default)]
+pub struct LedAnimator {
+    leds: BTreeMap<u8, AnimatedLed>,
+}
+
+impl LedAnimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the animation of an LED. The animation only restarts if it differs
+    /// from the current one, so this can be called on every update.
+    pub fn set(&mut self, button_id: u8, animation: LedAnimation, now: Instant) {
+        match self.leds.get_mut(&button_id) {
+            Some(led) if led.animation == animation => {}
+            Some(led) => {
+                led.animation = animation;
+                led.started = now;
+            }
+            None => {
+                self.leds.insert(
+                    button_id,
+                    AnimatedLed {
+                        animation,
+                        started: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Current color of every LED. `HidDevice::set_led` skips colors the
+    /// device already shows, so all frames can be sent on every tick.
+    pub fn frames(&self, now: Instant) -> Vec<(u8, LedColor)> {
+        self.leds
+            .iter()
+            .map(|(button_id, led)| {
+                (*button_id, led.animation.color_at(now.saturating_duration_since(led.started)))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Per-device LED framebuffer.
+//!
+//! Tracks the color each LED should show and the color the device acknowledged
+//! last, so only LEDs that actually change are written to the device.
+
+use std::collections::BTreeMap;
+
+/// Desired and acknowledged RGBW values of all LEDs of a device
+#[derive(Debug, Clone, Default)]
+pub struct LedFramebuffer {
+    desired: BTreeMap<u8, [u8; 4]>,
+    acknowledged: BTreeMap<u8, [u8; 4]>,
+}
+
+impl LedFramebuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the desired color of an LED; returns whether it has to be written.
+    /// A changed color is always written, even if it matches the acknowledged
+    /// one, as a write of the previous color may still be queued.
+    pub fn set(&mut self, id: u8, rgbw: [u8; 4]) -> bool {
+        let previous = self.desired.insert(id, rgbw);
+        previous != Some(rgbw) || self.acknowledged.get(&id) != Some(&rgbw)
+    }
+
+    /// Record that the device accepted a color
+    pub fn acknowledge(&mut self, id: u8, rgbw: [u8; 4]) {
+        self.acknowledged.insert(id, rgbw);
+    }
+
+    /// Forget what the device shows, e.g. after a reconnect or firmware restart.
+    /// Every desired LED has to be written again afterwards.
+    pub fn invalidate(&mut self) {
+        self.acknowledged.clear();
+    }
+
+    /// Desired color of every LED
+    pub fn desired(&self) -> Vec<(u8, [u8; 4])> {
+        self.desired.iter().map(|(id, rgbw)| (*id, *rgbw)).collect()
+    }
+}
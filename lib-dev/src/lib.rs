@@ -5,24 +5,77 @@
 //!
 //! This library provides HID communication with Mutenix devices, including:
 //! - Device discovery and connection management
+//! - Connection health watchdog with latency statistics
+//! - Handling several connected devices at once
 //! - Async message sending and receiving
-//! - Firmware update support
+//! - Bounded write queue with priorities and coalesced LED updates
+//! - Event streams of connection changes, messages and errors
+//! - Firmware update support with progress events and cancellation
+//! - Firmware bundles with manifest and checksums
+//! - Device settings (serial console and USB filesystem)
+//! - Writing and deleting single files without a firmware update
 //! - Command and status message handling
+//! - Human-readable descriptions of raw frames
+//! - Classification of button presses into short and long presses
+//! - Host-side LED animations
+//! - Pluggable transports (hidapi or in-memory loopback)
+//! - Capture of HID traffic to a file and replay of captures
 
+pub mod capture;
 pub mod chunks;
 pub mod constants;
+pub mod device_events;
+pub mod device_fs;
+pub mod device_manager;
 pub mod device_messages;
+pub mod device_settings;
 pub mod device_update;
+pub mod dissector;
+pub mod firmware_bundle;
 pub mod hid_commands;
 pub mod hid_device;
+mod io_thread;
+pub mod led_animation;
+pub mod led_framebuffer;
+pub mod press;
+pub mod replay;
+pub mod transport;
+pub mod watchdog;
+pub mod write_queue;
 
 // Re-export commonly used types
-pub use chunks::{Chunk, ChunkType, Completed, FileChunk, FileDelete, FileEnd, FileStart};
+pub use capture::{
+    read_capture, CaptureEntry, CaptureError, CaptureRecord, CaptureTransport, CaptureWriter,
+};
+pub use chunks::{Chunk, ChunkError, ChunkType, Completed, FileChunk, FileDelete, FileEnd, FileStart};
 pub use constants::*;
+pub use device_events::{DeviceEvent, DeviceEvents, EVENT_CAPACITY};
+pub use device_fs::{perform_fs_batch_with, DeviceFs, FsBatch, FsOperation};
+pub use device_manager::{DeviceManager, TaggedDeviceEvent, TaggedDeviceMessage};
 pub use device_messages::{ChunkAck, HidUpdateMessage, LogLevel, LogMessage, UpdateError};
-pub use device_update::{perform_hid_upgrade, TransferFile};
+pub use device_update::{
+    perform_bundle_upgrade_with, perform_hid_upgrade, perform_hid_upgrade_with, ProgressCallback,
+    TransferFile, UpdateOptions, UpdateProgress,
+};
+pub use device_settings::DeviceSettings;
+pub use dissector::{dissect, parse_hex, DissectedFrame, FrameDirection, FrameField, InvalidHex};
+pub use firmware_bundle::{BundleError, BundleFile, BundleManifest, FirmwareBundle};
 pub use hid_commands::{
-    HardwareType, HidInputMessage, HidOutCommand, HidOutputCommand, LedColor, SetLed,
-    SimpleCommand, Status, UpdateConfig, VersionInfo,
+    parse_input_message, HardwareType, HidInput, HidInputMessage, HidMessageError, HidOutCommand,
+    HidOutputCommand, LedColor, SetLed, SimpleCommand, Status, StatusRequest, UpdateConfig,
+    VersionInfo, WritePriority,
+};
+pub use hid_device::{
+    ConnectionState, DeviceInfo, DeviceMessage, HardwareState, HidDevice, HidError,
+    DEVICE_LOG_TARGET,
+};
+pub use led_animation::{LedAnimation, LedAnimator, LedPattern, LED_FRAME_INTERVAL};
+pub use led_framebuffer::LedFramebuffer;
+pub use press::{Press, PressClassifier, PressKind, PressMode, DEFAULT_LONGPRESS_THRESHOLD};
+pub use replay::{ReplayTransport, WrittenReport};
+pub use transport::{
+    DeviceDescriptor, HidApiTransport, HidConnection, HidTransport, LoopbackDevice,
+    LoopbackTransport,
 };
-pub use hid_device::{ConnectionState, DeviceInfo, DeviceMessage, HardwareState, HidDevice, HidError};
+pub use watchdog::{LinkStats, WatchdogConfig};
+pub use write_queue::{QueuePolicy, WriteQueueConfig};
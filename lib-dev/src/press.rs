@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Classification of button presses into short and long presses.
+//!
+//! The firmware times presses itself: when a button is released it reports
+//! `triggered` together with `longpressed` for a long press. The host can also
+//! time presses from the `pressed` and release reports, which suffers from USB
+//! latency and scheduling jitter but works with firmware that does not set the
+//! flags.
+
+use crate::hid_commands::Status;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Press duration from which the host counts a press as long press
+pub const DEFAULT_LONGPRESS_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// Who decides whether a press was long
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PressMode {
+    /// Only triggered reports count, `longpressed` decides
+    Firmware,
+    /// The host times press and release against the threshold
+    Host,
+    /// Firmware flags when a release is triggered, host timing of releases
+    /// with a recorded press otherwise
+    #[default]
+    Hybrid,
+}
+
+/// Kind of a completed press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressKind {
+    Short,
+    Long,
+}
+
+/// A completed press of a button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Press {
+    pub button: u8,
+    pub kind: PressKind,
+    /// Duration measured by the host, if it timed the press
+    pub duration: Option<Duration>,
+}
+
+impl Press {
+    pub fn is_long(&self) -> bool {
+        self.kind == PressKind::Long
+    }
+}
+
+/// Turns status reports into presses; keeps press times per device and button
+#[derive(Debug)]
+pub struct PressClassifier {
+    mode: PressMode,
+    threshold: Duration,
+    pressed_at: HashMap<(String, u8), Instant>,
+}
+
+impl PressClassifier {
+    pub fn new(mode: PressMode, threshold: Duration) -> Self {
+        Self {
+            mode,
+            threshold,
+            pressed_at: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> PressMode {
+        self.mode
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Feed a status report of the device `serial_number` received at `now`.
+    /// Returns the press it completes, if any. Call this as soon as the report
+    /// arrives, as the host timing depends on `now`.
+    pub fn classify(
+        &mut self,
+        serial_number: &str,
+        status: &Status,
+        now: Instant,
+    ) -> Option<Press> {
+        let button = status.button();
+        let key = (serial_number.to_string(), button);
+
+        let use_firmware = match self.mode {
+            PressMode::Firmware => true,
+            PressMode::Host => false,
+            PressMode::Hybrid => status.triggered(),
+        };
+
+        if use_firmware {
+            self.pressed_at.remove(&key);
+            if !status.triggered() {
+                return None;
+            }
+            let kind = if status.longpressed() {
+                PressKind::Long
+            } else {
+                PressKind::Short
+            };
+            return Some(Press {
+                button,
+                kind,
+                duration: None,
+            });
+        }
+
+        if status.pressed() {
+            self.pressed_at.insert(key, now);
+            return None;
+        }
+
+        if self.mode == PressMode::Hybrid && !status.released() {
+            return None;
+        }
+        let pressed_at = self.pressed_at.remove(&key);
+        if self.mode == PressMode::Hybrid && pressed_at.is_none() {
+            // The firmware may follow a triggered report with a plain release;
+            // without a recorded press that release was handled already
+            return None;
+        }
+
+        // In host mode a release without a recorded press counts as short press
+        let duration = pressed_at
+            .map(|pressed_at| now.saturating_duration_since(pressed_at))
+            .unwrap_or_default();
+        let kind = if duration >= self.threshold {
+            PressKind::Long
+        } else {
+            PressKind::Short
+        };
+        Some(Press {
+            button,
+            kind,
+            duration: Some(duration),
+        })
+    }
+}
+
+impl Default for PressClassifier {
+    fn default() -> Self {
+        Self::new(PressMode::default(), DEFAULT_LONGPRESS_THRESHOLD)
+    }
+}
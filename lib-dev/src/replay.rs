@@ -0,0 +1,257 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Replay of captured HID traffic.
+//!
+//! `ReplayTransport` presents every device of a capture as if it were
+//! attached. Each time the host opens a device it gets the next connection of
+//! the capture: the reports the device sent are handed out in their original
+//! order and, unless pacing is disabled, with their original delays relative
+//! to opening the connection. A captured disconnect ends the connection, so
+//! the host reconnects and continues with the next one. Reports written by the
+//! host are accepted and kept for inspection.
+
+use crate::capture::{read_capture, CaptureEntry, CaptureError, CaptureRecord};
+use crate::hid_device::HidError;
+use crate::transport::{DeviceDescriptor, HidConnection, HidTransport};
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Report written by the host, with the serial of the device it was written to
+pub type WrittenReport = (Option<String>, Vec<u8>);
+
+/// One captured connection: from opening the device until it disconnected
+#[derive(Debug, Default)]
+struct Segment {
+    /// Reports read by the host with their delay after opening
+    reports: VecDeque<(Duration, Vec<u8>)>,
+    /// Delay after which the device disconnected, if it did
+    disconnect: Option<Duration>,
+}
+
+struct ReplayDevice {
+    descriptor: DeviceDescriptor,
+    segments: VecDeque<Segment>,
+    open: Arc<AtomicBool>,
+}
+
+/// Transport replaying a capture written by `CaptureTransport`
+pub struct ReplayTransport {
+    devices: Mutex<Vec<ReplayDevice>>,
+    paced: bool,
+    remaining: Arc<AtomicUsize>,
+    written: Arc<Mutex<Vec<WrittenReport>>>,
+}
+
+impl ReplayTransport {
+    /// Replay the records of a capture
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        let mut devices: Vec<ReplayDevice> = Vec::new();
+        let mut opened_at: Vec<u64> = Vec::new();
+        let mut remaining = 0;
+
+        for record in records {
+            let index = devices
+                .iter()
+                .position(|d| d.descriptor.serial_number == record.serial);
+            let offset = |index: usize| {
+                Duration::from_micros(record.timestamp_us.saturating_sub(opened_at[index]))
+            };
+
+            match (record.entry, index) {
+                (CaptureEntry::Connect { vendor_id, product_id, product, manufacturer }, index) => {
+                    let index = index.unwrap_or_else(|| {
+                        let path = format!(
+                            "replay/{}",
+                            record.serial.clone().unwrap_or_else(|| devices.len().to_string())
+                        );
+                        let mut descriptor = DeviceDescriptor::new(vendor_id, product_id).with_path(path);
+                        descriptor.serial_number = record.serial.clone();
+                        descriptor.product = product;
+                        descriptor.manufacturer = manufacturer;
+                        devices.push(ReplayDevice {
+                            descriptor,
+                            segments: VecDeque::new(),
+                            open: Arc::new(AtomicBool::new(false)),
+                        });
+                        opened_at.push(0);
+                        devices.len() - 1
+                    });
+                    opened_at[index] = record.timestamp_us;
+                    devices[index].segments.push_back(Segment::default());
+                }
+                (CaptureEntry::Rx { report_id, data }, Some(index)) => {
+                    let offset = offset(index);
+                    if let Some(segment) = devices[index].segments.back_mut().filter(|s| s.disconnect.is_none()) {
+                        let mut report = vec![report_id];
+                        report.extend(data);
+                        segment.reports.push_back((offset, report));
+                        remaining += 1;
+                    }
+                }
+                (CaptureEntry::Disconnect, Some(index)) => {
+                    let offset = offset(index);
+                    if let Some(segment) = devices[index].segments.back_mut() {
+                        segment.disconnect.get_or_insert(offset);
+                    }
+                }
+                (CaptureEntry::Tx { .. }, Some(_)) => {}
+                (entry, None) => {
+                    warn!("Ignoring {:?} of {:?} before it was connected", entry, record.serial);
+                }
+            }
+        }
+
+        Self {
+            devices: Mutex::new(devices),
+            paced: true,
+            remaining: Arc::new(AtomicUsize::new(remaining)),
+            written: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Replay a capture file
+    pub fn from_file(path: &Path) -> Result<Self, CaptureError> {
+        Ok(Self::new(read_capture(path)?))
+    }
+
+    /// Hand out reports as soon as the host reads instead of with their
+    /// original delays; the order is kept
+    pub fn without_pacing(mut self) -> Self {
+        self.paced = false;
+        self
+    }
+
+    /// Number of captured reports the host has not read yet
+    pub fn remaining_reports(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// True once the host has read every captured report
+    pub fn is_finished(&self) -> bool {
+        self.remaining_reports() == 0
+    }
+
+    /// Reports written by the host so far, with the serial of the device
+    pub fn written(&self) -> Vec<WrittenReport> {
+        self.written.lock().unwrap().clone()
+    }
+}
+
+impl HidTransport for ReplayTransport {
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError> {
+        Ok(self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|d| !d.segments.is_empty() && !d.open.load(Ordering::SeqCst))
+            .map(|d| d.descriptor.clone())
+            .collect())
+    }
+
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError> {
+        let mut devices = self.devices.lock().unwrap();
+        let device = devices
+            .iter_mut()
+            .find(|d| d.descriptor.path == descriptor.path && !d.open.load(Ordering::SeqCst))
+            .ok_or(HidError::NotConnected)?;
+        let segment = device.segments.pop_front().ok_or(HidError::NotConnected)?;
+
+        debug!(
+            "Replaying connection of {:?} with {} reports",
+            device.descriptor.serial_number,
+            segment.reports.len()
+        );
+        device.open.store(true, Ordering::SeqCst);
+        Ok(Box::new(ReplayConnection {
+            serial: device.descriptor.serial_number.clone(),
+            opened: Instant::now(),
+            paced: self.paced,
+            segment: Mutex::new(segment),
+            open: device.open.clone(),
+            remaining: self.remaining.clone(),
+            written: self.written.clone(),
+        }))
+    }
+}
+
+struct ReplayConnection {
+    serial: Option<String>,
+    opened: Instant,
+    paced: bool,
+    segment: Mutex<Segment>,
+    open: Arc<AtomicBool>,
+    remaining: Arc<AtomicUsize>,
+    written: Arc<Mutex<Vec<WrittenReport>>>,
+}
+
+impl ReplayConnection {
+    /// Time until an event captured `offset` after opening is due
+    fn until(&self, offset: Duration) -> Duration {
+        if self.paced {
+            (self.opened + offset).saturating_duration_since(Instant::now())
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    fn is_disconnected(&self, segment: &Segment) -> bool {
+        segment.reports.is_empty()
+            && segment.disconnect.is_some_and(|at| self.until(at).is_zero())
+    }
+}
+
+impl HidConnection for ReplayConnection {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        if self.is_disconnected(&self.segment.lock().unwrap()) {
+            return Err(HidError::WriteFailed("replayed device disconnected".to_string()));
+        }
+        self.written.lock().unwrap().push((self.serial.clone(), data.to_vec()));
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        let mut segment = self.segment.lock().unwrap();
+
+        let next_event = match segment.reports.front() {
+            Some((offset, _)) => Some(*offset),
+            None => segment.disconnect,
+        };
+        let Some(offset) = next_event else {
+            // The capture ended with the device still attached; it stays idle
+            drop(segment);
+            std::thread::sleep(timeout);
+            return Ok(0);
+        };
+
+        let wait = self.until(offset);
+        if wait > timeout {
+            drop(segment);
+            std::thread::sleep(timeout);
+            return Ok(0);
+        }
+        std::thread::sleep(wait);
+
+        match segment.reports.pop_front() {
+            Some((_, report)) => {
+                self.remaining.fetch_sub(1, Ordering::SeqCst);
+                let size = report.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&report[..size]);
+                Ok(size)
+            }
+            None => Err(HidError::ReadFailed("replayed device disconnected".to_string())),
+        }
+    }
+}
+
+impl Drop for ReplayConnection {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Transport abstraction between `HidDevice` and the underlying HID stack.
+//!
+//! `HidApiTransport` talks to real hardware through hidapi and is the default.
+//! `LoopbackTransport` keeps everything in memory so the device handler can be
+//! exercised without a macropad attached.
+
+use crate::hid_device::HidError;
+use hidapi::HidApi;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Description of an enumerated HID device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceDescriptor {
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub usage_page: u16,
+    pub interface_number: i32,
+}
+
+impl DeviceDescriptor {
+    pub fn new(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            path: format!("{:04x}:{:04x}", vendor_id, product_id),
+            vendor_id,
+            product_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    pub fn with_serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.serial_number = Some(serial_number.into());
+        self
+    }
+
+    pub fn with_manufacturer(mut self, manufacturer: impl Into<String>) -> Self {
+        self.manufacturer = Some(manufacturer.into());
+        self
+    }
+
+    pub fn with_product(mut self, product: impl Into<String>) -> Self {
+        self.product = Some(product.into());
+        self
+    }
+}
+
+/// An open connection to a single HID device
+pub trait HidConnection: Send {
+    /// Write a report; the first byte is the report ID
+    fn write(&self, data: &[u8]) -> Result<usize, HidError>;
+
+    /// Read a report, waiting at most `timeout_ms` milliseconds.
+    /// Returns `Ok(0)` when no report arrived in time.
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError>;
+}
+
+/// Source of HID devices
+pub trait HidTransport: Send + Sync {
+    /// List all devices currently visible on this transport
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError>;
+
+    /// Open a previously enumerated device
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError>;
+}
+
+impl HidConnection for hidapi::HidDevice {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        hidapi::HidDevice::write(self, data).map_err(|e| HidError::WriteFailed(e.to_string()))
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        hidapi::HidDevice::read_timeout(self, buffer, timeout_ms)
+            .map_err(|e| HidError::ReadFailed(e.to_string()))
+    }
+}
+
+/// Transport backed by hidapi (real hardware)
+#[derive(Debug, Default)]
+pub struct HidApiTransport;
+
+impl HidApiTransport {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl HidTransport for HidApiTransport {
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError> {
+        let api = HidApi::new().map_err(|e| HidError::HidApiError(e.to_string()))?;
+
+        Ok(api
+            .device_list()
+            .map(|d| DeviceDescriptor {
+                path: d.path().to_string_lossy().into_owned(),
+                vendor_id: d.vendor_id(),
+                product_id: d.product_id(),
+                serial_number: d.serial_number().map(str::to_string),
+                manufacturer: d.manufacturer_string().map(str::to_string),
+                product: d.product_string().map(str::to_string),
+                usage_page: d.usage_page(),
+                interface_number: d.interface_number(),
+            })
+            .collect())
+    }
+
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError> {
+        let api = HidApi::new().map_err(|e| HidError::HidApiError(e.to_string()))?;
+        let path = CString::new(descriptor.path.as_str())
+            .map_err(|e| HidError::HidApiError(e.to_string()))?;
+
+        let device = api
+            .open_path(&path)
+            .map_err(|e| HidError::HidApiError(e.to_string()))?;
+        Ok(Box::new(device))
+    }
+}
+
+/// In-memory transport for tests and simulations
+#[derive(Clone, Default)]
+pub struct LoopbackTransport {
+    devices: Arc<Mutex<Vec<LoopbackDevice>>>,
+}
+
+impl LoopbackTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a new simulated device and return a handle to drive it
+    pub fn add_device(&self, descriptor: DeviceDescriptor) -> LoopbackDevice {
+        let device = LoopbackDevice {
+            descriptor,
+            inner: Arc::new(LoopbackInner::default()),
+        };
+        device.inner.connected.store(true, Ordering::SeqCst);
+        self.devices.lock().unwrap().push(device.clone());
+        device
+    }
+}
+
+impl HidTransport for LoopbackTransport {
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError> {
+        Ok(self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|d| d.is_connected())
+            .map(|d| d.descriptor.clone())
+            .collect())
+    }
+
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError> {
+        let devices = self.devices.lock().unwrap();
+        let device = devices
+            .iter()
+            .find(|d| d.descriptor.path == descriptor.path && d.is_connected())
+            .ok_or(HidError::NotConnected)?;
+
+        Ok(Box::new(LoopbackConnection {
+            inner: device.inner.clone(),
+            generation: device.inner.generation.load(Ordering::SeqCst),
+        }))
+    }
+}
+
+#[derive(Default)]
+struct LoopbackInner {
+    incoming: Mutex<VecDeque<Vec<u8>>>,
+    incoming_ready: Condvar,
+    outgoing: Mutex<Vec<Vec<u8>>>,
+    connected: AtomicBool,
+    generation: AtomicU64,
+}
+
+/// Handle to a simulated device attached to a `LoopbackTransport`
+#[derive(Clone)]
+pub struct LoopbackDevice {
+    descriptor: DeviceDescriptor,
+    inner: Arc<LoopbackInner>,
+}
+
+impl LoopbackDevice {
+    pub fn descriptor(&self) -> &DeviceDescriptor {
+        &self.descriptor
+    }
+
+    /// Queue a report for the host to read (device -> host)
+    pub fn inject(&self, report: &[u8]) {
+        self.inner.incoming.lock().unwrap().push_back(report.to_vec());
+        self.inner.incoming_ready.notify_all();
+    }
+
+    /// Take all reports the host has written so far (host -> device)
+    pub fn take_written(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.inner.outgoing.lock().unwrap())
+    }
+
+    /// Simulate plugging the device in or pulling it out.
+    /// Connections opened before an unplug stay broken after replugging.
+    pub fn set_connected(&self, connected: bool) {
+        if !connected {
+            self.inner.generation.fetch_add(1, Ordering::SeqCst);
+            self.inner.incoming.lock().unwrap().clear();
+        }
+        self.inner.connected.store(connected, Ordering::SeqCst);
+        self.inner.incoming_ready.notify_all();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
+    }
+}
+
+struct LoopbackConnection {
+    inner: Arc<LoopbackInner>,
+    generation: u64,
+}
+
+impl LoopbackConnection {
+    fn is_alive(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
+            && self.inner.generation.load(Ordering::SeqCst) == self.generation
+    }
+}
+
+impl HidConnection for LoopbackConnection {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        if !self.is_alive() {
+            return Err(HidError::WriteFailed("device unplugged".to_string()));
+        }
+        self.inner.outgoing.lock().unwrap().push(data.to_vec());
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        let incoming = self.inner.incoming.lock().unwrap();
+        let (mut incoming, _) = self
+            .inner
+            .incoming_ready
+            .wait_timeout_while(incoming, timeout, |queue| queue.is_empty() && self.is_alive())
+            .unwrap();
+
+        if !self.is_alive() {
+            return Err(HidError::ReadFailed("device unplugged".to_string()));
+        }
+
+        match incoming.pop_front() {
+            Some(report) => {
+                let size = report.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&report[..size]);
+                Ok(size)
+            }
+            None => Ok(0),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Connection health monitoring.
+//!
+//! `HidDevice` pings the device periodically; the firmware answers every ping
+//! with a VersionInfo message. Any report received before the next ping is due
+//! answers a ping, one that stays without answer counts as missed. After `degraded_after` consecutive misses the connection
+//! is `Degraded`, after `error_after` it is `Error`: the connection is dropped
+//! and opened again after a delay that doubles with every further failure.
+
+use crate::constants::{
+    DEFAULT_DEGRADED_AFTER_MISSES, DEFAULT_ERROR_AFTER_MISSES, DEFAULT_MAX_RECONNECT_DELAY_MS,
+    DEFAULT_RECONNECT_DELAY_MS, PING_LOOP_TIME_SECONDS,
+};
+use std::time::Duration;
+
+/// Settings of the connection watchdog
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    ping_interval: Duration,
+    degraded_after: u32,
+    error_after: u32,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(PING_LOOP_TIME_SECONDS),
+            degraded_after: DEFAULT_DEGRADED_AFTER_MISSES,
+            error_after: DEFAULT_ERROR_AFTER_MISSES,
+            reconnect_delay: Duration::from_millis(DEFAULT_RECONNECT_DELAY_MS),
+            max_reconnect_delay: Duration::from_millis(DEFAULT_MAX_RECONNECT_DELAY_MS),
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time between two pings; also the time the device has to answer one
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// Consecutive missed pings after which the connection is degraded, at least 1
+    pub fn with_degraded_after(mut self, misses: u32) -> Self {
+        self.degraded_after = misses.max(1);
+        self
+    }
+
+    /// Consecutive missed pings after which the connection is dropped, at least 1
+    pub fn with_error_after(mut self, misses: u32) -> Self {
+        self.error_after = misses.max(1);
+        self
+    }
+
+    /// Delay before reconnecting to a device that stopped responding and the
+    /// limit it doubles up to while the device keeps failing
+    pub fn with_reconnect_delay(mut self, delay: Duration, max_delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self.max_reconnect_delay = max_delay.max(delay);
+        self
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        self.ping_interval
+    }
+
+    pub fn degraded_after(&self) -> u32 {
+        self.degraded_after
+    }
+
+    pub fn error_after(&self) -> u32 {
+        self.error_after
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        self.reconnect_delay
+    }
+
+    pub fn max_reconnect_delay(&self) -> Duration {
+        self.max_reconnect_delay
+    }
+}
+
+/// Latency and error statistics of a device, kept across reconnects
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub pings_sent: u64,
+    /// Pings followed by any report from the device before the next was due
+    pub pings_answered: u64,
+    pub pings_missed: u64,
+    /// Pings missed since the last answer
+    pub consecutive_misses: u32,
+    /// Round-trip time of the last ping answered with VersionInfo
+    pub last_rtt: Option<Duration>,
+    pub min_rtt: Option<Duration>,
+    pub max_rtt: Option<Duration>,
+    /// Moving average of the round-trip time, weighting the last ping with 1/8
+    pub average_rtt: Option<Duration>,
+    /// The round-trip times are taken from replies that cannot be matched to
+    /// a particular ping, as VersionInfo does not repeat the ping counter
+    pub rtt_approximate: bool,
+    pub read_errors: u64,
+    pub write_errors: u64,
+    /// Connections dropped because the device stopped answering pings
+    pub watchdog_resets: u64,
+    /// Connections made after the first one
+    pub reconnects: u64,
+}
+
+impl LinkStats {
+    /// Record a ping followed by a report from the device
+    pub(crate) fn record_answer(&mut self) {
+        self.pings_answered += 1;
+        self.consecutive_misses = 0;
+    }
+
+    /// Record the round-trip time of an answered ping
+    pub(crate) fn record_rtt(&mut self, rtt: Duration, approximate: bool) {
+        self.rtt_approximate = approximate;
+        self.last_rtt = Some(rtt);
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        self.max_rtt = Some(self.max_rtt.map_or(rtt, |max| max.max(rtt)));
+        self.average_rtt = Some(match self.average_rtt {
+            Some(average) => (average * 7 + rtt) / 8,
+            None => rtt,
+        });
+    }
+
+    /// Record a ping that was not answered in time
+    pub(crate) fn record_miss(&mut self) {
+        self.pings_missed += 1;
+        self.consecutive_misses += 1;
+    }
+}
@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Bounded, prioritized write queue of a device handler.
+//!
+//! Control commands are written before LED updates. A queued command with a
+//! coalesce key, such as `SetLed`, is replaced by a newer one with the same
+//! key, so LED updates take at most one entry per LED. All other commands
+//! take one of a limited number of slots; `QueuePolicy` decides what happens
+//! when none is free, e.g. while no device has been connected yet.
+
+use crate::constants::DEFAULT_WRITE_QUEUE_CAPACITY;
+use crate::hid_commands::{HidOutputCommand, WritePriority};
+use crate::hid_device::HidError;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, Notify, OwnedSemaphorePermit, Semaphore};
+
+/// What to do with a command when all slots of the queue are taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueuePolicy {
+    /// Wait until a queued command has been written
+    #[default]
+    Backpressure,
+    /// Fail the new command with `HidError::QueueFull`
+    RejectNew,
+    /// Fail the oldest queued command with `HidError::QueueFull` to make room
+    DropOldest,
+}
+
+/// Settings of the write queue
+#[derive(Debug, Clone)]
+pub struct WriteQueueConfig {
+    capacity: usize,
+    policy: QueuePolicy,
+}
+
+impl Default for WriteQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_WRITE_QUEUE_CAPACITY,
+            policy: QueuePolicy::default(),
+        }
+    }
+}
+
+impl WriteQueueConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Commands without coalesce key that may wait in the queue, at least 1
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: QueuePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> QueuePolicy {
+        self.policy
+    }
+}
+
+pub(crate) type CommandResponder = oneshot::Sender<Result<usize, HidError>>;
+
+/// A command waiting to be written, with the sender of its result
+pub(crate) struct QueuedCommand {
+    pub(crate) command: Box<dyn HidOutputCommand + Send>,
+    pub(crate) response: CommandResponder,
+    /// Slot taken by commands without coalesce key, freed once written
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Default)]
+struct Pending {
+    control: VecDeque<QueuedCommand>,
+    led: VecDeque<QueuedCommand>,
+    closed: bool,
+}
+
+impl Pending {
+    fn lane(&mut self, priority: WritePriority) -> &mut VecDeque<QueuedCommand> {
+        match priority {
+            WritePriority::Control => &mut self.control,
+            WritePriority::Led => &mut self.led,
+        }
+    }
+}
+
+pub(crate) struct WriteQueue {
+    config: WriteQueueConfig,
+    pending: Mutex<Pending>,
+    available: Notify,
+    /// Replaced when the queue is opened again, as a closed semaphore stays closed
+    slots: Mutex<Arc<Semaphore>>,
+}
+
+impl WriteQueue {
+    pub(crate) fn new(config: WriteQueueConfig) -> Self {
+        let slots = Arc::new(Semaphore::new(config.capacity));
+        Self {
+            config,
+            pending: Mutex::new(Pending::default()),
+            available: Notify::new(),
+            slots: Mutex::new(slots),
+        }
+    }
+
+    /// Queue a command according to its priority and coalesce key
+    pub(crate) async fn push(
+        &self,
+        command: Box<dyn HidOutputCommand + Send>,
+        response: CommandResponder,
+    ) -> Result<(), HidError> {
+        let priority = command.priority();
+        let slot = match command.coalesce_key() {
+            Some(_) => None,
+            None => Some(self.take_slot().await?),
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        if pending.closed {
+            return Err(HidError::Stopped);
+        }
+        let lane = pending.lane(priority);
+        let queued = QueuedCommand {
+            command,
+            response,
+            slot,
+        };
+        let key = queued.command.coalesce_key();
+        match lane
+            .iter_mut()
+            .find(|other| key.is_some() && other.command.coalesce_key() == key)
+        {
+            Some(other) => {
+                let replaced = std::mem::replace(other, queued);
+                let _ = replaced.response.send(Err(HidError::Superseded));
+            }
+            None => lane.push_back(queued),
+        }
+        drop(pending);
+
+        self.available.notify_one();
+        Ok(())
+    }
+
+    /// Take a slot for a command without coalesce key, applying the policy if none is free
+    async fn take_slot(&self) -> Result<OwnedSemaphorePermit, HidError> {
+        let slots = self.slots.lock().unwrap().clone();
+        match slots.clone().try_acquire_owned() {
+            Ok(slot) => return Ok(slot),
+            Err(tokio::sync::TryAcquireError::Closed) => return Err(HidError::Stopped),
+            Err(tokio::sync::TryAcquireError::NoPermits) => {}
+        }
+
+        match self.config.policy {
+            QueuePolicy::RejectNew => Err(HidError::QueueFull),
+            QueuePolicy::DropOldest => {
+                let oldest = {
+                    let mut pending = self.pending.lock().unwrap();
+                    let position = pending.control.iter().position(|queued| queued.slot.is_some());
+                    match position {
+                        Some(position) => pending.control.remove(position),
+                        None => {
+                            let position = pending.led.iter().position(|queued| queued.slot.is_some());
+                            position.and_then(|position| pending.led.remove(position))
+                        }
+                    }
+                };
+                match oldest {
+                    Some(QueuedCommand { response, slot: Some(slot), .. }) => {
+                        let _ = response.send(Err(HidError::QueueFull));
+                        Ok(slot)
+                    }
+                    // All slots belong to commands being written right now
+                    _ => slots.acquire_owned().await.map_err(|_| HidError::Stopped),
+                }
+            }
+            QueuePolicy::Backpressure => slots.acquire_owned().await.map_err(|_| HidError::Stopped),
+        }
+    }
+
+    /// Wait for the next command, control commands first
+    pub(crate) async fn pop(&self) -> QueuedCommand {
+        loop {
+            {
+                let mut pending = self.pending.lock().unwrap();
+                if let Some(queued) = pending.control.pop_front().or_else(|| pending.led.pop_front()) {
+                    return queued;
+                }
+            }
+            // A push in between leaves a permit, so no wake-up is lost
+            self.available.notified().await;
+        }
+    }
+
+    /// Reject further commands and take all queued ones; waiting senders fail with `Stopped`
+    pub(crate) fn close(&self) -> Vec<QueuedCommand> {
+        self.slots.lock().unwrap().close();
+        let mut pending = self.pending.lock().unwrap();
+        pending.closed = true;
+        let mut queued: Vec<QueuedCommand> = pending.control.drain(..).collect();
+        queued.extend(pending.led.drain(..));
+        queued
+    }
+
+    /// Accept commands again after `close`
+    pub(crate) fn open(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.closed {
+            *self.slots.lock().unwrap() = Arc::new(Semaphore::new(self.config.capacity));
+            pending.closed = false;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn spawn_device(transport: Arc<dyn HidTransport>) -> (Arc<HidDevice>, Arc<Mutex<Vec<DeviceMessage>>>) {
+    let device = Arc::new(HidDevice::with_transport(Vec::new(), transport));
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let process_device = device.clone();
+    let received_clone = received.clone();
+    tokio::spawn(async move {
+        process_device
+            .register_callback(move |message| received_clone.lock().unwrap().push(message))
+            .await;
+        let _ = process_device.process().await;
+    });
+    (device, received)
+}
+
+fn pressed_buttons(received: &Mutex<Vec<DeviceMessage>>) -> Vec<(u8, bool)> {
+    received
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|message| match message {
+            DeviceMessage::Status(status) => Some((status.button(), status.pressed())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn record(timestamp_us: u64, entry: CaptureEntry) -> CaptureRecord {
+    CaptureRecord { timestamp_us, serial: Some("ABC".to_string()), entry }
+}
+
+fn connect() -> CaptureEntry {
+    CaptureEntry::Connect {
+        vendor_id: 0x1d50,
+        product_id: 0x6189,
+        product: Some("Mutenix Macropad".to_string()),
+        manufacturer: None,
+    }
+}
+
+fn status(button: u8, pressed: bool) -> CaptureEntry {
+    CaptureEntry::Rx { report_id: 1, data: vec![0x01, button, 0, 0, pressed as u8, !pressed as u8, 0] }
+}
+
+#[tokio::test]
+async fn test_capture_records_both_directions() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("capture.jsonl");
+
+    let loopback = LoopbackTransport::new();
+    let pad = loopback.add_device(mutenix_descriptor("ABC"));
+    let writer = Arc::new(CaptureWriter::create(&path).unwrap());
+    let (device, received) = spawn_device(Arc::new(CaptureTransport::new(Arc::new(loopback), writer)));
+
+    // The version request is the first report the host writes
+    assert!(eventually(|| !pad.take_written().is_empty()).await);
+    pad.inject(&[1, 0x01, 3, 0, 0, 1, 0, 0]);
+    assert!(eventually(|| pressed_buttons(&received) == vec![(3, true)]).await);
+    device.stop().await;
+
+    let records = read_capture(&path).unwrap();
+    assert!(records.iter().all(|r| r.serial.as_deref() == Some("ABC")));
+    assert!(records.windows(2).all(|w| w[0].timestamp_us <= w[1].timestamp_us));
+    assert!(matches!(&records[0].entry, CaptureEntry::Connect { vendor_id: 0x1d50, product, .. }
+        if product.as_deref() == Some("Mutenix Macropad")));
+    assert!(records.iter().any(|r| r.entry
+        == CaptureEntry::Tx { report_id: 1, data: vec![HidOutCommand::Ping as u8, 0, 0, 0, 0, 0, 0, 0] }));
+    assert!(records.iter().any(|r| r.entry == status(3, true)));
+
+    // Reports are stored as hex
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains(r#""direction":"rx","report_id":1,"data":"01030000010000""#));
+}
+
+#[tokio::test]
+async fn test_replay_feeds_reports_in_order() {
+    let records = vec![
+        record(0, connect()),
+        record(1_000, CaptureEntry::Tx { report_id: 1, data: vec![0xf0, 0, 0, 0, 0, 0, 0, 0] }),
+        record(2_000, status(3, true)),
+        record(3_000, status(3, false)),
+        record(4_000, status(1, true)),
+    ];
+    let transport = Arc::new(ReplayTransport::new(records).without_pacing());
+    assert_eq!(transport.remaining_reports(), 3);
+
+    let (device, received) = spawn_device(transport.clone());
+    assert!(eventually(|| transport.is_finished()).await);
+    assert!(eventually(|| pressed_buttons(&received).len() == 3).await);
+    assert_eq!(pressed_buttons(&received), vec![(3, true), (3, false), (1, true)]);
+
+    // The replayed device accepts what the host writes
+    assert!(eventually(|| !transport.written().is_empty()).await);
+    assert_eq!(transport.written()[0].0.as_deref(), Some("ABC"));
+    assert_eq!(device.state().await.product.as_deref(), Some("Mutenix Macropad"));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_replay_keeps_timing() {
+    let records = vec![
+        record(0, connect()),
+        record(10_000, status(3, true)),
+        record(310_000, status(3, false)),
+    ];
+    let transport = Arc::new(ReplayTransport::new(records));
+
+    let started = Instant::now();
+    let (device, received) = spawn_device(transport.clone());
+    assert!(eventually(|| pressed_buttons(&received).len() == 2).await);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_replay_reconnects_after_captured_disconnect() {
+    let records = vec![
+        record(0, connect()),
+        record(1_000, status(1, true)),
+        record(2_000, CaptureEntry::Disconnect),
+        record(3_000_000, connect()),
+        record(3_001_000, status(2, true)),
+    ];
+    let transport = Arc::new(ReplayTransport::new(records).without_pacing());
+
+    let (device, received) = spawn_device(transport.clone());
+    assert!(eventually(|| pressed_buttons(&received).len() == 2).await);
+    assert_eq!(pressed_buttons(&received), vec![(1, true), (2, true)]);
+    assert!(transport.enumerate().unwrap().is_empty());
+
+    device.stop().await;
+}
+
+#[test]
+fn test_invalid_capture_line() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("broken.jsonl");
+    std::fs::write(
+        &path,
+        "{\"timestamp_us\":0,\"serial\":null,\"direction\":\"disconnect\"}\n\n\
+         {\"timestamp_us\":1,\"serial\":null,\"direction\":\"rx\",\"report_id\":1,\"data\":\"0g\"}\n",
+    )
+    .unwrap();
+
+    assert!(matches!(read_capture(&path), Err(CaptureError::InvalidRecord { line: 3, .. })));
+}
@@ -2,7 +2,8 @@
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
 use mutenix_hid::chunks::*;
-use mutenix_hid::constants::MAX_CHUNK_SIZE;
+use mutenix_hid::constants::{HEADER_SIZE, MAX_CHUNK_SIZE, MAX_FILE_SIZE};
+use mutenix_hid::device_update::{TransferFile, UpdateError};
 
     #[test]
     fn test_file_chunk_packet() {
@@ -23,7 +24,7 @@ use mutenix_hid::constants::MAX_CHUNK_SIZE;
 
     #[test]
     fn test_file_start_packet() {
-        let start = FileStart::new(1, 0, 3, "test.py", 100);
+        let start = FileStart::new(1, 0, 3, "test.py", 100).unwrap();
         let packet = start.inner().packet();
         
         // Check type (FileStart = 1)
@@ -58,7 +59,7 @@ use mutenix_hid::constants::MAX_CHUNK_SIZE;
 
     #[test]
     fn test_file_delete_packet() {
-        let delete = FileDelete::new(1, "test.py");
+        let delete = FileDelete::new(1, "test.py").unwrap();
         let packet = delete.inner().packet();
         
         // Check type (FileDelete = 5)
@@ -75,13 +76,13 @@ use mutenix_hid::constants::MAX_CHUNK_SIZE;
     fn test_chunk_acked() {
         let mut chunk = Chunk::new(ChunkType::FileChunk, 1, 2, 3);
         
-        assert_eq!(chunk.is_acked(), false);
+        assert!(!chunk.is_acked());
         
         chunk.set_acked(true);
-        assert_eq!(chunk.is_acked(), true);
+        assert!(chunk.is_acked());
         
         chunk.set_acked(false);
-        assert_eq!(chunk.is_acked(), false);
+        assert!(!chunk.is_acked());
     }
 
     #[test]
@@ -94,3 +95,89 @@ use mutenix_hid::constants::MAX_CHUNK_SIZE;
         // Check id is 0
         assert_eq!(&packet[2..4], &(0u16).to_le_bytes());
     }
+
+/// Size indicator and size bytes of a FileStart packet
+fn encoded_size(start: &FileStart) -> Vec<u8> {
+    let content = &start.inner().content;
+    content[1 + content[0] as usize..].to_vec()
+}
+
+#[test]
+fn test_file_start_size_indicator() {
+    let start = FileStart::new(1, 0, 1261, "big.bin", 65535).unwrap();
+    assert_eq!(encoded_size(&start), vec![2, 0xff, 0xff]);
+
+    let start = FileStart::new(1, 0, 1261, "big.bin", 65536).unwrap();
+    assert_eq!(encoded_size(&start), vec![4, 0, 0, 1, 0]);
+
+    let start = FileStart::new(1, 0, u16::MAX, "big.bin", MAX_FILE_SIZE).unwrap();
+    assert_eq!(encoded_size(&start)[0], 4);
+    assert_eq!(
+        &encoded_size(&start)[1..],
+        &(MAX_FILE_SIZE as u32).to_le_bytes()
+    );
+}
+
+#[test]
+fn test_file_start_rejects_unrepresentable_values() {
+    assert_eq!(
+        FileStart::new(1, 0, 0, "big.bin", MAX_FILE_SIZE + 1).unwrap_err(),
+        ChunkError::FileTooLarge {
+            size: MAX_FILE_SIZE + 1,
+            max: MAX_FILE_SIZE
+        }
+    );
+
+    // Length prefix, name, size indicator and size have to fit into one chunk
+    let name = "n".repeat(MAX_CHUNK_SIZE - 4);
+    assert!(FileStart::new(1, 0, 1, &name, 100).is_ok());
+    assert!(matches!(
+        FileStart::new(1, 0, 1, &format!("{}n", name), 100),
+        Err(ChunkError::FilenameTooLong { max, .. }) if max == MAX_CHUNK_SIZE - 4
+    ));
+
+    let name = "n".repeat(MAX_CHUNK_SIZE - 6);
+    assert!(FileStart::new(1, 0, 1261, &name, 65536).is_ok());
+    assert!(FileStart::new(1, 0, 1261, &format!("{}n", name), 65536).is_err());
+}
+
+#[test]
+fn test_file_delete_filename_length() {
+    let name = "n".repeat(MAX_CHUNK_SIZE - 1);
+    assert_eq!(
+        FileDelete::new(1, &name).unwrap().inner().packet().len(),
+        HEADER_SIZE + MAX_CHUNK_SIZE
+    );
+    assert!(matches!(
+        FileDelete::new(1, &format!("{}n", name)),
+        Err(ChunkError::FilenameTooLong { .. })
+    ));
+}
+
+#[test]
+fn test_transfer_file_package_limits() {
+    let file = TransferFile::from_content(1, "max.bin", vec![0; MAX_FILE_SIZE]).unwrap();
+    let chunks = file.chunks();
+    assert_eq!(chunks.len(), u16::MAX as usize + 2);
+    assert_eq!(chunks[0].total_packages, u16::MAX);
+    let last_data = &chunks[chunks.len() - 2];
+    assert_eq!(
+        (last_data.type_, last_data.package),
+        (ChunkType::FileChunk, u16::MAX - 1)
+    );
+
+    let result = TransferFile::from_content(1, "too_big.bin", vec![0; MAX_FILE_SIZE + 1]);
+    assert!(matches!(
+        result,
+        Err(UpdateError::Chunk(ChunkError::FileTooLarge { .. }))
+    ));
+}
+
+#[test]
+fn test_file_id_limit() {
+    assert_eq!(file_id(u16::MAX as usize), Ok(u16::MAX));
+    assert!(matches!(
+        file_id(u16::MAX as usize + 1),
+        Err(ChunkError::TooManyFiles { .. })
+    ));
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use mutenix_hid::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Descriptor of a Mutenix pad attached to a `LoopbackTransport`
+pub fn mutenix_descriptor(serial: &str) -> DeviceDescriptor {
+    DeviceDescriptor::new(0x1d50, 0x6189)
+        .with_path(format!("loopback/{}", serial))
+        .with_serial_number(serial)
+        .with_manufacturer("Mutenix")
+        .with_product("Mutenix Macropad")
+}
+
+/// Poll until the condition holds or two seconds have passed
+pub async fn eventually<F: FnMut() -> bool>(mut condition: F) -> bool {
+    for _ in 0..200 {
+        if condition() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+/// Poll the hardware state until the condition holds or two seconds have passed
+pub async fn wait_for_state<F: Fn(&HardwareState) -> bool>(device: &HidDevice, condition: F) -> bool {
+    for _ in 0..200 {
+        if condition(&device.state().await) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+/// Run `process()` of the device on a task of its own
+pub fn spawn_process(device: HidDevice) -> Arc<HidDevice> {
+    let device = Arc::new(device);
+    let process_device = device.clone();
+    tokio::spawn(async move {
+        let _ = process_device.process().await;
+    });
+    device
+}
+
+pub fn spawn_device(transport: &LoopbackTransport, device_info: Vec<DeviceInfo>) -> Arc<HidDevice> {
+    spawn_process(HidDevice::with_transport(device_info, Arc::new(transport.clone())))
+}
+
+/// Simulated firmware: calls `respond` for every report the host writes to
+/// the pad, on a thread of its own, until `stop` is set
+pub fn spawn_responder<F>(pad: &LoopbackDevice, stop: Arc<AtomicBool>, mut respond: F)
+where
+    F: FnMut(&LoopbackDevice, Vec<u8>) + Send + 'static,
+{
+    let pad = pad.clone();
+    std::thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            for report in pad.take_written() {
+                respond(&pad, report);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    });
+}
+
+/// Simulated firmware in update mode: acknowledges every transfer packet and
+/// records everything the host writes
+pub fn spawn_update_responder(pad: &LoopbackDevice, stop: Arc<AtomicBool>) -> Arc<Mutex<Vec<Vec<u8>>>> {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let written_clone = written.clone();
+    spawn_responder(pad, stop, move |pad, report| {
+        if report[0] == HID_REPORT_ID_TRANSFER {
+            // type, id and package as sent in the chunk header
+            pad.inject(&[2, b'A', b'K', report[3], report[4], report[7], report[8], report[1]]);
+        }
+        written_clone.lock().unwrap().push(report);
+    });
+    written
+}
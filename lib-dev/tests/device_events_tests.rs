@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn spawn_device(transport: &LoopbackTransport) -> (Arc<HidDevice>, DeviceEvents) {
+    let device = HidDevice::with_transport(Vec::new(), Arc::new(transport.clone()));
+    let events = device.subscribe();
+    (spawn_process(device), events)
+}
+
+/// Next event the predicate accepts, skipping all others; None after two seconds
+async fn next_matching<T, F>(events: &mut DeviceEvents<T>, predicate: F) -> Option<T>
+where
+    T: Clone + Send + 'static,
+    F: Fn(&T) -> bool,
+{
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while let Some(event) = events.next().await {
+            if predicate(&event) {
+                return Some(event);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+#[tokio::test]
+async fn test_connection_and_messages_are_published() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+    let (device, mut events) = spawn_device(&transport);
+
+    match next_matching(&mut events, |e| matches!(e, DeviceEvent::Connected(_))).await {
+        Some(DeviceEvent::Connected(descriptor)) => {
+            assert_eq!(descriptor.serial_number.as_deref(), Some("ABC"))
+        }
+        other => panic!("Expected connected event, got {:?}", other),
+    }
+
+    pad.inject(&[1, 0x01, 3, 0, 0, 1, 0, 0]);
+    pad.inject(&[1, 0x99, 1, 2, 3, HardwareType::FiveButtonUsb as u8, 0, 0]);
+    match next_matching(&mut events, |e| matches!(e, DeviceEvent::Status(_))).await {
+        Some(DeviceEvent::Status(status)) => assert_eq!(status.button(), 3),
+        other => panic!("Expected status, got {:?}", other),
+    }
+    match next_matching(&mut events, |e| matches!(e, DeviceEvent::VersionInfo(_))).await {
+        Some(DeviceEvent::VersionInfo(version_info)) => assert_eq!(version_info.version(), "1.2.3"),
+        other => panic!("Expected version info, got {:?}", other),
+    }
+
+    pad.set_connected(false);
+    assert!(next_matching(&mut events, |e| matches!(e, DeviceEvent::Error(HidError::ReadFailed(_)))).await.is_some());
+    assert!(next_matching(&mut events, |e| matches!(e, DeviceEvent::Disconnected)).await.is_some());
+
+    pad.set_connected(true);
+    assert!(next_matching(&mut events, |e| matches!(e, DeviceEvent::Connected(_))).await.is_some());
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_lagging_subscriber_does_not_block_device() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+    let (device, mut events) = spawn_device(&transport);
+    let mut slow = device.subscribe();
+    // A dropped subscriber must not disturb the others either
+    drop(device.subscribe());
+
+    assert!(next_matching(&mut events, |e| matches!(e, DeviceEvent::Connected(_))).await.is_some());
+
+    let count = EVENT_CAPACITY + 50;
+    for button in 0..count {
+        pad.inject(&[1, 0x01, (button % 10) as u8 + 1, 0, 0, 1, 0, 0]);
+    }
+    for _ in 0..count {
+        assert!(next_matching(&mut events, |e| matches!(e, DeviceEvent::Status(_))).await.is_some());
+    }
+
+    // The slow subscriber lost the oldest events but still gets the newest ones
+    pad.inject(&[1, 0x02, 0, 0, 0, 0, 0, 0]);
+    assert!(next_matching(&mut slow, |e| matches!(e, DeviceEvent::StatusRequest(_))).await.is_some());
+    assert!(slow.skipped() > 0);
+    assert_eq!(events.skipped(), 0);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_manager_events_are_tagged_with_serial() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("LEFT"));
+    let right = transport.add_device(mutenix_descriptor("RIGHT"));
+
+    let manager = Arc::new(DeviceManager::with_transport(Vec::new(), Arc::new(transport.clone())));
+    let mut events = manager.subscribe();
+    let process_manager = manager.clone();
+    tokio::spawn(async move {
+        let _ = process_manager.process().await;
+    });
+
+    let mut connected = Vec::new();
+    while connected.len() < 2 {
+        let tagged = next_matching(&mut events, |t| matches!(t.event, DeviceEvent::Connected(_)))
+            .await
+            .expect("Both devices should connect");
+        connected.push(tagged.serial_number);
+    }
+    connected.sort();
+    assert_eq!(connected, vec!["LEFT", "RIGHT"]);
+
+    right.inject(&[1, 0x01, 4, 0, 0, 1, 0, 0]);
+    let tagged = next_matching(&mut events, |t| matches!(t.event, DeviceEvent::Status(_)))
+        .await
+        .unwrap();
+    assert_eq!(tagged.serial_number, "RIGHT");
+
+    manager.stop().await;
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn chunk_type(report: &[u8]) -> Option<ChunkType> {
+    if report[0] != HID_REPORT_ID_TRANSFER {
+        return None;
+    }
+    Some(match report[1] {
+        1 => ChunkType::FileStart,
+        2 => ChunkType::FileChunk,
+        3 => ChunkType::FileEnd,
+        4 => ChunkType::Complete,
+        5 => ChunkType::FileDelete,
+        other => panic!("Unexpected chunk type {}", other),
+    })
+}
+
+fn is_command(report: &[u8], command: u8) -> bool {
+    report[0] == HID_REPORT_ID_COMMUNICATION && report[1] == command
+}
+
+#[test]
+fn test_batch_keeps_operation_order() {
+    let batch = FsBatch::new()
+        .delete("old.json")
+        .write("keymap.json", b"{}".to_vec())
+        .delete("unused.bmp");
+
+    assert_eq!(batch.operations().len(), 3);
+    assert_eq!(batch.operations()[1].path(), "keymap.json");
+
+    let files = batch.transfer_files().unwrap();
+    let summary: Vec<_> = files
+        .iter()
+        .map(|f| (f.id, f.filename.as_str(), f.chunks()[0].type_))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0, "old.json", ChunkType::FileDelete),
+            (1, "keymap.json", ChunkType::FileStart),
+            (2, "unused.bmp", ChunkType::FileDelete),
+        ]
+    );
+}
+
+#[test]
+fn test_batch_runs_in_one_session_without_reset() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(DeviceDescriptor::new(0x1d50, 0x6189).with_path("loopback/fs"));
+    let mut connection = transport.open(pad.descriptor()).unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let written = spawn_update_responder(&pad, stop.clone());
+
+    let batch = FsBatch::new()
+        .write("keymap.json", vec![b'k'; 120])
+        .delete("old.json");
+    perform_fs_batch_with(connection.as_mut(), &batch, &UpdateOptions::new()).unwrap();
+    stop.store(true, Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(20));
+
+    let mut written = written.lock().unwrap().clone();
+    written.extend(pad.take_written());
+    let prepares = written.iter().filter(|r| is_command(r, HID_COMMAND_PREPARE_UPDATE)).count();
+    assert_eq!(prepares, 1);
+    assert!(!written.iter().any(|r| is_command(r, HID_COMMAND_RESET)));
+
+    let types: Vec<_> = written.iter().filter_map(|r| chunk_type(r)).collect();
+    assert_eq!(types.iter().filter(|t| **t == ChunkType::FileStart).count(), 1);
+    assert_eq!(types.iter().filter(|t| **t == ChunkType::FileDelete).count(), 1);
+    assert_eq!(types.last(), Some(&ChunkType::Complete));
+}
+
+#[test]
+fn test_empty_batch_sends_nothing() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(DeviceDescriptor::new(0x1d50, 0x6189).with_path("loopback/fs"));
+    let mut connection = transport.open(pad.descriptor()).unwrap();
+
+    perform_fs_batch_with(connection.as_mut(), &FsBatch::new(), &UpdateOptions::new()).unwrap();
+    assert!(pad.take_written().is_empty());
+}
+
+#[tokio::test]
+async fn test_push_keeps_device_connected() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(
+        DeviceDescriptor::new(0x1d50, 0x6189)
+            .with_path("loopback/fs")
+            .with_serial_number("ABC")
+            .with_product("Mutenix Macropad"),
+    );
+    let device = Arc::new(HidDevice::with_transport(Vec::new(), Arc::new(transport.clone())));
+    let process_device = device.clone();
+    tokio::spawn(async move {
+        let _ = process_device.process().await;
+    });
+
+    for _ in 0..100 {
+        if device.state().await.connection_status == ConnectionState::Connected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let written = spawn_update_responder(&pad, stop.clone());
+
+    device.fs().push("keymap.json", b"{\"1\": \"mute\"}".to_vec(), &UpdateOptions::new()).await.unwrap();
+    assert_eq!(device.state().await.connection_status, ConnectionState::Connected);
+
+    // Regular commands go through the same connection again
+    device.send_command(SimpleCommand::ping(7)).await.unwrap();
+    device.fs().delete("keymap.json", &UpdateOptions::new()).await.unwrap();
+    stop.store(true, Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(20));
+
+    let mut written = written.lock().unwrap().clone();
+    written.extend(pad.take_written());
+    assert!(written.iter().any(|r| is_command(r, HidOutCommand::Ping as u8) && r[8] == 7));
+    assert!(!written.iter().any(|r| is_command(r, HID_COMMAND_RESET)));
+    assert_eq!(written.iter().filter(|r| chunk_type(r) == Some(ChunkType::Complete)).count(), 2);
+
+    device.stop().await;
+}
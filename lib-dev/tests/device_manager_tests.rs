@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Poll until the manager reports the expected connected serials or two seconds have passed
+async fn wait_for_serials(manager: &DeviceManager, expected: &[&str]) -> bool {
+    for _ in 0..200 {
+        if manager.connected_serials().await == expected {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+fn spawn_manager(transport: &LoopbackTransport) -> Arc<DeviceManager> {
+    let manager = Arc::new(DeviceManager::with_transport(Vec::new(), Arc::new(transport.clone())));
+    let process_manager = manager.clone();
+    tokio::spawn(async move {
+        let _ = process_manager.process().await;
+    });
+    manager
+}
+
+#[tokio::test]
+async fn test_discovers_all_devices() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("LEFT"));
+    transport.add_device(mutenix_descriptor("RIGHT"));
+    transport.add_device(DeviceDescriptor::new(0x046d, 0xc52b).with_product("Keyboard"));
+
+    let manager = spawn_manager(&transport);
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    let states = manager.states().await;
+    assert_eq!(states.len(), 2);
+    assert_eq!(states["LEFT"].serial_number.as_deref(), Some("LEFT"));
+    assert_eq!(states["RIGHT"].serial_number.as_deref(), Some("RIGHT"));
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_devices_without_serial_are_told_apart_by_path() {
+    let transport = LoopbackTransport::new();
+    let pads: Vec<LoopbackDevice> = ["loopback/one", "loopback/two"]
+        .iter()
+        .map(|path| {
+            transport.add_device(
+                DeviceDescriptor::new(0x1d50, 0x6189)
+                    .with_path(*path)
+                    .with_product("Mutenix Macropad"),
+            )
+        })
+        .collect();
+
+    let manager = spawn_manager(&transport);
+    let mut events = manager.subscribe();
+    assert!(wait_for_serials(&manager, &["loopback/one", "loopback/two"]).await);
+
+    // Every handler has opened its own pad
+    pads[0].inject(&[1, 0x01, 1, 0, 0, 1, 0, 0]);
+    pads[1].inject(&[1, 0x01, 2, 0, 0, 1, 0, 0]);
+    let mut buttons = Vec::new();
+    while buttons.len() < 2 {
+        let tagged = tokio::time::timeout(Duration::from_secs(2), events.next())
+            .await
+            .expect("Both pads should report their status")
+            .unwrap();
+        if let DeviceEvent::Status(status) = tagged.event {
+            buttons.push((tagged.serial_number, status.button()));
+        }
+    }
+    buttons.sort();
+    assert_eq!(
+        buttons,
+        vec![("loopback/one".to_string(), 1), ("loopback/two".to_string(), 2)]
+    );
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_messages_are_tagged_with_serial() {
+    let transport = LoopbackTransport::new();
+    let left = transport.add_device(mutenix_descriptor("LEFT"));
+    let right = transport.add_device(mutenix_descriptor("RIGHT"));
+
+    let manager = spawn_manager(&transport);
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    manager
+        .register_callback(move |tagged: TaggedDeviceMessage| {
+            if let DeviceMessage::Status(status) = tagged.message {
+                received_clone.lock().unwrap().push((tagged.serial_number, status.button()));
+            }
+        })
+        .await;
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    left.inject(&[1, 0x01, 1, 0, 0, 1, 0, 0]);
+    right.inject(&[1, 0x01, 4, 0, 0, 1, 0, 0]);
+
+    for _ in 0..100 {
+        if received.lock().unwrap().len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let mut received = received.lock().unwrap().clone();
+    received.sort();
+    assert_eq!(received, vec![("LEFT".to_string(), 1), ("RIGHT".to_string(), 4)]);
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_send_command_to_routes_by_serial() {
+    let transport = LoopbackTransport::new();
+    let left = transport.add_device(mutenix_descriptor("LEFT"));
+    let right = transport.add_device(mutenix_descriptor("RIGHT"));
+
+    let manager = spawn_manager(&transport);
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    manager.send_command_to("RIGHT", SetLed::new(3, LedColor::Blue)).await.unwrap();
+
+    let set_led = |reports: Vec<Vec<u8>>| -> Vec<Vec<u8>> {
+        reports
+            .into_iter()
+            .filter(|report| report[1] == HidOutCommand::SetLed as u8)
+            .collect()
+    };
+    assert!(set_led(left.take_written()).is_empty());
+    assert_eq!(set_led(right.take_written()).len(), 1);
+
+    let result = manager.send_command_to("MISSING", SetLed::new(3, LedColor::Blue)).await;
+    assert!(matches!(result, Err(HidError::NotConnected)));
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_picks_up_devices_attached_later() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("LEFT"));
+
+    let manager = spawn_manager(&transport);
+    assert!(wait_for_serials(&manager, &["LEFT"]).await);
+
+    transport.add_device(mutenix_descriptor("RIGHT"));
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_restarts_after_stop() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("LEFT"));
+
+    let manager = spawn_manager(&transport);
+    assert!(wait_for_serials(&manager, &["LEFT"]).await);
+
+    manager.stop().await;
+    assert!(manager.states().await.is_empty());
+
+    let process_manager = manager.clone();
+    tokio::spawn(async move {
+        let _ = process_manager.process().await;
+    });
+    assert!(wait_for_serials(&manager, &["LEFT"]).await);
+
+    manager.stop().await;
+}
+
+#[tokio::test]
+async fn test_drops_handlers_of_devices_gone_for_good() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("LEFT"));
+    let right = transport.add_device(mutenix_descriptor("RIGHT"));
+
+    let manager = Arc::new(
+        DeviceManager::with_transport(Vec::new(), Arc::new(transport.clone()))
+            .with_removal_delay(Duration::from_millis(200)),
+    );
+    let process_manager = manager.clone();
+    tokio::spawn(async move {
+        let _ = process_manager.process().await;
+    });
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    right.set_connected(false);
+    let mut removed = false;
+    for _ in 0..50 {
+        if !manager.states().await.contains_key("RIGHT") {
+            removed = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(removed);
+    assert!(manager.states().await.contains_key("LEFT"));
+
+    // Plugged in again, it gets a new handler
+    right.set_connected(true);
+    assert!(wait_for_serials(&manager, &["LEFT", "RIGHT"]).await);
+
+    manager.stop().await;
+}
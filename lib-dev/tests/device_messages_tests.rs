@@ -164,3 +164,25 @@ use mutenix_hid::device_messages::*;
         
         assert!(msg.is_none());
     }
+
+    #[test]
+    fn test_parse_hid_update_message_keeps_truncated_frames() {
+        // Too short for the package and type of an acknowledgment
+        let data = b"AK\x01\x00";
+
+        match parse_hid_update_message(data) {
+            Some(HidUpdateMessage::ChunkAck(ack)) => {
+                assert!(ack.is_valid());
+                assert_eq!(ack.id, 1);
+                assert_eq!(ack.package, 0);
+                assert_eq!(ack.type_, 0);
+            }
+            _ => panic!("Expected ChunkAck"),
+        }
+
+        // Error without length byte
+        match parse_hid_update_message(b"ER") {
+            Some(HidUpdateMessage::Error(err)) => assert_eq!(err.info, ""),
+            _ => panic!("Expected Error"),
+        }
+    }
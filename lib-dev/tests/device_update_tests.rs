@@ -4,8 +4,15 @@
 use mutenix_hid::chunks::*;
 use mutenix_hid::device_messages::ChunkAck;
 use mutenix_hid::device_update::*;
+use mutenix_hid::{
+    DeviceDescriptor, HidConnection, HidError, HidTransport, LoopbackDevice, LoopbackTransport,
+    HID_REPORT_ID_TRANSFER,
+};
 use std::fs;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tempfile::TempDir;
 
 fn create_test_file(dir: &TempDir, name: &str, content: &[u8]) -> std::path::PathBuf {
@@ -178,3 +185,171 @@ fn test_transfer_file_chunks() {
         // Should have at least: FileStart, FileChunk(s), FileEnd
         assert!(transfer_file.total_chunks() >= 3);
     }
+
+fn mutenix_descriptor() -> DeviceDescriptor {
+    DeviceDescriptor::new(0x1d50, 0x6189)
+        .with_path("loopback/update")
+        .with_product("Mutenix Macropad")
+}
+
+/// Chunk headers written by the host
+type Packets = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Simulated firmware in update mode. Every transfer packet the host writes is
+/// recorded by its header and acknowledged if `ack` returns true for it.
+fn spawn_responder<F>(pad: &LoopbackDevice, ack: F) -> (Packets, Arc<AtomicBool>)
+where
+    F: Fn(&[u8]) -> bool + Send + 'static,
+{
+    let packets = Arc::new(Mutex::new(Vec::new()));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (packets_clone, stop_clone, pad) = (packets.clone(), stop.clone(), pad.clone());
+    std::thread::spawn(move || {
+        while !stop_clone.load(Ordering::SeqCst) {
+            for report in pad.take_written() {
+                if report[0] != HID_REPORT_ID_TRANSFER {
+                    continue;
+                }
+                if ack(&report[1..9]) {
+                    pad.inject(&[2, b'A', b'K', report[3], report[4], report[7], report[8], report[1]]);
+                }
+                packets_clone.lock().unwrap().push(report[1..9].to_vec());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    });
+    (packets, stop)
+}
+
+#[test]
+fn test_update_retransmits_lost_chunks() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor());
+    let mut connection = transport.open(pad.descriptor()).unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let path = create_test_file(&dir, "main.py", &[b'x'; 200]);
+
+    // The first transmission of data chunk 1 gets lost
+    let dropped = AtomicBool::new(false);
+    let (packets, stop) = spawn_responder(&pad, move |header| {
+        let is_chunk_1 = header[0] == ChunkType::FileChunk as u8 && header[6] == 1;
+        !is_chunk_1 || dropped.swap(true, Ordering::SeqCst)
+    });
+
+    let options = UpdateOptions::new()
+        .with_window_size(4)
+        .with_retransmit_timeout(Duration::from_millis(50));
+    perform_hid_upgrade_with(connection.as_mut(), vec![&path], &options).unwrap();
+    stop.store(true, Ordering::SeqCst);
+
+    let packets = packets.lock().unwrap();
+    let sent = |type_: ChunkType, package: u8| {
+        packets
+            .iter()
+            .filter(|header| header[0] == type_ as u8 && header[6] == package)
+            .count()
+    };
+    assert_eq!(sent(ChunkType::FileChunk, 1), 2);
+    assert_eq!(sent(ChunkType::FileChunk, 0), 1);
+    assert_eq!(sent(ChunkType::FileEnd, 0), 1);
+    assert_eq!(sent(ChunkType::Complete, 0), 1);
+}
+
+#[test]
+fn test_update_gives_up_after_max_retries() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor());
+    let mut connection = transport.open(pad.descriptor()).unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let path = create_test_file(&dir, "main.py", &[b'x'; 500]);
+
+    let (packets, stop) = spawn_responder(&pad, |_| false);
+
+    let options = UpdateOptions::new()
+        .with_window_size(3)
+        .with_retransmit_timeout(Duration::from_millis(20))
+        .with_max_retries(2);
+    let result = perform_hid_upgrade_with(connection.as_mut(), vec![&path], &options);
+    // Let the responder pick up the last retransmits
+    std::thread::sleep(Duration::from_millis(20));
+    stop.store(true, Ordering::SeqCst);
+
+    match result {
+        Err(UpdateError::ChunkTimeout { file, type_, package, attempts }) => {
+            assert_eq!(file, "main.py");
+            assert_eq!(type_, ChunkType::FileStart);
+            assert_eq!(package, 0);
+            assert_eq!(attempts, 3);
+        }
+        other => panic!("Expected chunk timeout, got {:?}", other),
+    }
+
+    // Only the window was ever in flight, each chunk sent once plus two retries
+    let packets = packets.lock().unwrap();
+    let mut distinct = packets.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 3);
+    assert_eq!(packets.len(), 9);
+}
+
+/// Connection whose first reads fail
+struct FlakyConnection {
+    inner: Box<dyn HidConnection>,
+    failing_reads: AtomicU32,
+}
+
+impl HidConnection for FlakyConnection {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        self.inner.write(data)
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        let failing = self.failing_reads.load(Ordering::SeqCst);
+        if failing > 0 {
+            self.failing_reads.store(failing - 1, Ordering::SeqCst);
+            return Err(HidError::ReadFailed("interrupted".to_string()));
+        }
+        self.inner.read_timeout(buffer, timeout_ms)
+    }
+}
+
+#[test]
+fn test_update_survives_transient_read_errors() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor());
+    let mut connection = FlakyConnection {
+        inner: transport.open(pad.descriptor()).unwrap(),
+        failing_reads: AtomicU32::new(2),
+    };
+
+    let dir = TempDir::new().unwrap();
+    let path = create_test_file(&dir, "main.py", &[b'x'; 100]);
+    let (_, stop) = spawn_responder(&pad, |_| true);
+
+    let options = UpdateOptions::new().with_max_retries(2);
+    let result = perform_hid_upgrade_with(&mut connection, vec![&path], &options);
+    stop.store(true, Ordering::SeqCst);
+    result.unwrap();
+}
+
+#[test]
+fn test_update_fails_on_persistent_read_errors() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor());
+    let mut connection = FlakyConnection {
+        inner: transport.open(pad.descriptor()).unwrap(),
+        failing_reads: AtomicU32::new(u32::MAX),
+    };
+
+    let dir = TempDir::new().unwrap();
+    let path = create_test_file(&dir, "main.py", &[b'x'; 100]);
+    let (_, stop) = spawn_responder(&pad, |_| true);
+
+    let options = UpdateOptions::new().with_max_retries(2);
+    let result = perform_hid_upgrade_with(&mut connection, vec![&path], &options);
+    stop.store(true, Ordering::SeqCst);
+    assert!(matches!(result, Err(UpdateError::ReadFailed(_))), "got {:?}", result);
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::*;
+
+fn command_frame<C: HidOutputCommand>(command: &C) -> Vec<u8> {
+    let mut frame = vec![command.report_id()];
+    frame.extend(command.to_buffer());
+    frame
+}
+
+fn chunk_frame(chunk: &Chunk) -> Vec<u8> {
+    let mut frame = vec![HID_REPORT_ID_TRANSFER];
+    frame.extend(chunk.packet());
+    frame
+}
+
+fn tx(frame: &[u8]) -> DissectedFrame {
+    dissect(FrameDirection::HostToDevice, frame)
+}
+
+fn rx(frame: &[u8]) -> DissectedFrame {
+    dissect(FrameDirection::DeviceToHost, frame)
+}
+
+#[test]
+fn test_host_commands() {
+    let frame = tx(&command_frame(&SetLed::new(3, LedColor::Red).with_counter(4)));
+    assert_eq!(frame.to_string(), "SetLed { led: 3, rgbw: 0a000000, counter: 4 }");
+    assert_eq!(frame.report_id, Some(1));
+
+    assert_eq!(tx(&command_frame(&SimpleCommand::ping(9))).to_string(), "Ping { counter: 9 }");
+    assert_eq!(tx(&command_frame(&SimpleCommand::prepare_update())).name, "PrepareUpdate");
+    assert_eq!(tx(&command_frame(&SimpleCommand::new(HidOutCommand::Reset))).name, "Reset");
+
+    let config = UpdateConfig::new().activate_serial_console(true).activate_filesystem(false);
+    assert_eq!(
+        tx(&command_frame(&config)).to_string(),
+        "UpdateConfig { serial_console: on, filesystem: off, counter: 0 }"
+    );
+    let config = tx(&command_frame(&UpdateConfig::new()));
+    assert_eq!(config.get("filesystem"), Some("unchanged"));
+}
+
+#[test]
+fn test_transfer_chunks() {
+    let start = tx(&chunk_frame(FileStart::new(2, 0, 3, "main.py", 70000).unwrap().inner()));
+    assert_eq!(start.to_string(), "FileStart { file: 2, packages: 3, filename: main.py, size: 70000 }");
+    let start = tx(&chunk_frame(FileStart::new(2, 0, 3, "main.py", 100).unwrap().inner()));
+    assert_eq!(start.get("size"), Some("100"));
+
+    let chunk = tx(&chunk_frame(FileChunk::new(2, 1, 3, b"print(1)".to_vec()).inner()));
+    assert_eq!(chunk.name, "FileChunk");
+    assert_eq!((chunk.get("file"), chunk.get("package"), chunk.get("packages")), (Some("2"), Some("1"), Some("3")));
+
+    assert_eq!(tx(&chunk_frame(FileEnd::new(2).inner())).to_string(), "FileEnd { file: 2 }");
+    assert_eq!(tx(&chunk_frame(Completed::new().inner())).to_string(), "Complete");
+    assert_eq!(
+        tx(&chunk_frame(FileDelete::new(5, "old.py").unwrap().inner())).to_string(),
+        "FileDelete { file: 5, filename: old.py }"
+    );
+}
+
+#[test]
+fn test_device_messages() {
+    assert_eq!(
+        rx(&[1, 0x01, 3, 1, 0, 1, 0, 0]).to_string(),
+        "Status { button: 3, triggered: true, longpressed: false, pressed: true, released: false }"
+    );
+    assert_eq!(rx(&[1, 0x02, 0, 0, 0, 0, 0, 0]).to_string(), "StatusRequest");
+    assert_eq!(
+        rx(&[1, 0x99, 1, 2, 3, 0x05, 0, 0]).to_string(),
+        "VersionInfo { version: 1.2.3, hardware: Ten Button USB }"
+    );
+    assert_eq!(
+        rx(&[2, b'A', b'K', 2, 0, 7, 0, 2]).to_string(),
+        "ChunkAck { file: 2, package: 7, chunk: FileChunk }"
+    );
+
+    let mut error = vec![2, b'E', b'R', 9];
+    error.extend(b"bad chunk\0\0");
+    assert_eq!(rx(&error).to_string(), "UpdateError { message: bad chunk }");
+
+    let mut log = vec![1, b'L', b'E'];
+    log.extend(b"boot failed\0");
+    assert_eq!(rx(&log).to_string(), "Log { level: error, message: boot failed }");
+}
+
+#[test]
+fn test_direction_matters() {
+    // Report 1 command 0x01 is SetLed towards the device and Status from it
+    let frame = [1, 0x01, 3, 0x0a, 0, 0, 0, 0];
+    assert_eq!(tx(&frame).name, "SetLed");
+    assert_eq!(rx(&frame).name, "Status");
+}
+
+#[test]
+fn test_malformed_frames() {
+    assert_eq!(tx(&[]).name, "Empty");
+
+    let unknown = tx(&[1, 0x42, 1, 2]);
+    assert_eq!(unknown.name, "Unknown");
+    assert_eq!(unknown.get("data"), Some("420102"));
+    assert!(unknown.problem.unwrap().contains("0x42"));
+
+    let short = tx(&[1, 0xF0, 0, 0]);
+    assert_eq!(short.name, "Ping");
+    assert!(short.problem.is_some());
+
+    assert!(tx(&[2, 9, 0, 0, 0, 0, 0, 0, 0]).problem.unwrap().contains("chunk type 9"));
+    assert!(tx(&[2, 1, 0]).problem.is_some());
+    assert!(rx(&[2, b'A', b'K', 1]).to_string().ends_with("[truncated: expected 7 bytes, got 3]"));
+    // Lengths count from the start of the message, like those of the update messages
+    assert_eq!(rx(&[1, 0x01, 3]).problem.as_deref(), Some("truncated: expected 7 bytes, got 2"));
+    assert_eq!(rx(&[1, 0x77]).get("data"), Some("77"));
+    assert_eq!(tx(&[7, 1, 2]).name, "Unknown");
+}
+
+#[test]
+fn test_parse_hex() {
+    let expected = vec![0x01, 0x01, 0x03, 0x0a, 0x00];
+    assert_eq!(parse_hex("01 01 03 0a 00").unwrap(), expected);
+    assert_eq!(parse_hex("0101030a00").unwrap(), expected);
+    assert_eq!(parse_hex("[01, 01, 03, 0a, 00]").unwrap(), expected);
+    assert_eq!(parse_hex("0x01 0x01 0x3 0x0A 0").unwrap(), expected);
+
+    assert!(parse_hex("").is_err());
+    assert!(parse_hex("012").is_err());
+    assert!(parse_hex("zz").is_err());
+}
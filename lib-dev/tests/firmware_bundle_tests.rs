@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mutenix_hid::device_update::UpdateError;
+use mutenix_hid::firmware_bundle::*;
+use mutenix_hid::{
+    ChunkType, ConnectionState, DeviceDescriptor, HardwareType, HidDevice, HidInputMessage,
+    LoopbackTransport, UpdateOptions, VersionInfo, HID_COMMAND_PREPARE_UPDATE,
+    HID_REPORT_ID_TRANSFER,
+};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAIN: &[u8] = b"print('hello from the macropad')";
+
+fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn tar_bundle(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *content).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+fn manifest(main_digest: &str) -> String {
+    format!(
+        r#"{{
+            "hardware_types": ["ten-button-usb", "five-button-usb"],
+            "min_version": "1.0.0",
+            "max_version": "1.9.0",
+            "files": [
+                {{ "action": "write", "path": "main.py", "sha256": "{}" }},
+                {{ "action": "delete", "path": "old.py" }}
+            ]
+        }}"#,
+        main_digest
+    )
+}
+
+fn version_info(major: u8, minor: u8, patch: u8, hardware_type: HardwareType) -> VersionInfo {
+    VersionInfo::from_buffer(&[major, minor, patch, hardware_type as u8, 0, 0]).unwrap()
+}
+
+#[test]
+fn test_load_bundle() {
+    let manifest = manifest(&sha256(MAIN));
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("./main.py", MAIN)]);
+
+    let bundle = FirmwareBundle::from_bytes(&data).unwrap();
+    assert_eq!(bundle.manifest().hardware_types, vec![HardwareType::TenButtonUsb, HardwareType::FiveButtonUsb]);
+    assert_eq!(bundle.content("main.py"), Some(MAIN));
+
+    let files = bundle.transfer_files().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!((files[0].id, files[0].filename.as_str(), files[0].size), (0, "main.py", MAIN.len()));
+    assert_eq!(files[1].filename, "old.py");
+    assert_eq!(files[1].get_next_chunk().unwrap().type_, ChunkType::FileDelete);
+}
+
+#[test]
+fn test_load_gzip_bundle() {
+    let manifest = manifest(&sha256(MAIN));
+    let tar = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&tar).unwrap();
+
+    let bundle = FirmwareBundle::from_bytes(&encoder.finish().unwrap()).unwrap();
+    assert_eq!(bundle.content("main.py"), Some(MAIN));
+}
+
+#[test]
+fn test_checksum_mismatch() {
+    let manifest = manifest(&sha256(b"something else"));
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+
+    let result = FirmwareBundle::from_bytes(&data);
+    assert!(matches!(result, Err(BundleError::ChecksumMismatch { file, .. }) if file == "main.py"));
+}
+
+#[test]
+fn test_missing_entries() {
+    let data = tar_bundle(&[("main.py", MAIN)]);
+    assert!(matches!(FirmwareBundle::from_bytes(&data), Err(BundleError::MissingManifest)));
+
+    let manifest = manifest(&sha256(MAIN));
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes())]);
+    assert!(matches!(FirmwareBundle::from_bytes(&data), Err(BundleError::MissingFile(file)) if file == "main.py"));
+
+    let data = tar_bundle(&[("manifest.json", b"{\"files\": 3}")]);
+    assert!(matches!(FirmwareBundle::from_bytes(&data), Err(BundleError::InvalidManifest(_))));
+}
+
+#[test]
+fn test_duplicate_manifest_entries() {
+    let main_digest = sha256(MAIN);
+    let manifest = format!(
+        r#"{{ "files": [
+            {{ "action": "write", "path": "main.py", "sha256": "{0}" }},
+            {{ "action": "write", "path": "main.py", "sha256": "{0}" }}
+        ] }}"#,
+        main_digest
+    );
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+    assert!(matches!(FirmwareBundle::from_bytes(&data), Err(BundleError::DuplicateFile(file)) if file == "main.py"));
+
+    // Writing and deleting the same file is just as ambiguous
+    let manifest = format!(
+        r#"{{ "files": [
+            {{ "action": "write", "path": "main.py", "sha256": "{}" }},
+            {{ "action": "delete", "path": "main.py" }}
+        ] }}"#,
+        main_digest
+    );
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+    assert!(matches!(FirmwareBundle::from_bytes(&data), Err(BundleError::DuplicateFile(file)) if file == "main.py"));
+}
+
+#[test]
+fn test_check_device() {
+    let manifest = manifest(&sha256(MAIN));
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+    let bundle = FirmwareBundle::from_bytes(&data).unwrap();
+
+    assert!(bundle.check_device(&version_info(1, 0, 0, HardwareType::TenButtonUsb)).is_ok());
+    assert!(bundle.check_device(&version_info(1, 9, 0, HardwareType::FiveButtonUsb)).is_ok());
+
+    assert!(matches!(
+        bundle.check_device(&version_info(1, 2, 0, HardwareType::TenButtonBt)),
+        Err(BundleError::UnsupportedHardware(HardwareType::TenButtonBt))
+    ));
+    assert!(matches!(
+        bundle.check_device(&version_info(0, 9, 9, HardwareType::TenButtonUsb)),
+        Err(BundleError::UnsupportedVersion { .. })
+    ));
+    assert!(matches!(
+        bundle.check_device(&version_info(1, 9, 1, HardwareType::TenButtonUsb)),
+        Err(BundleError::UnsupportedVersion { .. })
+    ));
+}
+
+#[test]
+fn test_unrestricted_manifest() {
+    let manifest: BundleManifest = serde_json::from_str(r#"{"files": []}"#).unwrap();
+    assert!(manifest.check_device(&version_info(0, 0, 1, HardwareType::Unknown)).is_ok());
+
+    let manifest: BundleManifest = serde_json::from_str(r#"{"min_version": "1.x", "files": []}"#).unwrap();
+    assert!(matches!(
+        manifest.check_device(&version_info(1, 0, 0, HardwareType::TenButtonUsb)),
+        Err(BundleError::InvalidManifest(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_device_rejects_incompatible_bundle() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(
+        DeviceDescriptor::new(0x1d50, 0x6189)
+            .with_path("loopback/bundle")
+            .with_product("Mutenix Macropad"),
+    );
+    let device = Arc::new(HidDevice::with_transport(Vec::new(), Arc::new(transport.clone())));
+    let process_device = device.clone();
+    tokio::spawn(async move {
+        let _ = process_device.process().await;
+    });
+
+    let manifest = manifest(&sha256(MAIN));
+    let data = tar_bundle(&[("manifest.json", manifest.as_bytes()), ("main.py", MAIN)]);
+    let bundle = FirmwareBundle::from_bytes(&data).unwrap();
+    let options = UpdateOptions::new();
+
+    // Wait until the device is connected, it has not reported its version yet
+    for _ in 0..100 {
+        if device.state().await.connection_status == ConnectionState::Connected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let result = device.update_bundle(&bundle, &options).await;
+    assert!(matches!(result, Err(UpdateError::Bundle(BundleError::UnknownDeviceVersion))));
+
+    pad.inject(&[1, 0x99, 2, 0, 0, HardwareType::TenButtonUsb as u8, 0, 0]);
+    for _ in 0..100 {
+        if device.state().await.firmware_version.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    pad.take_written();
+
+    let result = device.update_bundle(&bundle, &options).await;
+    assert!(matches!(result, Err(UpdateError::Bundle(BundleError::UnsupportedVersion { .. }))));
+
+    // Nothing was sent and the device stays connected
+    assert!(pad.take_written().iter().all(|report| report[0] != HID_REPORT_ID_TRANSFER
+        && report[1] != HID_COMMAND_PREPARE_UPDATE));
+    assert_eq!(device.state().await.connection_status, ConnectionState::Connected);
+
+    device.stop().await;
+}
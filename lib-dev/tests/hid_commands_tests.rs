@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
+use mutenix_hid::device_messages::{ChunkAck, LogMessage};
 use mutenix_hid::hid_commands::*;
 
     #[test]
@@ -9,10 +10,10 @@ use mutenix_hid::hid_commands::*;
         let status = Status::from_buffer(&buffer).unwrap();
         
         assert_eq!(status.button(), 1);
-        assert_eq!(status.triggered(), true);
-        assert_eq!(status.longpressed(), false);
-        assert_eq!(status.pressed(), false);
-        assert_eq!(status.released(), true);
+        assert!(status.triggered());
+        assert!(!status.longpressed());
+        assert!(!status.pressed());
+        assert!(status.released());
     }
 
     #[test]
@@ -48,7 +49,9 @@ use mutenix_hid::hid_commands::*;
         let buffer = [0, HidInCommand::VersionInfo as u8, 1, 2, 3, 4, 5, 6];
         let message = parse_input_message(&buffer).unwrap();
         
-        let version_info = message.downcast_ref::<VersionInfo>().unwrap();
+        let HidInput::VersionInfo(version_info) = message else {
+            panic!("Expected VersionInfo, got {:?}", message);
+        };
         assert_eq!(version_info.version(), "1.2.3");
         assert_eq!(version_info.hardware_type(), HardwareType::from(4));
     }
@@ -58,12 +61,14 @@ use mutenix_hid::hid_commands::*;
         let buffer = [0, HidInCommand::Status as u8, 1, 0, 0, 1, 0, 0];
         let message = parse_input_message(&buffer).unwrap();
         
-        let status = message.downcast_ref::<Status>().unwrap();
+        let HidInput::Status(status) = message else {
+            panic!("Expected Status, got {:?}", message);
+        };
         assert_eq!(status.button(), 1);
-        assert_eq!(status.triggered(), false);
-        assert_eq!(status.longpressed(), false);
-        assert_eq!(status.pressed(), true);
-        assert_eq!(status.released(), false);
+        assert!(!status.triggered());
+        assert!(!status.longpressed());
+        assert!(status.pressed());
+        assert!(!status.released());
     }
 
     #[test]
@@ -71,7 +76,7 @@ use mutenix_hid::hid_commands::*;
         let buffer = [0, HidInCommand::StatusRequest as u8, 0, 0, 0, 0, 0, 0];
         let message = parse_input_message(&buffer).unwrap();
         
-        assert!(message.downcast_ref::<StatusRequest>().is_some());
+        assert_eq!(message, HidInput::StatusRequest(StatusRequest));
     }
 
     #[test]
@@ -169,3 +174,72 @@ use mutenix_hid::hid_commands::*;
             "UpdateConfig { debug: 2, filesystem: 2 }"
         );
     }
+
+    #[test]
+    fn test_from_buffer_chunk_ack() {
+        let mut buffer = vec![1];
+        buffer.extend(b"AK");
+        buffer.extend(&(1u16).to_le_bytes());
+        buffer.extend(&(2u16).to_le_bytes());
+        buffer.push(3);
+        let message = parse_input_message(&buffer).unwrap();
+
+        let HidInput::ChunkAck(ack) = message else {
+            panic!("Expected ChunkAck, got {:?}", message);
+        };
+        assert_eq!((ack.id, ack.package, ack.type_), (1, 2, 3));
+    }
+
+    #[test]
+    fn test_from_buffer_log() {
+        let mut buffer = vec![1];
+        buffer.extend(b"LEsomething failed\0\0\0");
+        let message = parse_input_message(&buffer).unwrap();
+
+        let HidInput::Log(log) = message else {
+            panic!("Expected Log, got {:?}", message);
+        };
+        assert_eq!(log.message, "something failed");
+    }
+
+    #[test]
+    fn test_from_buffer_too_short() {
+        match parse_input_message(&[1]) {
+            Err(HidMessageError::InvalidLength { expected: 2, actual: 1 }) => {}
+            other => panic!("Expected InvalidLength, got {:?}", other),
+        }
+        match parse_input_message(&[1, HidInCommand::Status as u8, 1, 0]) {
+            Err(HidMessageError::InvalidLength { expected: 6, actual: 2 }) => {}
+            other => panic!("Expected InvalidLength, got {:?}", other),
+        }
+        match parse_input_message(b"\x01AK\x01\x00") {
+            Err(HidMessageError::InvalidLength { expected: 7, actual: 4 }) => {}
+            other => panic!("Expected InvalidLength, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_update_message_identifier_mismatch() {
+        assert!(matches!(ChunkAck::from_buffer(b"ER\x00\x00\x00\x00\x00"), Err(HidMessageError::InvalidData)));
+        assert!(matches!(LogMessage::from_buffer(b"AK"), Err(HidMessageError::InvalidData)));
+    }
+
+    #[test]
+    fn test_set_led_rgbw() {
+        let led = SetLed::new(4, LedColor::rgbw(0xFF, 0x88, 0x00, 0x10));
+        let buffer = led.to_buffer();
+
+        assert_eq!(&buffer[..7], &[HidOutCommand::SetLed as u8, 4, 0xFF, 0x88, 0x00, 0x10, 0]);
+    }
+
+    #[test]
+    fn test_set_led_brightness() {
+        let led = SetLed::new(1, LedColor::rgbw(200, 100, 0, 0)).with_brightness(0.5);
+        assert_eq!(&led.to_buffer()[2..6], &[100, 50, 0, 0]);
+
+        let led = SetLed::new(1, LedColor::rgbw(200, 100, 0, 0)).with_brightness(2.0);
+        assert_eq!(&led.to_buffer()[2..6], &[255, 200, 0, 0]);
+
+        let led = SetLed::new(1, LedColor::Red).with_brightness(0.0);
+        assert_eq!(&led.to_buffer()[2..6], &[0, 0, 0, 0]);
+    }
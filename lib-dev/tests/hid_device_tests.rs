@@ -0,0 +1,918 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn test_device_info_matches() {
+    let descriptor = mutenix_descriptor("ABC");
+
+    let by_ids = DeviceInfo::new(0x1d50, 0x6189);
+    assert!(by_ids.matches(&descriptor));
+
+    let wrong_ids = DeviceInfo::new(0x1d50, 0x0001);
+    assert!(!wrong_ids.matches(&descriptor));
+
+    let by_serial = DeviceInfo::serial("ABC");
+    assert!(by_serial.matches(&descriptor));
+
+    let wrong_serial = DeviceInfo::new(0x1d50, 0x6189).with_serial_number("XYZ");
+    assert!(!wrong_serial.matches(&descriptor));
+
+    let nothing = DeviceInfo::default();
+    assert!(!nothing.matches(&descriptor));
+}
+
+#[test]
+fn test_device_info_criteria() {
+    let mut descriptor = mutenix_descriptor("ABC");
+    descriptor.usage_page = 0xFF00;
+    descriptor.interface_number = 2;
+
+    assert!(DeviceInfo::default().with_product_pattern("mutenix*").matches(&descriptor));
+    assert!(DeviceInfo::default().with_product_pattern("*MACRO?AD").matches(&descriptor));
+    assert!(!DeviceInfo::default().with_product_pattern("Mutenix Dev*").matches(&descriptor));
+    assert!(!DeviceInfo::default()
+        .with_product_pattern("*")
+        .matches(&DeviceDescriptor::new(0x1d50, 0x6189)));
+
+    let by_interface = DeviceInfo::new(0x1d50, 0).with_usage_page(0xFF00).with_interface_number(2);
+    assert!(by_interface.matches(&descriptor));
+    assert!(!by_interface.clone().with_interface_number(0).matches(&descriptor));
+    assert!(!by_interface.with_usage_page(0x0001).matches(&descriptor));
+
+    // The hardware type is only checked once the device reported it
+    let ten_button = DeviceInfo::serial("ABC").with_hardware_type(HardwareType::TenButtonUsb);
+    assert!(ten_button.matches(&descriptor));
+    assert!(ten_button.accepts_hardware(HardwareType::TenButtonUsb));
+    assert!(!ten_button.accepts_hardware(HardwareType::FiveButtonUsb));
+    assert!(DeviceInfo::serial("ABC").accepts_hardware(HardwareType::FiveButtonUsb));
+}
+
+#[tokio::test]
+async fn test_connects_and_reports_hardware_state() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let state = device.state().await;
+    assert_eq!(state.serial_number.as_deref(), Some("ABC"));
+    assert_eq!(state.manufacturer.as_deref(), Some("Mutenix"));
+    assert_eq!(state.product.as_deref(), Some("Mutenix Macropad"));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_status_is_forwarded_to_callbacks() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    device
+        .register_callback(move |message| received_clone.lock().unwrap().push(message))
+        .await;
+
+    pad.inject(&[1, 0x01, 3, 0, 0, 1, 0, 0]);
+    pad.inject(&[1, 0x02, 0, 0, 0, 0, 0, 0]);
+
+    assert!(eventually(|| received.lock().unwrap().len() == 2).await);
+    let received = received.lock().unwrap().clone();
+    match &received[0] {
+        DeviceMessage::Status(status) => {
+            assert_eq!(status.button(), 3);
+            assert!(status.pressed());
+        }
+        other => panic!("Expected status, got {:?}", other),
+    }
+    assert!(matches!(received[1], DeviceMessage::StatusRequest(_)));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_send_command_writes_report() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    let size = device.send_command(SetLed::new(2, LedColor::Red)).await.unwrap();
+    assert_eq!(size, 9);
+
+    let written: Vec<Vec<u8>> = pad
+        .take_written()
+        .into_iter()
+        .filter(|report| report[1] == HidOutCommand::SetLed as u8)
+        .collect();
+    assert_eq!(written, vec![vec![1, 0x01, 2, 0x0A, 0, 0, 0, 0, 0]]);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_selects_configured_device() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("FIRST"));
+    transport.add_device(mutenix_descriptor("SECOND"));
+
+    let device = spawn_device(&transport, vec![DeviceInfo::serial("SECOND")]);
+
+    assert!(wait_for_state(&device, |s| s.serial_number.as_deref() == Some("SECOND")).await);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_prefers_rule_with_higher_priority() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("FIRST"));
+    transport.add_device(mutenix_descriptor("SECOND"));
+
+    let rules = vec![
+        DeviceInfo::new(0x1d50, 0x6189),
+        DeviceInfo::serial("SECOND").with_product_pattern("mutenix*").with_priority(10),
+    ];
+    let device = spawn_device(&transport, rules);
+
+    assert!(wait_for_state(&device, |s| s.serial_number.as_deref() == Some("SECOND")).await);
+
+    device.stop().await;
+}
+
+/// Answer pings with the version of the given hardware type
+fn spawn_version_responder(pad: &LoopbackDevice, hardware_type: HardwareType, stop: Arc<AtomicBool>) {
+    spawn_responder(pad, stop, move |pad, report| {
+        if report[1] == HidOutCommand::Ping as u8 {
+            pad.inject(&[1, 0x99, 1, 0, 0, hardware_type as u8, 0, 0]);
+        }
+    });
+}
+
+#[tokio::test]
+async fn test_rejects_device_with_other_hardware_type() {
+    let transport = LoopbackTransport::new();
+    let dev_board = transport.add_device(mutenix_descriptor("DEVBOARD"));
+    let pad = transport.add_device(mutenix_descriptor("PAD"));
+    let stop = Arc::new(AtomicBool::new(false));
+    spawn_version_responder(&dev_board, HardwareType::FiveButtonUsb, stop.clone());
+    spawn_version_responder(&pad, HardwareType::TenButtonUsb, stop.clone());
+
+    let rules = vec![DeviceInfo::new(0x1d50, 0x6189).with_hardware_type(HardwareType::TenButtonUsb)];
+    let device = spawn_device(&transport, rules);
+
+    // The dev board is enumerated first, opened, rejected and not opened again
+    assert!(
+        wait_for_state(&device, |s| {
+            s.serial_number.as_deref() == Some("PAD") && s.hardware_type == Some(HardwareType::TenButtonUsb)
+        })
+        .await
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let state = device.state().await;
+    stop.store(true, Ordering::SeqCst);
+
+    assert_eq!(state.serial_number.as_deref(), Some("PAD"));
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_rejected_device_is_checked_again_after_replug() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("PAD"));
+    let stop = Arc::new(AtomicBool::new(false));
+    let hardware_type = Arc::new(AtomicU8::new(HardwareType::FiveButtonUsb as u8));
+    let reported = hardware_type.clone();
+    spawn_responder(&pad, stop.clone(), move |pad, report| {
+        if report[1] == HidOutCommand::Ping as u8 {
+            pad.inject(&[1, 0x99, 1, 0, 0, reported.load(Ordering::SeqCst), 0, 0]);
+        }
+    });
+
+    let rules = vec![DeviceInfo::new(0x1d50, 0x6189).with_hardware_type(HardwareType::TenButtonUsb)];
+    let device = spawn_device(&transport, rules);
+    assert!(wait_for_state(&device, |s| s.hardware_type == Some(HardwareType::FiveButtonUsb)).await);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Disconnected).await);
+
+    // Reflashed while unplugged: the device is opened again once it is back
+    pad.set_connected(false);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    hardware_type.store(HardwareType::TenButtonUsb as u8, Ordering::SeqCst);
+    pad.set_connected(true);
+
+    let reconnected = wait_for_state(&device, |s| {
+        s.connection_status == ConnectionState::Connected && s.hardware_type == Some(HardwareType::TenButtonUsb)
+    })
+    .await;
+    stop.store(true, Ordering::SeqCst);
+    assert!(reconnected);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_reconnects_after_unplug() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    pad.set_connected(false);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Disconnected).await);
+
+    pad.set_connected(true);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_version_info_is_requested_and_stored() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    device
+        .register_callback(move |message| received_clone.lock().unwrap().push(message))
+        .await;
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    let requested = eventually(|| {
+        pad.take_written()
+            .iter()
+            .any(|report| report[1] == HidOutCommand::Ping as u8)
+    })
+    .await;
+    assert!(requested);
+
+    pad.inject(&[1, 0x99, 1, 2, 3, 0x05, 0, 0]);
+
+    assert!(wait_for_state(&device, |s| s.firmware_version.as_deref() == Some("1.2.3")).await);
+    assert_eq!(device.state().await.hardware_type, Some(HardwareType::TenButtonUsb));
+
+    assert!(eventually(|| received.lock().unwrap().len() == 1).await);
+    let received = received.lock().unwrap().clone();
+    match &received[0] {
+        DeviceMessage::VersionInfo(version_info) => assert_eq!(version_info.version(), "1.2.3"),
+        other => panic!("Expected version info, got {:?}", other),
+    }
+
+    device.stop().await;
+}
+
+fn set_led_reports(pad: &LoopbackDevice) -> Vec<Vec<u8>> {
+    pad.take_written()
+        .into_iter()
+        .filter(|report| report[1] == HidOutCommand::SetLed as u8)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_set_led_writes_changes_only() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap(), 9);
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap(), 0);
+    assert_eq!(device.set_led(SetLed::new(2, LedColor::Red)).await.unwrap(), 9);
+    assert_eq!(set_led_reports(&pad).len(), 2);
+
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Green)).await.unwrap(), 9);
+    assert_eq!(set_led_reports(&pad).len(), 1);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_set_led_back_before_write_wins() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap();
+    pad.take_written();
+
+    // Green gets queued, then red is set again before the write loop got to green
+    let green = {
+        let device = device.clone();
+        tokio::spawn(async move { device.set_led(SetLed::new(1, LedColor::Green)).await })
+    };
+    tokio::task::yield_now().await;
+    device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), green).await.unwrap().unwrap().unwrap();
+
+    let leds = set_led_reports(&pad);
+    assert_eq!(&leds.last().expect("LED 1 should be written")[2..7], &[1, 0x0A, 0, 0, 0]);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_set_led_during_replay_ends_with_newest_color() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap();
+
+    pad.inject(&[1, 0x02, 0, 0, 0, 0, 0, 0]);
+    device.set_led(SetLed::new(1, LedColor::Green)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let leds = set_led_reports(&pad);
+    assert_eq!(&leds.last().unwrap()[2..7], &[1, 0, 0x0A, 0, 0]);
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Green)).await.unwrap(), 0);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_writes_do_not_wait_for_reads() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    // Nothing to read, so a reader holding the connection would delay every write by its timeout
+    let start = std::time::Instant::now();
+    for id in 1..=20 {
+        device.set_led(SetLed::new(id, LedColor::Blue)).await.unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(500), "took {:?}", start.elapsed());
+    assert_eq!(set_led_reports(&pad).len(), 20);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_status_request_replays_state() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    let requests = Arc::new(Mutex::new(0));
+    let requests_clone = requests.clone();
+    device
+        .register_callback(move |message| {
+            if matches!(message, DeviceMessage::StatusRequest(_)) {
+                *requests_clone.lock().unwrap() += 1;
+            }
+        })
+        .await;
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap();
+    device.set_led(SetLed::new(2, LedColor::rgbw(1, 2, 3, 4))).await.unwrap();
+    assert_eq!(set_led_reports(&pad).len(), 2);
+
+    // The host answers without waiting for the next LED update
+    pad.inject(&[1, 0x02, 0, 0, 0, 0, 0, 0]);
+    assert!(eventually(|| *requests.lock().unwrap() == 1).await);
+
+    let written = pad.take_written();
+    let leds: Vec<&Vec<u8>> = written
+        .iter()
+        .filter(|report| report[1] == HidOutCommand::SetLed as u8)
+        .collect();
+    assert_eq!(leds.len(), 2);
+    assert_eq!(&leds[0][2..7], &[1, 0x0A, 0, 0, 0]);
+    assert_eq!(&leds[1][2..7], &[2, 1, 2, 3, 4]);
+    assert!(written.iter().any(|report| report[1] == HidOutCommand::Ping as u8));
+
+    // The replayed LEDs count as written
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap(), 0);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_set_led_resyncs_after_reconnect() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap();
+
+    pad.set_connected(false);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Disconnected).await);
+    pad.set_connected(true);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    pad.take_written();
+
+    assert_eq!(device.set_led(SetLed::new(1, LedColor::Red)).await.unwrap(), 9);
+    assert_eq!(set_led_reports(&pad).len(), 1);
+
+    device.stop().await;
+}
+
+fn firmware_file(dir: &tempfile::TempDir, name: &str, size: usize) -> std::path::PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, vec![b'x'; size]).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_update_with_default_options_resets_device() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let main = firmware_file(&dir, "main.py", 120);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let written = spawn_update_responder(&pad, stop.clone());
+    device.update(vec![&main], &UpdateOptions::default()).await.unwrap();
+
+    let reset = || {
+        written
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|report| report[0] == HID_REPORT_ID_COMMUNICATION && report[1] == HID_COMMAND_RESET)
+    };
+    assert!(eventually(reset).await);
+    stop.store(true, Ordering::SeqCst);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_update_reports_progress_and_reconnects() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let main = firmware_file(&dir, "main.py", 120);
+    let config = firmware_file(&dir, "config.py", 10);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let written = spawn_update_responder(&pad, stop.clone());
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let progress_clone = progress.clone();
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let threads_clone = threads.clone();
+    let options = UpdateOptions::new().with_progress(move |p| {
+        progress_clone.lock().unwrap().push(p);
+        threads_clone.lock().unwrap().push(std::thread::current().id());
+    });
+    device.update(vec![&main, &config], &options).await.unwrap();
+
+    // Progress is reported on the task that awaits the update
+    let runtime_thread = std::thread::current().id();
+    assert!(threads.lock().unwrap().iter().all(|id| *id == runtime_thread));
+    let progress = progress.lock().unwrap().clone();
+    let last = progress.last().unwrap();
+    assert_eq!((last.file.as_str(), last.file_index, last.file_count), ("config.py", 1, 2));
+    assert_eq!(last.chunk, last.total_chunks);
+    assert_eq!(last.bytes, last.total_bytes);
+    assert!(progress.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+    assert!(progress.iter().skip_while(|p| p.bytes == 0).all(|p| p.eta.is_some()));
+
+    let commands = || -> Vec<u8> {
+        written
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|report| report[0] == HID_REPORT_ID_COMMUNICATION)
+            .map(|report| report[1])
+            .collect()
+    };
+    assert!(eventually(|| commands().contains(&HID_COMMAND_RESET)).await);
+    stop.store(true, Ordering::SeqCst);
+    let commands = commands();
+    let prepare = commands.iter().position(|c| *c == HID_COMMAND_PREPARE_UPDATE).unwrap();
+    let reset = commands.iter().position(|c| *c == HID_COMMAND_RESET).unwrap();
+    assert!(prepare < reset);
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_update_runs_off_the_runtime() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let main = firmware_file(&dir, "main.py", 120);
+
+    // Nobody acknowledges; the update would give up after about five seconds.
+    // A task on the single runtime thread cancels it long before.
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        canceller.cancel();
+    });
+    let options = UpdateOptions::new()
+        .with_cancel_token(cancel)
+        .with_retransmit_timeout(Duration::from_millis(100))
+        .with_max_retries(50);
+    let result = device.update(vec![&main], &options).await;
+    assert!(matches!(result, Err(device_update::UpdateError::Cancelled)), "got {:?}", result);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_update_pauses_commands_and_can_be_cancelled() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let main = firmware_file(&dir, "main.py", 120);
+
+    // Nobody acknowledges, the update runs until it is cancelled
+    let cancel = CancellationToken::new();
+    let options = UpdateOptions::new().with_cancel_token(cancel.clone());
+    let update_device = device.clone();
+    let update = tokio::spawn(async move { update_device.update(vec![&main], &options).await });
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Updating).await);
+    let result = device.send_command(SimpleCommand::ping(1)).await;
+    assert!(matches!(result, Err(HidError::UpdateInProgress)));
+
+    cancel.cancel();
+    let result = update.await.unwrap();
+    assert!(matches!(result, Err(device_update::UpdateError::Cancelled)));
+
+    let written = pad.take_written();
+    assert!(written
+        .iter()
+        .any(|report| report[0] == HID_REPORT_ID_COMMUNICATION && report[1] == HID_COMMAND_RESET));
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    device.stop().await;
+}
+
+/// Records device log lines that reach the `log` backend
+struct DeviceLogCapture(Mutex<Vec<(log::Level, String)>>);
+
+impl log::Log for DeviceLogCapture {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == DEVICE_LOG_TARGET
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static DEVICE_LOGS: DeviceLogCapture = DeviceLogCapture(Mutex::new(Vec::new()));
+
+#[tokio::test]
+async fn test_device_log_frames_are_forwarded() {
+    log::set_logger(&DEVICE_LOGS).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    device
+        .register_callback(move |message| {
+            if let DeviceMessage::Log(log_message) = message {
+                received_clone.lock().unwrap().push(log_message);
+            }
+        })
+        .await;
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    pad.inject(b"\x01LDbooted\x00\x00");
+    pad.inject(b"\x01LEout of memory");
+    assert!(eventually(|| received.lock().unwrap().len() == 2).await);
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!((received[0].level, received[0].message.as_str()), (LogLevel::Debug, "booted"));
+    assert_eq!((received[1].level, received[1].message.as_str()), (LogLevel::Error, "out of memory"));
+
+    let logs = DEVICE_LOGS.0.lock().unwrap().clone();
+    assert_eq!(
+        logs,
+        vec![
+            (log::Level::Debug, "[ABC] booted".to_string()),
+            (log::Level::Error, "[ABC] out of memory".to_string()),
+        ]
+    );
+
+    device.stop().await;
+}
+
+/// Simulate the firmware restarting when it receives the given command, e.g.
+/// UpdateConfig to apply new settings; it answers every ping afterwards with
+/// its version. Returns the commands received.
+fn spawn_restart_responder(pad: &LoopbackDevice, command: HidOutCommand, stop: Arc<AtomicBool>) -> Arc<Mutex<Vec<Vec<u8>>>> {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    spawn_responder(pad, stop, move |pad, report| {
+        if report[1] == command as u8 {
+            received_clone.lock().unwrap().push(report);
+            pad.set_connected(false);
+            std::thread::sleep(Duration::from_millis(50));
+            pad.set_connected(true);
+        } else if report[1] == HidOutCommand::Ping as u8 && !received_clone.lock().unwrap().is_empty() {
+            pad.inject(&[1, 0x99, 1, 2, 3, HardwareType::TenButtonUsb as u8, 0, 0]);
+        }
+    });
+    received
+}
+
+#[tokio::test]
+async fn test_configure_waits_for_reenumeration() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let configs = spawn_restart_responder(&pad, HidOutCommand::UpdateConfig, stop.clone());
+
+    let settings = DeviceSettings::new().with_serial_console(true).with_filesystem(false);
+    let state = device.configure(settings, Duration::from_secs(5)).await.unwrap();
+    stop.store(true, Ordering::SeqCst);
+
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+    assert_eq!(state.firmware_version.as_deref(), Some("1.2.3"));
+    assert_eq!(state.serial_number.as_deref(), Some("ABC"));
+
+    let configs = configs.lock().unwrap().clone();
+    assert_eq!(configs.len(), 1);
+    assert_eq!(&configs[0][..4], &[1, HidOutCommand::UpdateConfig as u8, 2, 1]);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_configure_times_out_without_restart() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let settings = DeviceSettings::new().with_filesystem(true);
+    let result = device.configure(settings, Duration::from_millis(200)).await;
+    assert!(matches!(result, Err(HidError::ReconnectTimeout(_))));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_configure_requires_connection() {
+    let device = HidDevice::with_transport(Vec::new(), Arc::new(LoopbackTransport::new()));
+    let result = device.configure(DeviceSettings::new().with_filesystem(true), Duration::from_millis(100)).await;
+    assert!(matches!(result, Err(HidError::NotConnected)));
+}
+
+#[tokio::test]
+async fn test_configure_rejects_empty_settings() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    pad.take_written();
+
+    let result = device.configure(DeviceSettings::new(), Duration::from_secs(5)).await;
+    assert!(matches!(result, Err(HidError::NoSettings)));
+    assert!(!pad.take_written().iter().any(|report| report[1] == HidOutCommand::UpdateConfig as u8));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_reset_and_wait_returns_after_reenumeration() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    let reconnects = device.state().await.link.reconnects;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let resets = spawn_restart_responder(&pad, HidOutCommand::Reset, stop.clone());
+
+    let state = device.reset_and_wait(Duration::from_secs(5)).await.unwrap();
+    stop.store(true, Ordering::SeqCst);
+
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+    assert_eq!(state.serial_number.as_deref(), Some("ABC"));
+    assert_eq!(state.firmware_version.as_deref(), Some("1.2.3"));
+    assert_eq!(state.link.reconnects, reconnects + 1);
+    assert_eq!(resets.lock().unwrap().len(), 1);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_prepare_update_and_wait_returns_after_reenumeration() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let prepares = spawn_restart_responder(&pad, HidOutCommand::PrepareUpdate, stop.clone());
+
+    let state = device.prepare_update_and_wait(Duration::from_secs(5)).await.unwrap();
+    stop.store(true, Ordering::SeqCst);
+
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+    assert_eq!(state.serial_number.as_deref(), Some("ABC"));
+    assert_eq!(prepares.lock().unwrap().len(), 1);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_reset_and_wait_rejects_other_device() {
+    let transport = LoopbackTransport::new();
+    let left = transport.add_device(mutenix_descriptor("LEFT"));
+    let right = transport.add_device(mutenix_descriptor("RIGHT"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    let (pad, other) = match device.state().await.serial_number.as_deref() {
+        Some("LEFT") => (left, "RIGHT"),
+        _ => (right, "LEFT"),
+    };
+
+    // The device does not come back, so the handler picks up the other one
+    let stop = Arc::new(AtomicBool::new(false));
+    spawn_responder(&pad, stop.clone(), |pad, report| {
+        if report[1] == HidOutCommand::Reset as u8 {
+            pad.set_connected(false);
+        }
+    });
+
+    let result = device.reset_and_wait(Duration::from_secs(5)).await;
+    stop.store(true, Ordering::SeqCst);
+    match result {
+        Err(HidError::UnexpectedDevice(serial)) => assert_eq!(serial, other),
+        other => panic!("Expected unexpected device error, got {:?}", other),
+    }
+
+    device.stop().await;
+}
+
+/// Transport keeping track of the connections currently open
+struct CountingTransport {
+    inner: LoopbackTransport,
+    open: Arc<AtomicUsize>,
+}
+
+struct CountingConnection {
+    inner: Box<dyn HidConnection>,
+    open: Arc<AtomicUsize>,
+}
+
+impl HidTransport for CountingTransport {
+    fn enumerate(&self) -> Result<Vec<DeviceDescriptor>, HidError> {
+        self.inner.enumerate()
+    }
+
+    fn open(&self, descriptor: &DeviceDescriptor) -> Result<Box<dyn HidConnection>, HidError> {
+        let inner = self.inner.open(descriptor)?;
+        self.open.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(CountingConnection {
+            inner,
+            open: self.open.clone(),
+        }))
+    }
+}
+
+impl HidConnection for CountingConnection {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        self.inner.write(data)
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        self.inner.read_timeout(buffer, timeout_ms)
+    }
+}
+
+impl Drop for CountingConnection {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_stop_closes_connection_and_process_restarts() {
+    let loopback = LoopbackTransport::new();
+    loopback.add_device(mutenix_descriptor("ABC"));
+    let open = Arc::new(AtomicUsize::new(0));
+    let transport = CountingTransport {
+        inner: loopback,
+        open: open.clone(),
+    };
+    let device = Arc::new(HidDevice::with_transport(Vec::new(), Arc::new(transport)));
+
+    for _ in 0..2 {
+        let process_device = device.clone();
+        let process = tokio::spawn(async move { process_device.process().await });
+        assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+        assert_eq!(open.load(Ordering::SeqCst), 1);
+        assert!(device.send_command(SimpleCommand::ping(1)).await.is_ok());
+
+        tokio::time::timeout(Duration::from_secs(1), device.stop()).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(1), process).await.unwrap().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(open.load(Ordering::SeqCst), 0);
+        assert_eq!(device.state().await.connection_status, ConnectionState::Disconnected);
+        assert!(matches!(device.send_command(SimpleCommand::ping(2)).await, Err(HidError::Stopped)));
+    }
+}
+
+#[tokio::test]
+async fn test_stop_fails_queued_commands() {
+    // Without a device the command waits in the queue
+    let transport = LoopbackTransport::new();
+    let device = spawn_device(&transport, Vec::new());
+
+    let sender = device.clone();
+    let command = tokio::spawn(async move { sender.send_command(SimpleCommand::ping(1)).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!command.is_finished());
+
+    device.stop().await;
+    let result = tokio::time::timeout(Duration::from_secs(1), command).await.unwrap().unwrap();
+    assert!(matches!(result, Err(HidError::Stopped)));
+}
+
+#[tokio::test]
+async fn test_process_runs_only_once_at_a_time() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = spawn_device(&transport, Vec::new());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    assert!(matches!(device.process().await, Err(HidError::AlreadyRunning)));
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_stop_before_process_does_not_end_it() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    let device = Arc::new(HidDevice::with_transport(Vec::new(), Arc::new(transport.clone())));
+    tokio::time::timeout(Duration::from_secs(1), device.stop()).await.unwrap();
+
+    let process_device = device.clone();
+    let process = tokio::spawn(async move { process_device.process().await });
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    assert!(!process.is_finished());
+    assert!(device.send_command(SimpleCommand::ping(1)).await.is_ok());
+
+    device.stop().await;
+    let result = tokio::time::timeout(Duration::from_secs(1), process).await.unwrap().unwrap();
+    assert!(result.is_ok());
+}
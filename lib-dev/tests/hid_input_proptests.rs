@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::device_messages::{parse_hid_update_message, ChunkAck, LogMessage, UpdateError};
+use mutenix_hid::hid_commands::*;
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn parse_never_panics(buffer in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = parse_input_message(&buffer);
+    }
+
+    #[test]
+    fn update_parsers_never_panic(buffer in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = parse_hid_update_message(&buffer);
+        let _ = ChunkAck::from_bytes(&buffer);
+        let _ = UpdateError::from_bytes(&buffer);
+        let _ = LogMessage::from_bytes(&buffer);
+    }
+
+    #[test]
+    fn short_buffers_report_their_length(buffer in proptest::collection::vec(any::<u8>(), 0..2)) {
+        match parse_input_message(&buffer) {
+            Err(HidMessageError::InvalidLength { expected, actual }) => {
+                prop_assert_eq!(expected, 2);
+                prop_assert_eq!(actual, buffer.len());
+            }
+            other => prop_assert!(false, "Expected InvalidLength, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected(
+        report_id in any::<u8>(),
+        command in any::<u8>().prop_filter("known command", |c| ![0x01, 0x02, 0x99].contains(c)),
+        payload in proptest::collection::vec(any::<u8>(), 0..8),
+    ) {
+        let mut buffer = vec![report_id, command];
+        buffer.extend(&payload);
+        let is_update_message = matches!(buffer.get(1..3), Some(b"AK" | b"ER" | b"LD" | b"LE"));
+        prop_assume!(!is_update_message);
+
+        match parse_input_message(&buffer) {
+            Err(HidMessageError::UnknownCommand(cmd)) => prop_assert_eq!(cmd, command),
+            other => prop_assert!(false, "Expected UnknownCommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn status_fields_round_trip(
+        button in any::<u8>(),
+        triggered in any::<bool>(),
+        longpress in any::<bool>(),
+        pressed in any::<bool>(),
+        released in any::<bool>(),
+    ) {
+        let buffer = [
+            1,
+            HidInCommand::Status as u8,
+            button,
+            triggered as u8,
+            longpress as u8,
+            pressed as u8,
+            released as u8,
+            0,
+        ];
+        let HidInput::Status(status) = parse_input_message(&buffer).unwrap() else {
+            return Err(TestCaseError::fail("Expected Status"));
+        };
+        prop_assert_eq!(status.button(), button);
+        prop_assert_eq!(status.triggered(), triggered);
+        prop_assert_eq!(status.longpressed(), longpress);
+        prop_assert_eq!(status.pressed(), pressed);
+        prop_assert_eq!(status.released(), released);
+    }
+
+    #[test]
+    fn version_info_round_trips(major in any::<u8>(), minor in any::<u8>(), patch in any::<u8>(), hardware in any::<u8>()) {
+        let buffer = [1, HidInCommand::VersionInfo as u8, major, minor, patch, hardware, 0, 0];
+        let HidInput::VersionInfo(version_info) = parse_input_message(&buffer).unwrap() else {
+            return Err(TestCaseError::fail("Expected VersionInfo"));
+        };
+        prop_assert_eq!(version_info.version(), format!("{}.{}.{}", major, minor, patch));
+        prop_assert_eq!(version_info.hardware_type(), HardwareType::from(hardware));
+    }
+
+    #[test]
+    fn chunk_ack_round_trips(id in any::<u16>(), package in any::<u16>(), type_ in any::<u8>()) {
+        let mut buffer = vec![1];
+        buffer.extend(b"AK");
+        buffer.extend(&id.to_le_bytes());
+        buffer.extend(&package.to_le_bytes());
+        buffer.push(type_);
+        let HidInput::ChunkAck(ack) = parse_input_message(&buffer).unwrap() else {
+            return Err(TestCaseError::fail("Expected ChunkAck"));
+        };
+        prop_assert_eq!((ack.id, ack.package, ack.type_), (id, package, type_));
+    }
+
+    #[test]
+    fn log_message_stops_at_terminator(text in "[ -~]{0,40}", error in any::<bool>()) {
+        let mut buffer = vec![1];
+        buffer.extend(if error { b"LE" } else { b"LD" });
+        buffer.extend(text.as_bytes());
+        buffer.extend([0, 0xFF, 0xFF]);
+        let HidInput::Log(log) = parse_input_message(&buffer).unwrap() else {
+            return Err(TestCaseError::fail("Expected Log"));
+        };
+        prop_assert_eq!(log.message, text);
+        prop_assert_eq!(log.level, if error { mutenix_hid::LogLevel::Error } else { mutenix_hid::LogLevel::Debug });
+    }
+}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::led_animation::*;
+use mutenix_hid::LedColor;
+use std::time::{Duration, Instant};
+
+const RED: LedColor = LedColor::Rgbw { r: 200, g: 0, b: 0, w: 0 };
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+#[test]
+fn test_solid() {
+    let animation = LedAnimation::solid(RED);
+    assert_eq!(animation.color_at(ms(0)), RED);
+    assert_eq!(animation.color_at(ms(12345)), RED);
+}
+
+#[test]
+fn test_blink() {
+    let animation = LedAnimation::new(RED, LedPattern::Blink { period: ms(1000) });
+    assert_eq!(animation.color_at(ms(0)), RED);
+    assert_eq!(animation.color_at(ms(499)), RED);
+    assert_eq!(animation.color_at(ms(500)), LedColor::Black);
+    assert_eq!(animation.color_at(ms(1200)), RED);
+}
+
+#[test]
+fn test_pulse_fades_out() {
+    let animation = LedAnimation::new(RED, LedPattern::Pulse { period: ms(1000) });
+    assert_eq!(animation.color_at(ms(0)).to_rgbw(), [200, 0, 0, 0]);
+    assert_eq!(animation.color_at(ms(500)).to_rgbw(), [100, 0, 0, 0]);
+    assert_eq!(animation.color_at(ms(1000)).to_rgbw(), [200, 0, 0, 0]);
+}
+
+#[test]
+fn test_breathe() {
+    let animation = LedAnimation::new(RED, LedPattern::Breathe { period: ms(2000) });
+    assert_eq!(animation.color_at(ms(0)).to_rgbw(), [0, 0, 0, 0]);
+    assert_eq!(animation.color_at(ms(500)).to_rgbw(), [100, 0, 0, 0]);
+    assert_eq!(animation.color_at(ms(1000)).to_rgbw(), [200, 0, 0, 0]);
+    assert_eq!(animation.color_at(ms(1500)).to_rgbw(), [100, 0, 0, 0]);
+}
+
+#[test]
+fn test_alternate() {
+    let animation = LedAnimation::new(
+        RED,
+        LedPattern::Alternate { other: LedColor::Blue, period: ms(1000) },
+    );
+    assert_eq!(animation.color_at(ms(100)), RED);
+    assert_eq!(animation.color_at(ms(600)), LedColor::Blue);
+}
+
+#[test]
+fn test_flash_is_one_shot() {
+    let animation = LedAnimation::new(RED, LedPattern::Flash { duration: ms(300) });
+    assert_eq!(animation.color_at(ms(0)), RED);
+    assert_eq!(animation.color_at(ms(299)), RED);
+    assert_eq!(animation.color_at(ms(300)), LedColor::Black);
+    assert_eq!(animation.color_at(ms(5000)), LedColor::Black);
+}
+
+#[test]
+fn test_zero_period_does_not_panic() {
+    let animation = LedAnimation::new(RED, LedPattern::Blink { period: Duration::ZERO });
+    assert_eq!(animation.color_at(ms(100)), RED);
+}
+
+#[test]
+fn test_animator_frames() {
+    let start = Instant::now();
+    let mut animator = LedAnimator::new();
+    animator.set(1, LedAnimation::solid(LedColor::Green), start);
+    animator.set(2, LedAnimation::new(RED, LedPattern::Blink { period: ms(1000) }), start);
+
+    assert_eq!(animator.frames(start), vec![(1, LedColor::Green), (2, RED)]);
+    assert_eq!(animator.frames(start + ms(100)), vec![(1, LedColor::Green), (2, RED)]);
+
+    // The blinking LED turned off, frames include unchanged LEDs
+    assert_eq!(
+        animator.frames(start + ms(600)),
+        vec![(1, LedColor::Green), (2, LedColor::Black)]
+    );
+}
+
+#[test]
+fn test_animator_restarts_only_on_change() {
+    let start = Instant::now();
+    let mut animator = LedAnimator::new();
+    let flash = LedAnimation::new(RED, LedPattern::Flash { duration: ms(300) });
+
+    animator.set(1, flash, start);
+    animator.set(1, flash, start + ms(200));
+    assert_eq!(animator.frames(start + ms(400)), vec![(1, LedColor::Black)]);
+
+    // A different animation starts over
+    animator.set(1, LedAnimation::new(LedColor::Blue, LedPattern::Flash { duration: ms(300) }), start + ms(500));
+    assert_eq!(animator.frames(start + ms(600)), vec![(1, LedColor::Blue)]);
+}
@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::led_framebuffer::*;
+
+const RED: [u8; 4] = [0x0A, 0, 0, 0];
+const GREEN: [u8; 4] = [0, 0x0A, 0, 0];
+
+#[test]
+fn test_only_changes_are_written() {
+    let mut framebuffer = LedFramebuffer::new();
+
+    assert!(framebuffer.set(1, RED));
+    framebuffer.acknowledge(1, RED);
+    assert!(!framebuffer.set(1, RED));
+
+    assert!(framebuffer.set(1, GREEN));
+    assert_eq!(framebuffer.desired(), vec![(1, GREEN)]);
+}
+
+#[test]
+fn test_unacknowledged_write_is_repeated() {
+    let mut framebuffer = LedFramebuffer::new();
+
+    assert!(framebuffer.set(1, RED));
+    // Write failed, so the next update has to write again
+    assert!(framebuffer.set(1, RED));
+}
+
+#[test]
+fn test_invalidate_resyncs_everything() {
+    let mut framebuffer = LedFramebuffer::new();
+    for (id, rgbw) in [(1, RED), (2, GREEN)] {
+        framebuffer.set(id, rgbw);
+        framebuffer.acknowledge(id, rgbw);
+    }
+    assert!(!framebuffer.set(1, RED));
+
+    framebuffer.invalidate();
+    assert_eq!(framebuffer.desired(), vec![(1, RED), (2, GREEN)]);
+    assert!(framebuffer.set(1, RED));
+    assert!(framebuffer.set(2, GREEN));
+}
+
+#[test]
+fn test_change_back_before_write_is_written() {
+    let mut framebuffer = LedFramebuffer::new();
+    framebuffer.set(1, RED);
+    framebuffer.acknowledge(1, RED);
+
+    // GREEN is still queued when RED is set again, so RED has to follow it
+    assert!(framebuffer.set(1, GREEN));
+    assert!(framebuffer.set(1, RED));
+}
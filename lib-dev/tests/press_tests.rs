@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::*;
+use std::time::{Duration, Instant};
+
+/// Status report as the firmware sends it: `[button, triggered, longpressed, pressed, released]`
+fn status(button: u8, triggered: bool, longpressed: bool, pressed: bool, released: bool) -> Status {
+    let buffer = [button, triggered as u8, longpressed as u8, pressed as u8, released as u8, 0];
+    Status::from_buffer(&buffer).unwrap()
+}
+
+fn press(button: u8) -> Status {
+    status(button, false, false, true, false)
+}
+
+fn release(button: u8) -> Status {
+    status(button, false, false, false, true)
+}
+
+fn triggered_release(button: u8, longpressed: bool) -> Status {
+    status(button, true, longpressed, false, true)
+}
+
+#[test]
+fn test_host_timing_uses_threshold() {
+    let mut classifier = PressClassifier::new(PressMode::Host, Duration::from_millis(300));
+    let start = Instant::now();
+
+    assert_eq!(classifier.classify("A", &press(1), start), None);
+    let short = classifier.classify("A", &release(1), start + Duration::from_millis(299)).unwrap();
+    assert_eq!(short.kind, PressKind::Short);
+    assert_eq!(short.duration, Some(Duration::from_millis(299)));
+
+    classifier.classify("A", &press(1), start);
+    let long = classifier.classify("A", &release(1), start + Duration::from_millis(300)).unwrap();
+    assert!(long.is_long());
+    assert_eq!(long.button, 1);
+
+    // Firmware flags are ignored when the host times presses
+    classifier.classify("A", &press(2), start);
+    let press = classifier
+        .classify("A", &triggered_release(2, true), start + Duration::from_millis(10))
+        .unwrap();
+    assert_eq!(press.kind, PressKind::Short);
+}
+
+#[test]
+fn test_host_timing_per_device_and_button() {
+    let mut classifier = PressClassifier::new(PressMode::Host, DEFAULT_LONGPRESS_THRESHOLD);
+    let start = Instant::now();
+
+    classifier.classify("A", &press(1), start);
+    classifier.classify("B", &press(1), start + Duration::from_millis(400));
+    classifier.classify("A", &press(2), start + Duration::from_millis(400));
+
+    let at = start + Duration::from_millis(600);
+    assert!(classifier.classify("A", &release(1), at).unwrap().is_long());
+    assert!(!classifier.classify("B", &release(1), at).unwrap().is_long());
+    assert!(!classifier.classify("A", &release(2), at).unwrap().is_long());
+
+    // A release without a recorded press is a short press
+    let unmatched = classifier.classify("A", &release(3), at).unwrap();
+    assert_eq!(unmatched.kind, PressKind::Short);
+    assert_eq!(unmatched.duration, Some(Duration::ZERO));
+}
+
+#[test]
+fn test_firmware_decides() {
+    let mut classifier = PressClassifier::new(PressMode::Firmware, Duration::from_millis(100));
+    let start = Instant::now();
+
+    assert_eq!(classifier.classify("A", &press(1), start), None);
+    // Untriggered releases are ignored, however long the button was held
+    assert_eq!(classifier.classify("A", &release(1), start + Duration::from_secs(2)), None);
+
+    let long = classifier.classify("A", &triggered_release(1, true), start).unwrap();
+    assert_eq!(long.kind, PressKind::Long);
+    assert_eq!(long.duration, None);
+
+    let short = classifier
+        .classify("A", &Status::trigger_button(4), start + Duration::from_secs(2))
+        .unwrap();
+    assert_eq!(short.kind, PressKind::Short);
+    assert_eq!(short.button, 4);
+}
+
+#[test]
+fn test_hybrid_prefers_firmware_flags() {
+    let mut classifier = PressClassifier::default();
+    assert_eq!(classifier.mode(), PressMode::Hybrid);
+    assert_eq!(classifier.threshold(), DEFAULT_LONGPRESS_THRESHOLD);
+    let start = Instant::now();
+
+    // Triggered release: the firmware decides and the host timing is dropped
+    classifier.classify("A", &press(1), start);
+    let press_1 = classifier
+        .classify("A", &triggered_release(1, false), start + Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(press_1.kind, PressKind::Short);
+    assert_eq!(press_1.duration, None);
+
+    // Firmware without the flags: the host times the press
+    classifier.classify("A", &press(2), start);
+    let press_2 = classifier.classify("A", &release(2), start + Duration::from_secs(1)).unwrap();
+    assert_eq!(press_2.kind, PressKind::Long);
+    assert_eq!(press_2.duration, Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_hybrid_ignores_release_after_trigger() {
+    let mut classifier = PressClassifier::new(PressMode::Hybrid, DEFAULT_LONGPRESS_THRESHOLD);
+    let start = Instant::now();
+    let reports = [
+        (press(1), start),
+        (triggered_release(1, false), start + Duration::from_millis(100)),
+        (release(1), start + Duration::from_millis(110)),
+    ];
+
+    let presses: Vec<Press> = reports
+        .iter()
+        .filter_map(|(status, at)| classifier.classify("A", status, *at))
+        .collect();
+    assert_eq!(presses.len(), 1);
+    assert_eq!(presses[0].kind, PressKind::Short);
+    assert_eq!(presses[0].duration, None);
+
+    // Neither a stray release nor a report without the release flag is a press
+    assert_eq!(classifier.classify("A", &release(2), start), None);
+    classifier.classify("A", &press(3), start);
+    assert_eq!(classifier.classify("A", &status(3, false, false, false, false), start), None);
+    assert!(classifier.classify("A", &release(3), start + Duration::from_secs(1)).unwrap().is_long());
+}
+
+#[test]
+fn test_mode_names() {
+    for (name, mode) in [
+        ("firmware", PressMode::Firmware),
+        ("host", PressMode::Host),
+        ("hybrid", PressMode::Hybrid),
+    ] {
+        assert_eq!(serde_json::to_string(&mode).unwrap(), format!("\"{}\"", name));
+        assert_eq!(serde_json::from_str::<PressMode>(&format!("\"{}\"", name)).unwrap(), mode);
+    }
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::transport::*;
+
+#[test]
+fn test_loopback_enumerate() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("A"));
+    transport.add_device(mutenix_descriptor("B"));
+
+    let devices = transport.enumerate().unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].serial_number.as_deref(), Some("A"));
+    assert_eq!(devices[1].serial_number.as_deref(), Some("B"));
+}
+
+#[test]
+fn test_loopback_read_and_write() {
+    let transport = LoopbackTransport::new();
+    let device = transport.add_device(mutenix_descriptor("A"));
+    let connection = transport.open(device.descriptor()).unwrap();
+
+    device.inject(&[1, 0x01, 2, 0, 0, 1, 0, 0]);
+    let mut buffer = [0u8; 64];
+    let size = connection.read_timeout(&mut buffer, 10).unwrap();
+    assert_eq!(&buffer[..size], &[1, 0x01, 2, 0, 0, 1, 0, 0]);
+
+    // Nothing queued reads as a timeout
+    assert_eq!(connection.read_timeout(&mut buffer, 10).unwrap(), 0);
+
+    connection.write(&[1, 0xF0, 0, 0, 0, 0, 0, 0, 7]).unwrap();
+    assert_eq!(device.take_written(), vec![vec![1, 0xF0, 0, 0, 0, 0, 0, 0, 7]]);
+    assert!(device.take_written().is_empty());
+}
+
+#[test]
+fn test_loopback_unplug() {
+    let transport = LoopbackTransport::new();
+    let device = transport.add_device(mutenix_descriptor("A"));
+    let connection = transport.open(device.descriptor()).unwrap();
+
+    device.set_connected(false);
+    assert!(transport.enumerate().unwrap().is_empty());
+    assert!(transport.open(device.descriptor()).is_err());
+
+    let mut buffer = [0u8; 64];
+    assert!(connection.read_timeout(&mut buffer, 10).is_err());
+    assert!(connection.write(&[1, 0xF0]).is_err());
+
+    // Replugging needs a fresh connection
+    device.set_connected(true);
+    assert!(connection.write(&[1, 0xF0]).is_err());
+    let connection = transport.open(device.descriptor()).unwrap();
+    assert!(connection.write(&[1, 0xF0]).is_ok());
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn fast_watchdog() -> WatchdogConfig {
+    WatchdogConfig::new()
+        .with_ping_interval(Duration::from_millis(150))
+        .with_degraded_after(1)
+        .with_error_after(3)
+        .with_reconnect_delay(Duration::from_millis(50), Duration::from_millis(200))
+}
+
+fn spawn_watched_device(transport: &LoopbackTransport, watchdog: WatchdogConfig) -> Arc<HidDevice> {
+    spawn_process(HidDevice::with_transport(Vec::new(), Arc::new(transport.clone())).with_watchdog(watchdog))
+}
+
+/// Answer every ping with the version, as the firmware does, while `answer` is set
+fn spawn_ping_responder(pad: &LoopbackDevice, answer: Arc<AtomicBool>, stop: Arc<AtomicBool>) {
+    spawn_responder(pad, stop, move |pad, report| {
+        if report[1] == HidOutCommand::Ping as u8 && answer.load(Ordering::SeqCst) {
+            pad.inject(&[1, 0x99, 1, 2, 3, HardwareType::FiveButtonUsb as u8, 0, 0]);
+        }
+    });
+}
+
+#[tokio::test]
+async fn test_measures_round_trip_time() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+    let stop = Arc::new(AtomicBool::new(false));
+    spawn_ping_responder(&pad, Arc::new(AtomicBool::new(true)), stop.clone());
+
+    let device = spawn_watched_device(&transport, fast_watchdog());
+    assert!(wait_for_state(&device, |s| s.link.pings_answered >= 3).await);
+    stop.store(true, Ordering::SeqCst);
+
+    let state = device.state().await;
+    let link = &state.link;
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+    assert!(link.pings_sent >= link.pings_answered);
+    assert_eq!(link.consecutive_misses, 0);
+    let (min, max) = (link.min_rtt.unwrap(), link.max_rtt.unwrap());
+    assert!(min <= link.last_rtt.unwrap() && link.last_rtt.unwrap() <= max);
+    assert!(min <= link.average_rtt.unwrap() && link.average_rtt.unwrap() <= max);
+    // VersionInfo does not repeat the ping counter
+    assert!(link.rtt_approximate);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_unresponsive_device_is_degraded_and_reopened() {
+    let transport = LoopbackTransport::new();
+    transport.add_device(mutenix_descriptor("ABC"));
+
+    // Nobody answers the pings
+    let device = spawn_watched_device(&transport, fast_watchdog());
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Degraded).await);
+    assert!(device.state().await.connection_status.is_connected());
+
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Error).await);
+    assert!(!device.state().await.connection_status.is_connected());
+
+    // Reopened after the reconnect delay
+    assert!(wait_for_state(&device, |s| s.link.reconnects >= 1).await);
+    let link = device.state().await.link;
+    assert_eq!(link.watchdog_resets, 1);
+    assert!(link.pings_missed >= 3);
+    assert_eq!(link.pings_answered, 0);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_any_report_keeps_link_alive() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+
+    // The device never answers pings, but keeps sending button reports
+    let stop = Arc::new(AtomicBool::new(false));
+    let sender_pad = pad.clone();
+    let sender_stop = stop.clone();
+    std::thread::spawn(move || {
+        while !sender_stop.load(Ordering::SeqCst) {
+            sender_pad.inject(&[1, 0x01, 3, 0, 0, 1, 0, 0]);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    });
+
+    let device = spawn_watched_device(&transport, fast_watchdog());
+    assert!(wait_for_state(&device, |s| s.link.pings_answered >= 3).await);
+    let state = device.state().await;
+    stop.store(true, Ordering::SeqCst);
+
+    assert_eq!(state.connection_status, ConnectionState::Connected);
+    assert_eq!(state.link.pings_missed, 0);
+    assert_eq!(state.link.watchdog_resets, 0);
+    // Without VersionInfo there is no round-trip time
+    assert_eq!(state.link.last_rtt, None);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_degraded_device_recovers() {
+    let transport = LoopbackTransport::new();
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+    let answer = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    spawn_ping_responder(&pad, answer.clone(), stop.clone());
+
+    let watchdog = fast_watchdog().with_error_after(1000);
+    let device = spawn_watched_device(&transport, watchdog);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Degraded).await);
+
+    answer.store(true, Ordering::SeqCst);
+    assert!(wait_for_state(&device, |s| s.connection_status == ConnectionState::Connected).await);
+    stop.store(true, Ordering::SeqCst);
+
+    let link = device.state().await.link;
+    assert_eq!(link.consecutive_misses, 0);
+    assert!(link.pings_missed >= 1);
+    assert_eq!(link.watchdog_resets, 0);
+    assert_eq!(link.reconnects, 0);
+
+    device.stop().await;
+}
+
+#[test]
+fn test_watchdog_config_limits() {
+    let config = WatchdogConfig::new()
+        .with_degraded_after(0)
+        .with_error_after(0)
+        .with_reconnect_delay(Duration::from_secs(5), Duration::from_secs(1));
+    assert_eq!(config.degraded_after(), 1);
+    assert_eq!(config.error_after(), 1);
+    assert_eq!(config.max_reconnect_delay(), Duration::from_secs(5));
+
+    let defaults = WatchdogConfig::default();
+    assert_eq!(defaults.ping_interval(), Duration::from_secs(PING_LOOP_TIME_SECONDS));
+    assert_eq!(defaults.error_after(), DEFAULT_ERROR_AFTER_MISSES);
+}
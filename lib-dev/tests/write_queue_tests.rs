@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+mod common;
+
+use common::*;
+use mutenix_hid::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Device handler without device, so commands stay in the queue
+fn idle_device(config: WriteQueueConfig) -> Arc<HidDevice> {
+    Arc::new(HidDevice::with_transport(Vec::new(), Arc::new(LoopbackTransport::new())).with_write_queue(config))
+}
+
+fn send_ping(device: &Arc<HidDevice>, counter: u8) -> tokio::task::JoinHandle<Result<usize, HidError>> {
+    let device = device.clone();
+    tokio::spawn(async move { device.send_command(SimpleCommand::ping(counter)).await })
+}
+
+async fn result_of(task: tokio::task::JoinHandle<Result<usize, HidError>>) -> Result<usize, HidError> {
+    tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn test_control_commands_overtake_coalesced_led_updates() {
+    let transport = LoopbackTransport::new();
+    let device = spawn_device(&transport, Vec::new());
+
+    // Queued while no device is attached
+    let red = {
+        let device = device.clone();
+        tokio::spawn(async move { device.send_command(SetLed::new(1, LedColor::Red)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let green = {
+        let device = device.clone();
+        tokio::spawn(async move { device.send_command(SetLed::new(1, LedColor::Green)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let ping = send_ping(&device, 7);
+
+    assert!(matches!(result_of(red).await, Err(HidError::Superseded)));
+
+    let pad = transport.add_device(mutenix_descriptor("ABC"));
+    tokio::time::timeout(Duration::from_secs(3), green).await.unwrap().unwrap().unwrap();
+    result_of(ping).await.unwrap();
+
+    let written = pad.take_written();
+    let position = |wanted: &dyn Fn(&Vec<u8>) -> bool| written.iter().position(wanted);
+    let ping_at = position(&|r| r[1] == HidOutCommand::Ping as u8 && r[8] == 7).unwrap();
+    let led_at = position(&|r| r[1] == HidOutCommand::SetLed as u8).unwrap();
+    assert!(ping_at < led_at);
+
+    let leds: Vec<&Vec<u8>> = written.iter().filter(|r| r[1] == HidOutCommand::SetLed as u8).collect();
+    assert_eq!(leds.len(), 1);
+    assert_eq!(&leds[0][2..7], &[1, 0x00, 0x0A, 0x00, 0x00]);
+
+    device.stop().await;
+}
+
+#[tokio::test]
+async fn test_full_queue_rejects_new_commands() {
+    let device = idle_device(WriteQueueConfig::new().with_capacity(2).with_policy(QueuePolicy::RejectNew));
+    let first = send_ping(&device, 1);
+    let second = send_ping(&device, 2);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    assert!(matches!(device.send_command(SimpleCommand::ping(3)).await, Err(HidError::QueueFull)));
+    // LED updates take no slot
+    let led = {
+        let device = device.clone();
+        tokio::spawn(async move { device.send_command(SetLed::new(1, LedColor::Red)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!led.is_finished());
+
+    device.stop().await;
+    for task in [first, second, led] {
+        assert!(matches!(result_of(task).await, Err(HidError::Stopped)));
+    }
+}
+
+#[tokio::test]
+async fn test_full_queue_drops_oldest_command() {
+    let device = idle_device(WriteQueueConfig::new().with_capacity(1).with_policy(QueuePolicy::DropOldest));
+    let first = send_ping(&device, 1);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let second = send_ping(&device, 2);
+
+    assert!(matches!(result_of(first).await, Err(HidError::QueueFull)));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!second.is_finished());
+
+    device.stop().await;
+    assert!(matches!(result_of(second).await, Err(HidError::Stopped)));
+}
+
+#[tokio::test]
+async fn test_full_queue_applies_backpressure() {
+    let device = idle_device(WriteQueueConfig::new().with_capacity(1));
+    let first = send_ping(&device, 1);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let waiting = send_ping(&device, 2);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!first.is_finished());
+    assert!(!waiting.is_finished());
+
+    // Stopping releases senders still waiting for room as well
+    device.stop().await;
+    assert!(matches!(result_of(first).await, Err(HidError::Stopped)));
+    assert!(matches!(result_of(waiting).await, Err(HidError::Stopped)));
+}
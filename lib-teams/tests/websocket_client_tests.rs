@@ -187,9 +187,11 @@ async fn test_state_management() {
     assert!(current_state.meeting_update.is_none());
     
     // Update state with a message
-    let mut message = ServerMessage::default();
-    message.request_id = Some(1);
-    message.error_msg = Some("TEST".to_string());
+    let message = ServerMessage {
+        request_id: Some(1),
+        error_msg: Some("TEST".to_string()),
+        ..Default::default()
+    };
     
     state.update_state(&message).await;
     
@@ -315,11 +317,15 @@ async fn test_request_id_auto_increment() {
 
 #[tokio::test]
 async fn test_server_message_merge() {
-    let mut base_msg = ServerMessage::default();
-    base_msg.request_id = Some(1);
+    let mut base_msg = ServerMessage {
+        request_id: Some(1),
+        ..Default::default()
+    };
     
-    let mut update_msg = ServerMessage::default();
-    update_msg.error_msg = Some("Error".to_string());
+    let update_msg = ServerMessage {
+        error_msg: Some("Error".to_string()),
+        ..Default::default()
+    };
     
     base_msg.merge(&update_msg);
     
@@ -364,8 +370,10 @@ async fn test_state_concurrent_updates() {
     for i in 0..10 {
         let state_clone = state.clone();
         let handle = tokio::spawn(async move {
-            let mut msg = ServerMessage::default();
-            msg.request_id = Some(i);
+            let msg = ServerMessage {
+                request_id: Some(i),
+                ..Default::default()
+            };
             state_clone.update_state(&msg).await;
         });
         handles.push(handle);
@@ -68,8 +68,8 @@ impl EmulatorState {
         Self {
             version: "1.0.0".to_string(),
             hardware_type: hardware_type as u8,
-            leds: (0..num_leds).map(|i| LedState::new(i)).collect(),
-            buttons: (0..num_buttons).map(|i| ButtonState::new(i)).collect(),
+            leds: (0..num_leds).map(LedState::new).collect(),
+            buttons: (0..num_buttons).map(ButtonState::new).collect(),
             serial_number: "EMULATOR001".to_string(),
         }
     }
@@ -78,6 +78,7 @@ impl EmulatorState {
 /// Device emulator
 pub struct DeviceEmulator {
     state: Arc<RwLock<EmulatorState>>,
+    version_on_ping: bool,
 }
 
 impl DeviceEmulator {
@@ -91,9 +92,18 @@ impl DeviceEmulator {
 
         Self {
             state: Arc::new(RwLock::new(EmulatorState::new(hardware_type, num_buttons))),
+            version_on_ping: false,
         }
     }
 
+    /// Answer pings with the version info like the firmware does, so a host
+    /// learns the firmware version and hardware type on connect.
+    /// Off by default, pings are then answered with nothing.
+    pub fn with_version_on_ping(mut self, enabled: bool) -> Self {
+        self.version_on_ping = enabled;
+        self
+    }
+
     /// Get current state
     pub async fn get_state(&self) -> EmulatorState {
         self.state.read().await.clone()
@@ -148,7 +158,11 @@ impl DeviceEmulator {
         let counter = buffer[7];
         log::debug!("Ping received with counter: {}", counter);
 
-        Ok(Vec::new())
+        if self.version_on_ping {
+            Ok(self.get_version_info().await)
+        } else {
+            Ok(Vec::new())
+        }
     }
 
     /// Handle UpdateConfig command
@@ -202,7 +216,7 @@ impl DeviceEmulator {
         let state = self.state.read().await;
         let version_parts: Vec<&str> = state.version.split('.').collect();
         
-        let major = version_parts.get(0).and_then(|v| v.parse::<u8>().ok()).unwrap_or(1);
+        let major = version_parts.first().and_then(|v| v.parse::<u8>().ok()).unwrap_or(1);
         let minor = version_parts.get(1).and_then(|v| v.parse::<u8>().ok()).unwrap_or(0);
         let patch = version_parts.get(2).and_then(|v| v.parse::<u8>().ok()).unwrap_or(0);
 
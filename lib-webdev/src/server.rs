@@ -53,7 +53,7 @@ struct ServerState {
 
 /// Web server for device emulation
 pub struct WebServer {
-    emulator: Arc<DeviceEmulator>,
+    emulator: DeviceEmulator,
     port: u16,
 }
 
@@ -61,15 +61,21 @@ impl WebServer {
     /// Create a new web server
     pub fn new(hardware_type: HardwareType, port: u16) -> Self {
         Self {
-            emulator: Arc::new(DeviceEmulator::new(hardware_type)),
+            emulator: DeviceEmulator::new(hardware_type),
             port,
         }
     }
 
+    /// Answer pings with the version info, see `DeviceEmulator::with_version_on_ping`
+    pub fn with_version_on_ping(mut self, enabled: bool) -> Self {
+        self.emulator = self.emulator.with_version_on_ping(enabled);
+        self
+    }
+
     /// Run the web server
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
         let state = Arc::new(ServerState {
-            emulator: self.emulator,
+            emulator: Arc::new(self.emulator),
         });
 
         let app = Router::new()
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+use mutenix_hid::HardwareType;
+use mutenix_webdev::DeviceEmulator;
+
+/// Ping report with the given counter: report ID, command, padding, counter
+fn ping(counter: u8) -> Vec<u8> {
+    vec![1, 0xF0, 0, 0, 0, 0, 0, 0, counter]
+}
+
+#[tokio::test]
+async fn test_ping_is_not_answered_by_default() {
+    let emulator = DeviceEmulator::new(HardwareType::FiveButtonUsb);
+    assert!(emulator.process_command(&ping(3)).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_ping_is_answered_with_version_info_when_enabled() {
+    let emulator = DeviceEmulator::new(HardwareType::TenButtonUsb).with_version_on_ping(true);
+    let response = emulator.process_command(&ping(3)).await.unwrap();
+    assert_eq!(response, vec![1, 0x99, 1, 0, 0, HardwareType::TenButtonUsb as u8, 0, 0]);
+    assert_eq!(response, emulator.get_version_info().await);
+}
@@ -46,26 +46,24 @@ impl LogEntry {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DeviceStatus {
     pub connected: bool,
+    pub device_count: usize,
     pub manufacturer: Option<String>,
     pub product: Option<String>,
     pub serial_number: Option<String>,
+    pub firmware_version: Option<String>,
+    pub hardware_type: Option<String>,
+    /// A connected device recently missed pings
+    pub degraded: bool,
+    /// Average ping round-trip time of the first device in milliseconds
+    pub latency_ms: Option<f64>,
+    /// Pings missed by all connected devices
+    pub missed_pings: u64,
 }
 
-impl Default for DeviceStatus {
-    fn default() -> Self {
-        Self {
-            connected: false,
-            manufacturer: None,
-            product: None,
-            serial_number: None,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TeamsStatus {
     pub connected: bool,
     pub in_meeting: bool,
@@ -75,19 +73,6 @@ pub struct TeamsStatus {
     pub is_recording: bool,
 }
 
-impl Default for TeamsStatus {
-    fn default() -> Self {
-        Self {
-            connected: false,
-            in_meeting: false,
-            is_muted: false,
-            is_video_on: false,
-            is_hand_raised: false,
-            is_recording: false,
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct AppState {
     device_status: Arc<RwLock<DeviceStatus>>,
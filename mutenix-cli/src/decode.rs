@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! `mutenix-cli decode <hex>`: describe a raw HID frame, e.g. from a debug log
+
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use mutenix_hid::{dissect, parse_hex, FrameDirection};
+
+#[derive(Args, Debug)]
+pub struct DecodeArgs {
+    /// Direction of the frame; both are shown if omitted
+    #[arg(short, long, value_enum)]
+    direction: Option<Direction>,
+
+    /// Frame bytes including the report ID, e.g. "01 01 03 0a" or "[01, 01, 03, 0a]"
+    #[arg(required = true, num_args = 1..)]
+    hex: Vec<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Direction {
+    /// Sent by the host
+    Tx,
+    /// Sent by the device
+    Rx,
+}
+
+pub fn run(args: DecodeArgs) -> Result<()> {
+    let frame = parse_hex(&args.hex.join(" "))?;
+
+    let directions = match args.direction {
+        Some(Direction::Tx) => vec![FrameDirection::HostToDevice],
+        Some(Direction::Rx) => vec![FrameDirection::DeviceToHost],
+        None => vec![FrameDirection::HostToDevice, FrameDirection::DeviceToHost],
+    };
+
+    for direction in directions {
+        let dissected = dissect(direction, &frame);
+        let label = match direction {
+            FrameDirection::HostToDevice => "host -> device",
+            FrameDirection::DeviceToHost => "device -> host",
+        };
+        match dissected.report_id {
+            Some(report_id) => println!("{} (report {}): {}", label, report_id, dissected),
+            None => println!("{}: {}", label, dissected),
+        }
+    }
+    Ok(())
+}
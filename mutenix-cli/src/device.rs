@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
+
+//! `mutenix-cli device ...`: one-shot commands for a single device, run
+//! without the terminal UI or the Teams connection.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use lib_base::Config;
+use mutenix_hid::{
+    DeviceInfo, DeviceSettings, FsBatch, FsOperation, HardwareState, HidDevice, UpdateOptions,
+    UpdateProgress, DEFAULT_CONFIGURE_TIMEOUT_MS,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceCommand {
+    /// Change the settings stored on the device; it restarts to apply them
+    Config(ConfigArgs),
+    /// Restart the device and wait until it is back
+    Reset(Target),
+    /// Put the device into update mode and wait until it is back
+    PrepareUpdate(Target),
+    /// Write a single file to the device without resetting it
+    Push(PushArgs),
+    /// Delete a single file on the device without resetting it
+    Delete(DeleteArgs),
+    /// Write and delete several files in one session
+    Fs(FsArgs),
+}
+
+/// Which device to talk to
+#[derive(Args, Debug)]
+pub struct Target {
+    /// Serial number of the device (default: first configured device)
+    #[arg(short, long)]
+    serial: Option<String>,
+
+    /// Seconds to wait for the device to connect and, for `config`, `reset` and
+    /// `prepare-update`, to restart
+    #[arg(long, default_value_t = DEFAULT_CONFIGURE_TIMEOUT_MS / 1000)]
+    timeout: u64,
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    clap::ArgGroup::new("settings")
+        .required(true)
+        .multiple(true)
+        .args(["serial_console", "filesystem"])
+))]
+pub struct ConfigArgs {
+    /// Expose the serial console over USB
+    #[arg(long, value_enum)]
+    serial_console: Option<Switch>,
+
+    /// Expose the device filesystem as USB drive
+    #[arg(long, value_enum)]
+    filesystem: Option<Switch>,
+
+    #[command(flatten)]
+    target: Target,
+}
+
+#[derive(Args, Debug)]
+pub struct PushArgs {
+    /// Local file to write
+    file: PathBuf,
+
+    /// Path on the device (default: the name of the local file)
+    #[arg(long = "as")]
+    remote: Option<String>,
+
+    #[command(flatten)]
+    target: Target,
+}
+
+#[derive(Args, Debug)]
+pub struct DeleteArgs {
+    /// Path on the device
+    remote: String,
+
+    #[command(flatten)]
+    target: Target,
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    clap::ArgGroup::new("operations")
+        .required(true)
+        .multiple(true)
+        .args(["push", "delete"])
+))]
+pub struct FsArgs {
+    /// Local file to write under its own name; pushes run before deletes
+    #[arg(long, value_name = "FILE")]
+    push: Vec<PathBuf>,
+
+    /// Path on the device to delete
+    #[arg(long, value_name = "PATH")]
+    delete: Vec<String>,
+
+    #[command(flatten)]
+    target: Target,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Switch {
+    On,
+    Off,
+}
+
+impl From<Switch> for bool {
+    fn from(switch: Switch) -> Self {
+        matches!(switch, Switch::On)
+    }
+}
+
+pub async fn run(config: &Config, command: DeviceCommand) -> Result<()> {
+    match command {
+        DeviceCommand::Config(args) => configure(config, args).await,
+        DeviceCommand::Reset(target) => reset(config, target).await,
+        DeviceCommand::PrepareUpdate(target) => prepare_update(config, target).await,
+        DeviceCommand::Push(args) => {
+            let remote = match args.remote {
+                Some(remote) => remote,
+                None => file_name(&args.file)?,
+            };
+            let batch = FsBatch::new().write_file(remote, &args.file)?;
+            run_fs_batch(config, &args.target, batch).await
+        }
+        DeviceCommand::Delete(args) => {
+            run_fs_batch(config, &args.target, FsBatch::new().delete(args.remote)).await
+        }
+        DeviceCommand::Fs(args) => {
+            let mut batch = FsBatch::new();
+            for file in &args.push {
+                batch = batch.write_file(file_name(file)?, file)?;
+            }
+            for remote in args.delete {
+                batch = batch.delete(remote);
+            }
+            run_fs_batch(config, &args.target, batch).await
+        }
+    }
+}
+
+/// Connect to the target device and wait until it has reported its version
+async fn connect(config: &Config, target: &Target) -> Result<(Arc<HidDevice>, HardwareState)> {
+    let device_info = match &target.serial {
+        Some(serial) => vec![DeviceInfo::serial(serial.clone())],
+        None => config.get_device_info(),
+    };
+    let device = Arc::new(HidDevice::new(device_info).with_watchdog(config.watchdog.to_watchdog()));
+    let process_device = device.clone();
+    tokio::spawn(async move {
+        if let Err(e) = process_device.process().await {
+            eprintln!("Device error: {}", e);
+        }
+    });
+
+    match wait_for_version(&device, Duration::from_secs(target.timeout)).await {
+        Ok(state) => Ok((device, state)),
+        Err(e) => {
+            device.stop().await;
+            Err(e)
+        }
+    }
+}
+
+async fn run_fs_batch(config: &Config, target: &Target, batch: FsBatch) -> Result<()> {
+    let (device, state) = connect(config, target).await?;
+    println!("Connected to {}", describe(&state));
+    for operation in batch.operations() {
+        match operation {
+            FsOperation::Write { path, content } => println!("  write  {} ({} bytes)", path, content.len()),
+            FsOperation::Delete { path } => println!("  delete {}", path),
+        }
+    }
+
+    let options = UpdateOptions::new().with_progress(print_progress);
+    let result = device.fs().apply(&batch, &options).await;
+    println!();
+    device.stop().await;
+
+    result.context("Filesystem session failed")?;
+    println!("Done, device keeps running");
+    Ok(())
+}
+
+fn print_progress(progress: UpdateProgress) {
+    print!(
+        "\r{} ({}/{}): {:>3.0}%",
+        progress.file,
+        progress.file_index + 1,
+        progress.file_count,
+        progress.fraction() * 100.0
+    );
+    let _ = std::io::stdout().flush();
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .with_context(|| format!("Invalid file name {:?}", path))
+}
+
+async fn configure(config: &Config, args: ConfigArgs) -> Result<()> {
+    let settings = DeviceSettings {
+        serial_console: args.serial_console.map(bool::from),
+        filesystem: args.filesystem.map(bool::from),
+    };
+    let timeout = Duration::from_secs(args.target.timeout);
+    let (device, state) = connect(config, &args.target).await?;
+
+    let result = async {
+        println!("Configuring {}: {}", describe(&state), settings);
+        println!("Waiting for the device to restart...");
+
+        let state = device
+            .configure(settings, timeout)
+            .await
+            .context("Failed to configure device")?;
+        println!("Device back online: {}", describe(&state));
+        println!("Connection: {:?}", state.connection_status);
+        // The firmware does not report its settings, only the restart is confirmed
+        println!("Requested settings: {}", settings);
+        Ok(())
+    }
+    .await;
+
+    device.stop().await;
+    result
+}
+
+async fn reset(config: &Config, target: Target) -> Result<()> {
+    let timeout = Duration::from_secs(target.timeout);
+    let (device, state) = connect(config, &target).await?;
+
+    let result = async {
+        println!("Resetting {}", describe(&state));
+        println!("Waiting for the device to restart...");
+
+        let state = device
+            .reset_and_wait(timeout)
+            .await
+            .context("Failed to reset device")?;
+        println!("Device back online: {}", describe(&state));
+        println!("Connection: {:?}", state.connection_status);
+        Ok(())
+    }
+    .await;
+
+    device.stop().await;
+    result
+}
+
+async fn prepare_update(config: &Config, target: Target) -> Result<()> {
+    let timeout = Duration::from_secs(target.timeout);
+    let (device, state) = connect(config, &target).await?;
+
+    let result = async {
+        println!("Putting {} into update mode", describe(&state));
+        println!("Waiting for the device to restart...");
+
+        let state = device
+            .prepare_update_and_wait(timeout)
+            .await
+            .context("Failed to enter update mode")?;
+        println!("Device back online: {}", describe(&state));
+        println!("Connection: {:?}", state.connection_status);
+        Ok(())
+    }
+    .await;
+
+    device.stop().await;
+    result
+}
+
+/// Wait until a device is connected and has reported its version
+async fn wait_for_version(device: &HidDevice, timeout: Duration) -> Result<HardwareState> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let state = device.state().await;
+        if state.connection_status.is_connected() && state.firmware_version.is_some() {
+            return Ok(state);
+        }
+        if Instant::now() >= deadline {
+            bail!("No device found within {:?}", timeout);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn describe(state: &HardwareState) -> String {
+    format!(
+        "{} (serial {}, firmware {})",
+        state.product.as_deref().unwrap_or("Unknown device"),
+        state.serial_number.as_deref().unwrap_or("unknown"),
+        state.firmware_version.as_deref().unwrap_or("unknown")
+    )
+}
@@ -2,16 +2,23 @@
 // Copyright (c) 2025 Matthias Bilger <matthias@bilger.info>
 
 mod app;
+mod decode;
+mod device;
 mod ui;
 
 use anyhow::{Context, Result};
 use app::{AppState, LogLevel};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use lib_base::{Config, TeamsStateType, execute_button_actions};
-use mutenix_hid::{ConnectionState as DeviceConnectionState, DeviceMessage, HidDevice, LedColor, SetLed};
+use mutenix_hid::{
+    CaptureTransport, CaptureWriter, ConnectionState as DeviceConnectionState, DeviceEvent,
+    DeviceManager, HidApiTransport, HidTransport, LedAnimator, LedColor, LogLevel as DeviceLogLevel,
+    PressClassifier, ReplayTransport, SetLed, TaggedDeviceEvent, LED_FRAME_INTERVAL,
+};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use teams_api::{
     ClientMessage, ConnectionState as TeamsConnectionState, Identifier, MeetingState, ServerMessage,
     TeamsState, TeamsWebSocketClient,
@@ -28,7 +35,7 @@ const TEAMS_WS_URI: &str = "ws://localhost:8124";
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Path to the configuration file
-    #[arg(short, long, default_value = DEFAULT_CONFIG_PATH)]
+    #[arg(short, long, default_value = DEFAULT_CONFIG_PATH, global = true)]
     config: PathBuf,
 
     /// Teams WebSocket URI
@@ -42,31 +49,44 @@ struct Args {
     /// Disable terminal UI (run in background mode)
     #[arg(long)]
     no_ui: bool,
+
+    /// Record all HID traffic into this file
+    #[arg(long, value_name = "FILE")]
+    capture: Option<PathBuf>,
+
+    /// Replay a capture instead of talking to attached devices
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage a connected device
+    Device {
+        #[command(subcommand)]
+        command: device::DeviceCommand,
+    },
+    /// Describe a raw HID frame given as hex
+    Decode(decode::DecodeArgs),
 }
 
 struct MutenixCli {
     config: Config,
-    device: Arc<HidDevice>,
+    devices: Arc<DeviceManager>,
     teams_client: Arc<TeamsWebSocketClient>,
     teams_state: TeamsState,
     token_file: PathBuf,
     saved_token: Arc<RwLock<String>>,
-    button_press_times: Arc<RwLock<HashMap<u8, std::time::Instant>>>,
+    press_classifier: Arc<Mutex<PressClassifier>>,
     app_state: AppState,
 }
 
 impl MutenixCli {
     async fn new(args: Args) -> Result<Self> {
-        // Load configuration
-        let config = if args.config.to_str() == Some(DEFAULT_CONFIG_PATH) {
-            // Use automatic search when default path is specified
-            Config::load()
-                .with_context(|| "Failed to load config from default locations")?
-        } else {
-            // Use explicit path when user provided one
-            Config::from_file(&args.config)
-                .with_context(|| format!("Failed to load config from {:?}", args.config))?
-        };
+        let config = load_config(&args.config)?;
 
         // Create app state
         let app_state = AppState::new(env!("CARGO_PKG_VERSION").to_string());
@@ -74,9 +94,24 @@ impl MutenixCli {
         // Load saved token
         let saved_token = Arc::new(RwLock::new(load_token(&args.token_file).await));
 
-        // Create HID device
+        // Create HID device manager (handles every matching device)
         let device_info = config.get_device_info();
-        let device = Arc::new(HidDevice::new(device_info));
+        let mut transport: Arc<dyn HidTransport> = match &args.replay {
+            Some(path) => Arc::new(
+                ReplayTransport::from_file(path)
+                    .with_context(|| format!("Failed to load capture {:?}", path))?,
+            ),
+            None => Arc::new(HidApiTransport::new()),
+        };
+        if let Some(path) = &args.capture {
+            let writer = CaptureWriter::create(path)
+                .with_context(|| format!("Failed to create capture {:?}", path))?;
+            transport = Arc::new(CaptureTransport::new(transport, Arc::new(writer)));
+        }
+        let devices = Arc::new(
+            DeviceManager::with_transport(device_info, transport)
+                .with_watchdog(config.watchdog.to_watchdog()),
+        );
 
         // Create Teams state and client
         let teams_state = TeamsState::new();
@@ -89,14 +124,16 @@ impl MutenixCli {
             identifier,
         ));
 
+        let press_classifier = Arc::new(Mutex::new(config.button_press.classifier()));
+
         Ok(Self {
             config,
-            device,
+            devices,
             teams_client,
             teams_state,
             token_file: args.token_file,
             saved_token,
-            button_press_times: Arc::new(RwLock::new(HashMap::new())),
+            press_classifier,
             app_state,
         })
     }
@@ -106,8 +143,8 @@ impl MutenixCli {
             .add_device_log(LogLevel::Info, "Starting Mutenix CLI")
             .await;
 
-        // Setup device callbacks
-        self.setup_device_callbacks().await;
+        // Handle device events
+        self.setup_device_events().await;
 
         // Setup Teams callbacks
         self.setup_teams_callbacks().await;
@@ -147,83 +184,100 @@ impl MutenixCli {
         Ok(())
     }
 
-    async fn setup_device_callbacks(&self) {
+    async fn setup_device_events(&self) {
         let config = self.config.clone();
         let teams_client = self.teams_client.clone();
-        let button_times = self.button_press_times.clone();
+        let press_classifier = self.press_classifier.clone();
         let app_state = self.app_state.clone();
+        let mut events = self.devices.subscribe();
+
+        tokio::spawn(async move {
+            while let Some(TaggedDeviceEvent { serial_number, event }) = events.next().await {
+                let log_message = match &event {
+                    DeviceEvent::Connected(_) => Some((LogLevel::Info, format!("Device {} connected", serial_number))),
+                    DeviceEvent::Disconnected => {
+                        Some((LogLevel::Warn, format!("Device {} disconnected", serial_number)))
+                    }
+                    DeviceEvent::Error(e) => Some((LogLevel::Error, format!("Device {}: {}", serial_number, e))),
+                    DeviceEvent::VersionInfo(version_info) => {
+                        Some((LogLevel::Info, format!("{} ({})", version_info, serial_number)))
+                    }
+                    // The device handler answers these itself by replaying the LED state
+                    DeviceEvent::StatusRequest(_) => Some((
+                        LogLevel::Info,
+                        format!("Status requested by {}, state replayed", serial_number),
+                    )),
+                    DeviceEvent::Log(log_message) => {
+                        let level = match log_message.level {
+                            DeviceLogLevel::Debug => LogLevel::Debug,
+                            DeviceLogLevel::Error => LogLevel::Error,
+                        };
+                        Some((level, format!("[{}] {}", serial_number, log_message.message)))
+                    }
+                    DeviceEvent::Status(_) => None,
+                };
+                if let Some((level, message)) = log_message {
+                    app_state.add_device_log(level, message).await;
+                }
 
-        self.device
-            .register_callback(move |message| {
-                // Only process Status messages (StatusRequest is also forwarded but we ignore it)
-                if let DeviceMessage::Status(status) = message {
-                    let button_id = status.button();
-                    let is_pressed = status.pressed();
+                // Button handling only needs Status messages
+                let DeviceEvent::Status(status) = event else {
+                    continue;
+                };
+                let press = press_classifier
+                    .lock()
+                    .unwrap()
+                    .classify(&serial_number, &status, Instant::now());
+                let button_id = status.button();
+                app_state
+                    .add_device_log(
+                        LogLevel::Info,
+                        format!(
+                            "Button {} {} ({})",
+                            button_id,
+                            if status.pressed() { "pressed" } else { "released" },
+                            serial_number
+                        ),
+                    )
+                    .await;
 
-                    let config = config.clone();
+                let Some(press) = press else {
+                    continue;
+                };
+                let is_long_press = press.is_long();
+
+                // Get the appropriate action
+                let button_action = if is_long_press {
+                    config.find_longpress_action(button_id, Some(serial_number.as_str()))
+                } else {
+                    config.find_button_action(button_id, Some(serial_number.as_str()))
+                };
+
+                if let Some(action_config) = button_action {
+                    // Actions may take a while, further events are handled meanwhile
+                    let action_config = action_config.clone();
                     let teams_client = teams_client.clone();
-                    let button_times = button_times.clone();
                     let app_state = app_state.clone();
-
                     tokio::spawn(async move {
-                        app_state
-                            .add_device_log(
-                                LogLevel::Info,
-                                format!(
-                                    "Button {} {}",
-                                    button_id,
-                                    if is_pressed { "pressed" } else { "released" }
-                                ),
-                            )
-                            .await;
-
-                        if is_pressed {
-                            // Record press time
-                            button_times
-                                .write()
-                                .await
-                                .insert(button_id, std::time::Instant::now());
-                        } else {
-                            // Handle release - determine if it's a long press
-                            let press_duration =
-                                if let Some(press_time) = button_times.write().await.remove(&button_id)
-                                {
-                                    press_time.elapsed()
-                                } else {
-                                    std::time::Duration::from_millis(0)
-                                };
-
-                            let is_long_press = press_duration.as_millis() > 500;
-
-                            // Get the appropriate action
-                            let button_action = if is_long_press {
-                                config.find_longpress_action(button_id)
-                            } else {
-                                config.find_button_action(button_id)
-                            };
-
-                            if let Some(action_config) = button_action {
-                                execute_button_actions(
-                                    action_config,
-                                    is_long_press,
-                                    button_id,
-                                    teams_client.clone(),
-                                    Arc::new(app_state.clone()),
-                                )
-                                .await;
-                            } else {
-                                app_state
-                                    .add_device_log(
-                                        LogLevel::Warn,
-                                        format!("No action configured for button {}", button_id),
-                                    )
-                                    .await;
-                            }
-                        }
+                        execute_button_actions(
+                            &action_config,
+                            is_long_press,
+                            button_id,
+                            teams_client,
+                            Arc::new(app_state),
+                        )
+                        .await;
                     });
+                } else {
+                    app_state
+                        .add_device_log(
+                            LogLevel::Warn,
+                            format!("No action configured for button {}", button_id),
+                        )
+                        .await;
                 }
-            })
-            .await;
+            }
+        });
     }
 
     async fn setup_teams_callbacks(&self) {
@@ -282,21 +336,43 @@ impl MutenixCli {
     }
 
     fn start_device_status_monitor(&self) {
-        let device = self.device.clone();
+        let devices = self.devices.clone();
         let app_state = self.app_state.clone();
 
         tokio::spawn(async move {
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
 
-                let hw_state = device.state().await;
+                let connected: Vec<_> = devices
+                    .states()
+                    .await
+                    .into_values()
+                    .filter(|hw_state| hw_state.connection_status.is_connected())
+                    .collect();
 
                 app_state
                     .update_device_status(|status| {
-                        status.connected = hw_state.connection_status == DeviceConnectionState::Connected;
-                        status.manufacturer = hw_state.manufacturer.clone();
-                        status.product = hw_state.product.clone();
-                        status.serial_number = hw_state.serial_number.clone();
+                        status.connected = !connected.is_empty();
+                        status.device_count = connected.len();
+                        status.manufacturer = connected.first().and_then(|s| s.manufacturer.clone());
+                        status.product = connected.first().and_then(|s| s.product.clone());
+                        status.firmware_version =
+                            connected.first().and_then(|s| s.firmware_version.clone());
+                        status.hardware_type = connected
+                            .first()
+                            .and_then(|s| s.hardware_type)
+                            .map(|hardware_type| hardware_type.to_string());
+                        let serials: Vec<String> =
+                            connected.iter().filter_map(|s| s.serial_number.clone()).collect();
+                        status.serial_number = (!serials.is_empty()).then(|| serials.join(", "));
+                        status.degraded = connected
+                            .iter()
+                            .any(|s| s.connection_status == DeviceConnectionState::Degraded);
+                        status.latency_ms = connected
+                            .first()
+                            .and_then(|s| s.link.average_rtt)
+                            .map(|rtt| rtt.as_secs_f64() * 1000.0);
+                        status.missed_pings = connected.iter().map(|s| s.link.pings_missed).sum();
                     })
                     .await;
             }
@@ -335,14 +411,19 @@ impl MutenixCli {
     }
 
     fn start_led_update_task(&self) {
-        let device = self.device.clone();
+        let devices = self.devices.clone();
         let teams_state = self.teams_state.clone();
         let config = self.config.clone();
         let app_state = self.app_state.clone();
 
         tokio::spawn(async move {
+            let mut animators: HashMap<String, LedAnimator> = HashMap::new();
+            let mut interval = tokio::time::interval(LED_FRAME_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
             loop {
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                interval.tick().await;
+                let now = Instant::now();
 
                 // Get current Teams meeting state
                 let server_message = teams_state.state().await;
@@ -351,31 +432,37 @@ impl MutenixCli {
                     .as_ref()
                     .and_then(|u| u.meeting_state.as_ref());
 
-                // Update LEDs based on configuration
-                for led_config in &config.led_status {
-                    if let Some(teams_config) = &led_config.teams_state {
-                        let is_state_active = meeting_state
-                            .map(|state| Self::check_teams_state(state, &teams_config.teams_state))
-                            .unwrap_or(false);
-
-                        let color = if is_state_active {
-                            teams_config.color_on.as_ref()
-                                .map(|c| c.to_led_color())
-                                .unwrap_or(LedColor::White)
-                        } else {
-                            teams_config.color_off.as_ref()
-                                .map(|c| c.to_led_color())
-                                .unwrap_or(LedColor::Black)
-                        };
+                // Update LEDs of every connected device based on configuration
+                let serials = devices.connected_serials().await;
+                animators.retain(|serial_number, _| serials.contains(serial_number));
+
+                for serial_number in serials {
+                    let animator = animators.entry(serial_number.clone()).or_default();
+                    let mut brightness = HashMap::new();
+
+                    for led_config in config.led_status_for(Some(serial_number.as_str())) {
+                        if let Some(teams_config) = &led_config.teams_state {
+                            let is_state_active = meeting_state
+                                .map(|state| Self::check_teams_state(state, &teams_config.teams_state))
+                                .unwrap_or(false);
 
-                        if let Err(e) = device
-                            .send_command(SetLed::new(led_config.button_id, color))
-                            .await
-                        {
+                            animator.set(led_config.button_id, teams_config.animation(is_state_active, LedColor::White), now);
+                            brightness.insert(led_config.button_id, config.led_brightness_for(led_config));
+                        }
+                    }
+
+                    // Only LEDs whose color changed are written to the device
+                    for (button_id, color) in animator.frames(now) {
+                        let set_led = SetLed::new(button_id, color)
+                            .with_brightness(brightness.get(&button_id).copied().unwrap_or(1.0));
+                        if let Err(e) = devices.set_led_on(&serial_number, set_led).await {
                             app_state
                                 .add_device_log(
                                     LogLevel::Error,
-                                    format!("Failed to set LED {}: {}", led_config.button_id, e),
+                                    format!(
+                                        "Failed to set LED {} on {}: {}",
+                                        button_id, serial_number, e
+                                    ),
                                 )
                                 .await;
                         }
@@ -406,6 +493,16 @@ async fn load_token(path: &PathBuf) -> String {
     }
 }
 
+fn load_config(path: &PathBuf) -> Result<Config> {
+    if path.to_str() == Some(DEFAULT_CONFIG_PATH) {
+        // Use automatic search when default path is specified
+        Config::load().with_context(|| "Failed to load config from default locations")
+    } else {
+        // Use explicit path when user provided one
+        Config::from_file(path).with_context(|| format!("Failed to load config from {:?}", path))
+    }
+}
+
 async fn save_token(path: &PathBuf, token: &str) -> Result<()> {
     fs::write(path, token)
         .await
@@ -415,9 +512,18 @@ async fn save_token(path: &PathBuf, token: &str) -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
     let no_ui = args.no_ui;
 
+    match args.command.take() {
+        Some(Command::Device { command }) => {
+            let config = load_config(&args.config)?;
+            return device::run(&config, command).await;
+        }
+        Some(Command::Decode(args)) => return decode::run(args),
+        None => {}
+    }
+
     // Create and initialize CLI
     let cli = MutenixCli::new(args).await?;
     cli.run().await?;
@@ -427,24 +533,29 @@ async fn main() -> Result<()> {
         println!("Mutenix CLI v{} running in background mode", env!("CARGO_PKG_VERSION"));
         println!("Press Ctrl+C to exit");
 
-        // Run device processing (blocks)
-        cli.device.process().await?;
+        // Run device processing until Ctrl+C
+        let result = tokio::select! {
+            result = cli.devices.process() => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        };
+
+        // Close the device connections before exiting
+        cli.devices.stop().await;
+        result?;
     } else {
         // Run with TUI
         let mut ui = Ui::new()?;
         let app_state = cli.app_state.clone();
 
         // Spawn device processing task
-        let device = cli.device.clone();
+        let devices = cli.devices.clone();
         let app_state_clone = app_state.clone();
-        tokio::task::spawn_blocking(move || {
-            tokio::runtime::Handle::current().block_on(async {
-                if let Err(e) = device.process().await {
-                    app_state_clone
-                        .add_device_log(LogLevel::Error, format!("Device error: {}", e))
-                        .await;
-                }
-            });
+        tokio::spawn(async move {
+            if let Err(e) = devices.process().await {
+                app_state_clone
+                    .add_device_log(LogLevel::Error, format!("Device error: {}", e))
+                    .await;
+            }
         });
 
         // Run UI (blocks until 'q' is pressed)
@@ -453,6 +564,9 @@ async fn main() -> Result<()> {
         // Cleanup terminal
         ui.cleanup()?;
 
+        // Close the device connections before exiting
+        cli.devices.stop().await;
+
         result?;
     }
 
@@ -111,23 +111,26 @@ fn render_status_section(
         .split(area);
 
     // Device status
-    let device_color = if device_status.connected {
-        Color::Green
+    let (device_color, device_label) = if !device_status.connected {
+        (Color::Red, "Disconnected")
+    } else if device_status.degraded {
+        (Color::Yellow, "Degraded")
     } else {
-        Color::Red
+        (Color::Green, "Connected")
     };
 
     let device_info = vec![
         Line::from(vec![
             Span::raw("Status: "),
             Span::styled(
-                if device_status.connected {
-                    "Connected"
-                } else {
-                    "Disconnected"
-                },
+                device_label,
                 Style::default().fg(device_color).add_modifier(Modifier::BOLD),
             ),
+            Span::raw(if device_status.device_count > 1 {
+                format!(" ({} devices)", device_status.device_count)
+            } else {
+                String::new()
+            }),
         ]),
         Line::from(format!(
             "Product: {}",
@@ -141,6 +144,19 @@ fn render_status_section(
             "Serial: {}",
             device_status.serial_number.as_deref().unwrap_or("N/A")
         )),
+        Line::from(format!(
+            "Firmware: {} ({})",
+            device_status.firmware_version.as_deref().unwrap_or("N/A"),
+            device_status.hardware_type.as_deref().unwrap_or("N/A")
+        )),
+        Line::from(format!(
+            "Latency: {} ({} missed pings)",
+            device_status
+                .latency_ms
+                .map(|ms| format!("{:.1} ms", ms))
+                .unwrap_or_else(|| "N/A".to_string()),
+            device_status.missed_pings
+        )),
     ];
 
     let device_block = Block::default()
@@ -10,11 +10,14 @@ use anyhow::{Context, Result};
 use app::{AppState, DeviceStatus, LogLevel, TeamsStatus};
 use lib_base::{Config, TeamsStateType, execute_button_actions};
 use mutenix_hid::{
-    ConnectionState as DeviceConnectionState, DeviceMessage, HidDevice, LedColor, SetLed,
+    ConnectionState as DeviceConnectionState, DeviceEvent, DeviceManager, LedAnimator, LedColor,
+    LogLevel as DeviceLogLevel, PressClassifier, SetLed, TaggedDeviceEvent,
+    LED_FRAME_INTERVAL,
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconEvent};
 use tauri::Manager;
@@ -36,12 +39,12 @@ struct StatusPayload {
 
 struct MutenixUi {
     config: Arc<RwLock<Config>>,
-    device: Arc<HidDevice>,
+    devices: Arc<DeviceManager>,
     teams_client: Arc<TeamsWebSocketClient>,
     teams_state: TeamsState,
     token_file: PathBuf,
     saved_token: Arc<RwLock<String>>,
-    button_press_times: Arc<RwLock<HashMap<u8, std::time::Instant>>>,
+    press_classifier: Arc<Mutex<PressClassifier>>,
     app_state: AppState,
 }
 
@@ -55,6 +58,7 @@ impl MutenixUi {
             Config::load()
                 .with_context(|| "Failed to load config from default locations")?
         };
+        let press_classifier = Arc::new(Mutex::new(config_data.button_press.classifier()));
         let config = Arc::new(RwLock::new(config_data));
 
         // Create app state
@@ -63,9 +67,10 @@ impl MutenixUi {
         // Load saved token
         let saved_token = Arc::new(RwLock::new(load_token(&token_file).await));
 
-        // Create HID device
+        // Create HID device manager (handles every matching device)
         let device_info = config.read().await.get_device_info();
-        let device = Arc::new(HidDevice::new(device_info));
+        let watchdog = config.read().await.watchdog.to_watchdog();
+        let devices = Arc::new(DeviceManager::new(device_info).with_watchdog(watchdog));
 
         // Create Teams state and client
         let teams_state = TeamsState::new();
@@ -81,12 +86,12 @@ impl MutenixUi {
 
         Ok(Self {
             config,
-            device,
+            devices,
             teams_client,
             teams_state,
             token_file,
             saved_token,
-            button_press_times: Arc::new(RwLock::new(HashMap::new())),
+            press_classifier,
             app_state,
         })
     }
@@ -96,23 +101,21 @@ impl MutenixUi {
             .add_device_log(LogLevel::Info, "Starting Mutenix UI")
             .await;
 
-        // Setup device callbacks
-        self.setup_device_callbacks().await;
+        // Handle device events
+        self.setup_device_events().await;
 
         // Setup Teams callbacks
         self.setup_teams_callbacks().await;
 
         // Start device processing
-        let device = self.device.clone();
+        let devices = self.devices.clone();
         let app_state = self.app_state.clone();
-        tokio::task::spawn_blocking(move || {
-            tokio::runtime::Handle::current().block_on(async {
-                if let Err(e) = device.process().await {
-                    app_state
-                        .add_device_log(LogLevel::Error, format!("Device error: {}", e))
-                        .await;
-                }
-            })
+        tokio::spawn(async move {
+            if let Err(e) = devices.process().await {
+                app_state
+                    .add_device_log(LogLevel::Error, format!("Device error: {}", e))
+                    .await;
+            }
         });
 
         // Start device status monitor
@@ -150,81 +153,104 @@ impl MutenixUi {
         Ok(())
     }
 
-    async fn setup_device_callbacks(&self) {
+    async fn setup_device_events(&self) {
         let config = self.config.clone();
         let teams_client = self.teams_client.clone();
-        let button_times = self.button_press_times.clone();
+        let press_classifier = self.press_classifier.clone();
         let app_state = self.app_state.clone();
+        let mut events = self.devices.subscribe();
+
+        tokio::spawn(async move {
+            while let Some(TaggedDeviceEvent { serial_number, event }) = events.next().await {
+                let log_message = match &event {
+                    DeviceEvent::Connected(_) => Some((LogLevel::Info, format!("Device {} connected", serial_number))),
+                    DeviceEvent::Disconnected => {
+                        Some((LogLevel::Warn, format!("Device {} disconnected", serial_number)))
+                    }
+                    DeviceEvent::Error(e) => Some((LogLevel::Error, format!("Device {}: {}", serial_number, e))),
+                    DeviceEvent::VersionInfo(version_info) => {
+                        Some((LogLevel::Info, format!("{} ({})", version_info, serial_number)))
+                    }
+                    // The device handler answers these itself by replaying the LED state
+                    DeviceEvent::StatusRequest(_) => Some((
+                        LogLevel::Info,
+                        format!("Status requested by {}, state replayed", serial_number),
+                    )),
+                    DeviceEvent::Log(log_message) => {
+                        let level = match log_message.level {
+                            DeviceLogLevel::Debug => LogLevel::Debug,
+                            DeviceLogLevel::Error => LogLevel::Error,
+                        };
+                        Some((level, format!("[{}] {}", serial_number, log_message.message)))
+                    }
+                    DeviceEvent::Status(_) => None,
+                };
+                if let Some((level, message)) = log_message {
+                    app_state.add_device_log(level, message).await;
+                }
+
+                // Button handling only needs Status messages
+                let DeviceEvent::Status(status) = event else {
+                    continue;
+                };
+                let press = press_classifier
+                    .lock()
+                    .unwrap()
+                    .classify(&serial_number, &status, Instant::now());
+                let button_id = status.button();
+                app_state
+                    .add_device_log(
+                        LogLevel::Info,
+                        format!(
+                            "Button {} {} ({})",
+                            button_id,
+                            if status.pressed() { "pressed" } else { "released" },
+                            serial_number
+                        ),
+                    )
+                    .await;
 
-        self.device
-            .register_callback(move |message| {
-                if let DeviceMessage::Status(status) = message {
-                    let button_id = status.button();
-                    let is_pressed = status.pressed();
+                let Some(press) = press else {
+                    continue;
+                };
+                let is_long_press = press.is_long();
+
+                let config_guard = config.read().await;
+                let button_action = if is_long_press {
+                    config_guard
+                        .find_longpress_action(button_id, Some(serial_number.as_str()))
+                        .cloned()
+                } else {
+                    config_guard
+                        .find_button_action(button_id, Some(serial_number.as_str()))
+                        .cloned()
+                };
+                drop(config_guard);
 
-                    let config = config.clone();
+                if let Some(action_config) = button_action {
+                    // Actions may take a while, further events are handled meanwhile
                     let teams_client = teams_client.clone();
-                    let button_times = button_times.clone();
                     let app_state = app_state.clone();
-
                     tokio::spawn(async move {
-                        app_state
-                            .add_device_log(
-                                LogLevel::Info,
-                                format!(
-                                    "Button {} {}",
-                                    button_id,
-                                    if is_pressed { "pressed" } else { "released" }
-                                ),
-                            )
-                            .await;
-
-                        if is_pressed {
-                            button_times
-                                .write()
-                                .await
-                                .insert(button_id, std::time::Instant::now());
-                        } else {
-                            let press_duration =
-                                if let Some(press_time) = button_times.write().await.remove(&button_id)
-                                {
-                                    press_time.elapsed()
-                                } else {
-                                    std::time::Duration::from_millis(0)
-                                };
-
-                            let is_long_press = press_duration.as_millis() > 500;
-
-                            let config_guard = config.read().await;
-                            let button_action = if is_long_press {
-                                config_guard.find_longpress_action(button_id).cloned()
-                            } else {
-                                config_guard.find_button_action(button_id).cloned()
-                            };
-                            drop(config_guard);
-
-                            if let Some(action_config) = button_action {
-                                execute_button_actions(
-                                    &action_config,
-                                    is_long_press,
-                                    button_id,
-                                    teams_client.clone(),
-                                    Arc::new(app_state.clone()),
-                                )
-                                .await;
-                            } else {
-                                app_state
-                                    .add_device_log(
-                                        LogLevel::Warn,
-                                        format!("No action configured for button {}", button_id),
-                                    )
-                                    .await;
-                            }
-                        }
+                        execute_button_actions(
+                            &action_config,
+                            is_long_press,
+                            button_id,
+                            teams_client,
+                            Arc::new(app_state),
+                        )
+                        .await;
                     });
+                } else {
+                    app_state
+                        .add_device_log(
+                            LogLevel::Warn,
+                            format!("No action configured for button {}", button_id),
+                        )
+                        .await;
                 }
-            })
-            .await;
+            }
+        });
     }
 
     async fn setup_teams_callbacks(&self) {
@@ -279,26 +305,41 @@ impl MutenixUi {
     }
 
     fn start_device_status_monitor(&self) {
-        let device = self.device.clone();
+        let devices = self.devices.clone();
         let app_state = self.app_state.clone();
 
         tokio::spawn(async move {
             loop {
-                let hw_state = device.state().await;
-                let is_connected = hw_state.connection_status == DeviceConnectionState::Connected;
+                let connected: Vec<_> = devices
+                    .states()
+                    .await
+                    .into_values()
+                    .filter(|hw_state| hw_state.connection_status.is_connected())
+                    .collect();
 
                 app_state
                     .update_device_status(|status| {
-                        status.connected = is_connected;
-                        if is_connected {
-                            status.manufacturer = hw_state.manufacturer.clone();
-                            status.product = hw_state.product.clone();
-                            status.serial_number = hw_state.serial_number.clone();
-                        } else {
-                            status.manufacturer = None;
-                            status.product = None;
-                            status.serial_number = None;
-                        }
+                        status.connected = !connected.is_empty();
+                        status.device_count = connected.len();
+                        status.manufacturer = connected.first().and_then(|s| s.manufacturer.clone());
+                        status.product = connected.first().and_then(|s| s.product.clone());
+                        status.firmware_version =
+                            connected.first().and_then(|s| s.firmware_version.clone());
+                        status.hardware_type = connected
+                            .first()
+                            .and_then(|s| s.hardware_type)
+                            .map(|hardware_type| hardware_type.to_string());
+                        let serials: Vec<String> =
+                            connected.iter().filter_map(|s| s.serial_number.clone()).collect();
+                        status.serial_number = (!serials.is_empty()).then(|| serials.join(", "));
+                        status.degraded = connected
+                            .iter()
+                            .any(|s| s.connection_status == DeviceConnectionState::Degraded);
+                        status.latency_ms = connected
+                            .first()
+                            .and_then(|s| s.link.average_rtt)
+                            .map(|rtt| rtt.as_secs_f64() * 1000.0);
+                        status.missed_pings = connected.iter().map(|s| s.link.pings_missed).sum();
                     })
                     .await;
 
@@ -335,63 +376,70 @@ impl MutenixUi {
 
     fn start_led_update_task(&self) {
         let config = self.config.clone();
-        let device = self.device.clone();
+        let devices = self.devices.clone();
         let teams_state = self.teams_state.clone();
 
         tokio::spawn(async move {
-            let mut first_run = true;
+            let mut animators: HashMap<String, LedAnimator> = HashMap::new();
+            let mut interval = tokio::time::interval(LED_FRAME_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
             loop {
+                interval.tick().await;
+                let now = Instant::now();
+
                 let state = teams_state.state().await;
                 let meeting_state = state.meeting_update.and_then(|u| u.meeting_state).unwrap_or_default();
 
-                if first_run {
-                    first_run = false;
-                }
+                let serials = devices.connected_serials().await;
+                animators.retain(|serial_number, _| serials.contains(serial_number));
 
                 let config_guard = config.read().await;
-                for led_status in &config_guard.led_status {
-                    if let Some(teams_config) = &led_status.teams_state {
-                        let is_active = match teams_config.teams_state {
-                            TeamsStateType::IsMuted => meeting_state.is_muted,
-                            TeamsStateType::IsVideoOn => meeting_state.is_video_on,
-                            TeamsStateType::IsHandRaised => meeting_state.is_hand_raised,
-                            TeamsStateType::IsInMeeting => meeting_state.is_in_meeting,
-                            TeamsStateType::IsRecordingOn => meeting_state.is_recording_on,
-                            TeamsStateType::IsBackgroundBlurred => {
-                                meeting_state.is_background_blurred
-                            }
-                            TeamsStateType::IsSharing => meeting_state.is_sharing,
-                            TeamsStateType::HasUnreadMessages => {
-                                meeting_state.has_unread_messages
-                            }
-                        };
+                for serial_number in serials {
+                    let animator = animators.entry(serial_number.clone()).or_default();
+                    let mut brightness = HashMap::new();
+
+                    for led_status in config_guard.led_status_for(Some(serial_number.as_str())) {
+                        if let Some(teams_config) = &led_status.teams_state {
+                            let is_active = match teams_config.teams_state {
+                                TeamsStateType::IsMuted => meeting_state.is_muted,
+                                TeamsStateType::IsVideoOn => meeting_state.is_video_on,
+                                TeamsStateType::IsHandRaised => meeting_state.is_hand_raised,
+                                TeamsStateType::IsInMeeting => meeting_state.is_in_meeting,
+                                TeamsStateType::IsRecordingOn => meeting_state.is_recording_on,
+                                TeamsStateType::IsBackgroundBlurred => {
+                                    meeting_state.is_background_blurred
+                                }
+                                TeamsStateType::IsSharing => meeting_state.is_sharing,
+                                TeamsStateType::HasUnreadMessages => {
+                                    meeting_state.has_unread_messages
+                                }
+                            };
 
-                        let color = if is_active {
-                            teams_config
-                                .color_on
-                                .as_ref()
-                                .map(|c| c.to_led_color())
-                                .unwrap_or(LedColor::Green)
-                        } else {
-                            teams_config
-                                .color_off
-                                .as_ref()
-                                .map(|c| c.to_led_color())
-                                .unwrap_or(LedColor::Black)
-                        };
+                            animator.set(led_status.button_id, teams_config.animation(is_active, LedColor::Green), now);
+                            brightness.insert(
+                                led_status.button_id,
+                                config_guard.led_brightness_for(led_status),
+                            );
+                        }
+                    }
 
-                        let set_led_cmd = SetLed::new(led_status.button_id, color);
-                        match device.send_command(set_led_cmd).await {
+                    // Only LEDs whose color changed are written to the device
+                    for (button_id, color) in animator.frames(now) {
+                        let set_led_cmd = SetLed::new(button_id, color)
+                            .with_brightness(brightness.get(&button_id).copied().unwrap_or(1.0));
+                        match devices.set_led_on(&serial_number, set_led_cmd).await {
                             Ok(_) => {},
                             Err(e) => {
-                                eprintln!("[LED] Failed to set LED {}: {}", led_status.button_id, e);
+                                eprintln!(
+                                    "[LED] Failed to set LED {} on {}: {}",
+                                    button_id, serial_number, e
+                                );
                             }
                         }
                     }
                 }
                 drop(config_guard);
-
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
             }
         });
     }
@@ -414,6 +462,7 @@ async fn save_token(token_file: &PathBuf, token: &str) -> Result<()> {
 // Shared state for config
 struct ConfigState {
     config: Arc<RwLock<Config>>,
+    press_classifier: Arc<Mutex<PressClassifier>>,
     config_path: Option<PathBuf>,
 }
 
@@ -458,6 +507,7 @@ async fn save_config(
     config: Config,
 ) -> Result<(), String> {
     // Update in-memory config
+    *config_state.press_classifier.lock().unwrap() = config.button_press.classifier();
     *config_state.config.write().await = config.clone();
     
     // Determine config path
@@ -517,6 +567,11 @@ pub fn run() {
                             }
                         }
                         "quit" => {
+                            // Close the device connections before exiting
+                            if let Some(devices) = _app.try_state::<Arc<DeviceManager>>() {
+                                let devices = devices.inner().clone();
+                                tauri::async_runtime::block_on(async move { devices.stop().await });
+                            }
                             std::process::exit(0);
                         }
                         _ => {}
@@ -565,10 +620,14 @@ pub fn run() {
                         // Register config state
                         let config_state = Arc::new(ConfigState {
                             config: ui.config.clone(),
+                            press_classifier: ui.press_classifier.clone(),
                             config_path: None,
                         });
                         handle.manage(config_state);
 
+                        // Register the device manager so quitting can stop it
+                        handle.manage(ui.devices.clone());
+
                         if let Err(e) = ui.run().await {
                             eprintln!("[Main] Error running Mutenix UI: {}", e);
                         }